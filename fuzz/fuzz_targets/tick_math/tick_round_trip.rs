@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use hedgebot::solidints::U160::U160;
+use hedgebot::solidmath::tick_math;
+
+fuzz_target!(|data: (i32, U160)| {
+    let (tick, sqrt_price) = data;
+
+    if tick >= tick_math::MIN_TICK && tick <= tick_math::MAX_TICK {
+        let ratio = tick_math::get_sqrt_ratio_at_tick(tick).unwrap();
+        assert!(ratio >= tick_math::MIN_SQRT_RATIO && ratio <= tick_math::MAX_SQRT_RATIO);
+        if tick < tick_math::MAX_TICK {
+            assert_eq!(tick_math::get_tick_at_sqrt_ratio(ratio).unwrap(), tick);
+        }
+    } else {
+        assert!(tick_math::get_sqrt_ratio_at_tick(tick).is_err());
+    }
+
+    if sqrt_price < tick_math::MIN_SQRT_RATIO || sqrt_price >= tick_math::MAX_SQRT_RATIO {
+        assert!(tick_math::get_tick_at_sqrt_ratio(sqrt_price).is_err());
+        return;
+    }
+    // the tick is the greatest one whose ratio doesn't exceed the input
+    let tick = tick_math::get_tick_at_sqrt_ratio(sqrt_price).unwrap();
+    assert!(tick_math::get_sqrt_ratio_at_tick(tick).unwrap() <= sqrt_price);
+    assert!(tick_math::get_sqrt_ratio_at_tick(tick + 1).unwrap() > sqrt_price);
+});
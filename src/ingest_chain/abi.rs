@@ -0,0 +1,121 @@
+// just enough ABI decoding for event logs: indexed arguments are whole topics, the rest are 32 byte words in the data.
+// everything is checked to fit its solidity type, so a log that doesn't decode is an error and not a garbage value.
+
+use crate::solidints::sized::Int24;
+use crate::solidints::{I256::I256, U160::U160, U256};
+use anyhow::{anyhow, ensure, Result};
+use primitive_types::H256;
+use tiny_keccak::{Hasher, Keccak};
+
+/// keccak256 of an event's signature, e.g. `Transfer(address,address,uint256)`. topic 0 of its logs
+pub fn event_topic(signature: &str) -> H256 {
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(signature.as_bytes());
+    keccak.finalize(&mut hash);
+    H256(hash)
+}
+
+/// the `index`th word of a log's data
+pub fn word(data: &[u8], index: usize) -> Result<[u8; 32]> {
+    let word = data
+        .get(32 * index..32 * (index + 1))
+        .ok_or(anyhow!("log data too short for word {}", index))?;
+    let mut ret = [0u8; 32];
+    ret.copy_from_slice(word);
+    Ok(ret)
+}
+
+/// the `index`th topic of a log
+pub fn topic(topics: &[H256], index: usize) -> Result<[u8; 32]> {
+    Ok(topics
+        .get(index)
+        .ok_or(anyhow!("log is missing topic {}", index))?
+        .0)
+}
+
+pub fn uint256(word: [u8; 32]) -> U256 {
+    U256::from_big_endian(&word)
+}
+
+pub fn int256(word: [u8; 32]) -> I256 {
+    I256::from_raw(uint256(word))
+}
+
+pub fn uint160(word: [u8; 32]) -> Result<U160> {
+    U160::try_from(uint256(word))
+}
+
+pub fn address(word: [u8; 32]) -> Result<U160> {
+    uint160(word)
+}
+
+pub fn uint128(word: [u8; 32]) -> Result<u128> {
+    let value = uint256(word);
+    ensure!(
+        value <= U256::from(u128::MAX),
+        "{} doesn't fit a uint128",
+        value
+    );
+    Ok(value.low_u128())
+}
+
+pub fn uint16(word: [u8; 32]) -> Result<u16> {
+    let value = uint256(word);
+    ensure!(
+        value <= U256::from(u16::MAX),
+        "{} doesn't fit a uint16",
+        value
+    );
+    Ok(value.low_u32() as u16)
+}
+
+pub fn uint8(word: [u8; 32]) -> Result<u8> {
+    let value = uint256(word);
+    ensure!(
+        value <= U256::from(u8::MAX),
+        "{} doesn't fit a uint8",
+        value
+    );
+    Ok(value.low_u32() as u8)
+}
+
+/// sign extended to 256 bits, like every int narrower than a word
+pub fn int24(word: [u8; 32]) -> Result<i32> {
+    Ok(Int24::try_from(int256(word))?.get() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word_of(value: I256) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        value.into_raw().to_big_endian(&mut word);
+        word
+    }
+
+    #[test]
+    fn hashes_signatures() {
+        assert_eq!(
+            format!("{:?}", event_topic("Transfer(address,address,uint256)")),
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+
+    #[test]
+    fn checks_words_fit_their_type() {
+        assert_eq!(int24(word_of(I256::from(-887272))).unwrap(), -887272);
+        assert_eq!(int24(word_of(I256::from(8388607))).unwrap(), 8388607);
+        assert!(int24(word_of(I256::from(8388608))).is_err());
+        assert!(int24(word_of(I256::from(-8388609))).is_err());
+        assert_eq!(int256(word_of(I256::minus_one())), I256::minus_one());
+        assert!(uint128(word_of(I256::minus_one())).is_err());
+        assert_eq!(uint16(word_of(I256::from(65535))).unwrap(), u16::MAX);
+        assert!(uint16(word_of(I256::from(65536))).is_err());
+        assert_eq!(uint8(word_of(I256::from(10))).unwrap(), 10);
+        assert!(uint8(word_of(I256::from(256))).is_err());
+        assert!(uint160(word_of(I256::minus_one())).is_err());
+        assert!(word(&[0u8; 63], 1).is_err());
+    }
+}
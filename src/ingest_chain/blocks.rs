@@ -9,7 +9,7 @@ use std::ops::RangeBounds;
 
 pub type BlockNumber = u64;
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Blocks {
     ranges: Ranges<BlockNumber>,
 }
@@ -24,13 +24,37 @@ impl Blocks {
         }
     }
 
+    /// blocks `first` through `last`, both included
+    pub fn between(first: BlockNumber, last: BlockNumber) -> Self {
+        Blocks::new(GenericRange::new_closed(first, last).into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
     pub fn union(&self, rhs: Blocks) -> Self {
         Blocks {
-            ranges: self.ranges.union(rhs.ranges),
+            ranges: self.ranges.clone().union(rhs.ranges),
         }
     }
 }
 
+/// the first and last block in a range, both included
+pub fn first_and_last(range: &GenericRange<BlockNumber>) -> (BlockNumber, BlockNumber) {
+    let first = match range.start_bound() {
+        Bound::Included(first) => *first,
+        Bound::Excluded(before) => before + 1,
+        Bound::Unbounded => BlockNumber::MIN,
+    };
+    let last = match range.end_bound() {
+        Bound::Included(last) => *last,
+        Bound::Excluded(after) => after - 1,
+        Bound::Unbounded => BlockNumber::MAX,
+    };
+    (first, last)
+}
+
 impl AsRef<Vec<GenericRange<BlockNumber>>> for Blocks {
     fn as_ref(&self) -> &Vec<GenericRange<BlockNumber>> {
         self.ranges.as_ref()
@@ -90,11 +114,25 @@ impl From<&Blocks> for StoreBlocks {
     }
 }
 
+impl From<MyBlockBound> for Bound<BlockNumber> {
+    fn from(b: MyBlockBound) -> Bound<BlockNumber> {
+        match b {
+            MyBlockBound::Included(a) => Bound::Included(a),
+            MyBlockBound::Excluded(a) => Bound::Excluded(a),
+        }
+    }
+}
+
 impl From<StoreBlocks> for Blocks {
     // serde can eat me
-    fn from(_range: StoreBlocks) -> Blocks {
-        todo!();
-        //range.as_ref().iter().map(|generic_range| (generic_range.start_bound().into(), generic_range.end_bound().into())).collect()
+    fn from(range: StoreBlocks) -> Blocks {
+        Blocks::new(
+            range
+                .serdeable_ranges
+                .into_iter()
+                .map(|(start, end)| GenericRange::new_with_bounds(start.into(), end.into()))
+                .collect(),
+        )
     }
 }
 
@@ -102,7 +140,7 @@ impl TryFrom<&[u8]> for Blocks {
     type Error = anyhow::Error;
 
     fn try_from(bytes: &[u8]) -> Result<Blocks> {
-        let sb: StoreBlocks = bincode::deserialize(&bytes)?;
+        let sb: StoreBlocks = bincode::deserialize(bytes)?;
         Ok(sb.into())
     }
 }
@@ -113,3 +151,24 @@ impl TryInto<Vec<u8>> for &Blocks {
         Ok(bincode::serialize(&StoreBlocks::from(self))?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let blocks = Blocks::between(10, 20).union(Blocks::between(30, 30));
+        let bytes: Vec<u8> = (&blocks).try_into().unwrap();
+        assert_eq!(Blocks::try_from(&bytes[..]).unwrap(), blocks);
+    }
+
+    #[test]
+    fn subtracts_what_we_already_have() {
+        let missing =
+            Blocks::between(0, 100) - Blocks::between(10, 20).union(Blocks::between(50, 200));
+        let ranges: Vec<_> = missing.as_ref().iter().map(first_and_last).collect();
+        assert_eq!(ranges, vec![(0, 9), (21, 49)]);
+        assert!((Blocks::between(10, 20) - Blocks::between(0, 30)).is_empty());
+    }
+}
@@ -1,9 +1,14 @@
 use crate::ingest_chain::blocks::BlockNumber;
 
+use crate::ingest_chain::hegic::ContractEvent;
+use crate::ingest_chain::rpc::{BlockHeader, Log};
+use crate::ingest_chain::uniswap_v3::PoolEvent;
 use crate::ingest_chain::Protocol;
+use anyhow::{bail, Result};
+use primitive_types::H256;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Timestamp {
     block_number: BlockNumber,
     // preserving my boyfriend's "code" here in a comment from when he "helped" me "work"
@@ -15,8 +20,103 @@ pub struct Timestamp {
     tx_id: u64, // this may not work. fix it later.
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Event {
     protocol: Protocol,
-    event_data: Vec<u8>, //sucks but works for now
+    event_data: EventData,
+}
+
+/// What an event says, tagged with what kind of event it is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventData {
+    /// a log we don't decode (yet), as it came
+    Raw {
+        topics: Vec<[u8; 32]>,
+        data: Vec<u8>,
+    },
+    UniswapV3(PoolEvent),
+    Hegic(ContractEvent),
+}
+
+impl EventData {
+    /// decodes a log from one of `protocol`'s contracts, keeping it raw if we don't know what it is
+    pub fn from_log(protocol: Protocol, log: &Log) -> Result<Self> {
+        let decoded = match protocol {
+            Protocol::UniswapV3 => PoolEvent::from_log(log)?.map(EventData::UniswapV3),
+            Protocol::HegicOptions => ContractEvent::from_log(log)?.map(EventData::Hegic),
+            Protocol::UniswapV2 => None,
+        };
+        Ok(decoded.unwrap_or_else(|| EventData::Raw {
+            topics: log.topics.iter().map(|topic| topic.0).collect(),
+            data: log.data.clone(),
+        }))
+    }
+}
+
+impl Timestamp {
+    pub fn new(block_number: BlockNumber, tx_id: u64) -> Self {
+        Timestamp {
+            block_number,
+            tx_id,
+        }
+    }
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+    pub fn tx_id(&self) -> u64 {
+        self.tx_id
+    }
+
+    /// the key in the data tree. big endian, so sled keeps events in block order and a block's events share a prefix
+    pub fn to_key(&self) -> [u8; 16] {
+        let mut key = [0u8; 16];
+        key[..8].copy_from_slice(&self.block_number.to_be_bytes());
+        key[8..].copy_from_slice(&self.tx_id.to_be_bytes());
+        key
+    }
+
+    pub fn from_key(key: &[u8]) -> Result<Self> {
+        if key.len() != 16 {
+            bail!("data tree keys are 16 bytes, got {}", key.len());
+        }
+        let mut block_number = [0u8; 8];
+        let mut tx_id = [0u8; 8];
+        block_number.copy_from_slice(&key[..8]);
+        tx_id.copy_from_slice(&key[8..]);
+        Ok(Timestamp::new(
+            u64::from_be_bytes(block_number),
+            u64::from_be_bytes(tx_id),
+        ))
+    }
+}
+
+impl Event {
+    pub fn new(protocol: Protocol, event_data: EventData) -> Self {
+        Event {
+            protocol,
+            event_data,
+        }
+    }
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+    pub fn event_data(&self) -> &EventData {
+        &self.event_data
+    }
+}
+
+/// What we remember about a block we stored events for, so we notice when the chain changes under us
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRecord {
+    pub hash: H256,
+    pub parent_hash: H256,
+}
+
+impl From<&BlockHeader> for BlockRecord {
+    fn from(header: &BlockHeader) -> Self {
+        BlockRecord {
+            hash: header.hash,
+            parent_hash: header.parent_hash,
+        }
+    }
 }
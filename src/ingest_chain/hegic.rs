@@ -0,0 +1,643 @@
+// hegic v888 options: the options contract (HegicETHOptions and friends) that sells them, and the pool that writes them.
+// the options contract only logs the fees when an option is created, the terms live in its `options` mapping,
+// so building positions takes an eth_call per option on top of the logs.
+
+use super::abi::{self, event_topic};
+use super::blocks::BlockNumber;
+use super::rpc::{Log, LogFilter, RpcClient, Transport};
+use crate::solidints::U256;
+use crate::unisim::Address;
+use anyhow::{anyhow, bail, ensure, Result};
+use primitive_types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+lazy_static! {
+    pub static ref CREATE_TOPIC: H256 = event_topic("Create(uint256,address,uint256,uint256)");
+    pub static ref EXERCISE_TOPIC: H256 = event_topic("Exercise(uint256,uint256)");
+    pub static ref EXPIRE_TOPIC: H256 = event_topic("Expire(uint256,uint256)");
+    pub static ref PROVIDE_TOPIC: H256 = event_topic("Provide(address,uint256,uint256)");
+    pub static ref WITHDRAW_TOPIC: H256 = event_topic("Withdraw(address,uint256,uint256)");
+    static ref OPTIONS_SELECTOR: [u8; 4] = {
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&event_topic("options(uint256)")[..4]);
+        selector
+    };
+}
+
+pub type OptionId = U256;
+
+/// An option was bought
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Create {
+    pub id: OptionId,
+    /// who holds it
+    pub account: Address,
+    /// the protocol's cut
+    pub settlement_fee: U256,
+    /// premium plus settlement fee
+    pub total_fee: U256,
+}
+
+/// An option was exercised, paying `profit` to its holder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exercise {
+    pub id: OptionId,
+    pub profit: U256,
+}
+
+/// An option ran out without being exercised, and the pool kept `premium`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Expire {
+    pub id: OptionId,
+    pub premium: U256,
+}
+
+/// Liquidity went into the pool. `write_amount` is the pool tokens minted for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provide {
+    pub account: Address,
+    pub amount: U256,
+    pub write_amount: U256,
+}
+
+/// Liquidity came out of the pool. `write_amount` is the pool tokens burned for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Withdraw {
+    pub account: Address,
+    pub amount: U256,
+    pub write_amount: U256,
+}
+
+/// Any event of the options contract or its pool that we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HegicEvent {
+    Create(Create),
+    Exercise(Exercise),
+    Expire(Expire),
+    Provide(Provide),
+    Withdraw(Withdraw),
+}
+
+impl HegicEvent {
+    /// Decodes a log from its topics and data
+    ///
+    /// returns None for events this doesn't cover, and errors if the log doesn't fit its event
+    pub fn decode(topics: &[H256], data: &[u8]) -> Result<Option<Self>> {
+        use abi::{address, topic, uint256, word};
+
+        let signature = match topics.first() {
+            None => return Ok(None),
+            Some(signature) => signature,
+        };
+        let event = if *signature == *CREATE_TOPIC {
+            HegicEvent::Create(Create {
+                id: uint256(topic(topics, 1)?),
+                account: address(topic(topics, 2)?)?,
+                settlement_fee: uint256(word(data, 0)?),
+                total_fee: uint256(word(data, 1)?),
+            })
+        } else if *signature == *EXERCISE_TOPIC {
+            HegicEvent::Exercise(Exercise {
+                id: uint256(topic(topics, 1)?),
+                profit: uint256(word(data, 0)?),
+            })
+        } else if *signature == *EXPIRE_TOPIC {
+            HegicEvent::Expire(Expire {
+                id: uint256(topic(topics, 1)?),
+                premium: uint256(word(data, 0)?),
+            })
+        } else if *signature == *PROVIDE_TOPIC {
+            HegicEvent::Provide(Provide {
+                account: address(topic(topics, 1)?)?,
+                amount: uint256(word(data, 0)?),
+                write_amount: uint256(word(data, 1)?),
+            })
+        } else if *signature == *WITHDRAW_TOPIC {
+            HegicEvent::Withdraw(Withdraw {
+                account: address(topic(topics, 1)?)?,
+                amount: uint256(word(data, 0)?),
+                write_amount: uint256(word(data, 1)?),
+            })
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(event))
+    }
+
+    /// A filter for every event above, from any of `contracts`, options contracts and pools alike
+    pub fn log_filter(contracts: Vec<Address>) -> LogFilter {
+        LogFilter {
+            addresses: contracts,
+            topics: vec![Some(vec![
+                *CREATE_TOPIC,
+                *EXERCISE_TOPIC,
+                *EXPIRE_TOPIC,
+                *PROVIDE_TOPIC,
+                *WITHDRAW_TOPIC,
+            ])],
+        }
+    }
+}
+
+/// An event, and the options contract or pool that emitted it. Option ids and pool tokens only mean something per contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractEvent {
+    pub contract: Address,
+    pub event: HegicEvent,
+}
+
+impl ContractEvent {
+    pub fn from_log(log: &Log) -> Result<Option<Self>> {
+        Ok(
+            HegicEvent::decode(&log.topics, &log.data)?.map(|event| ContractEvent {
+                contract: log.address,
+                event,
+            }),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OptionType {
+    Put,
+    Call,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OptionState {
+    Active,
+    Exercised { profit: U256 },
+    Expired,
+}
+
+/// What the options contract keeps about an option, i.e. what `options(id)` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionTerms {
+    pub holder: Address,
+    /// in USD with 8 decimals, like the price feed
+    pub strike: U256,
+    /// in the underlying
+    pub amount: U256,
+    /// what the pool locked to cover it, which caps the profit
+    pub locked_amount: U256,
+    /// the premium without the settlement fee
+    pub premium: U256,
+    /// a unix timestamp
+    pub expiration: u64,
+    pub option_type: OptionType,
+}
+
+impl OptionTerms {
+    /// the calldata for `options(id)`
+    pub fn calldata(id: OptionId) -> Vec<u8> {
+        let mut data = OPTIONS_SELECTOR.to_vec();
+        let mut word = [0u8; 32];
+        id.to_big_endian(&mut word);
+        data.extend_from_slice(&word);
+        data
+    }
+
+    /// decodes what `options(id)` returned:
+    /// (State state, address holder, uint256 strike, uint256 amount, uint256 lockedAmount, uint256 premium, uint256 expiration, OptionType optionType)
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        use abi::{address, uint256, word};
+
+        // State {Inactive, Active, Exercised, Expired}
+        if uint256(word(data, 0)?).is_zero() {
+            bail!("no such option");
+        }
+        let expiration = uint256(word(data, 6)?);
+        ensure!(
+            expiration <= U256::from(u64::MAX),
+            "bad expiration {}",
+            expiration
+        );
+        Ok(OptionTerms {
+            holder: address(word(data, 1)?)?,
+            strike: uint256(word(data, 2)?),
+            amount: uint256(word(data, 3)?),
+            locked_amount: uint256(word(data, 4)?),
+            premium: uint256(word(data, 5)?),
+            expiration: expiration.low_u64(),
+            // OptionType {Invalid, Put, Call}
+            option_type: match uint256(word(data, 7)?).low_u64() {
+                1 => OptionType::Put,
+                2 => OptionType::Call,
+                other => bail!("bad option type {}", other),
+            },
+        })
+    }
+}
+
+/// Somewhere to look up an option's terms
+pub trait OptionTermsSource {
+    /// the terms of option `id` of the options contract `contract`, as of block `block_number`
+    fn terms(
+        &self,
+        contract: Address,
+        id: OptionId,
+        block_number: BlockNumber,
+    ) -> Result<OptionTerms>;
+}
+
+/// Looks terms up in the options contracts themselves, through a node
+pub struct OptionsContracts<'a, T: Transport> {
+    client: &'a RpcClient<T>,
+}
+
+impl<'a, T: Transport> OptionsContracts<'a, T> {
+    pub fn new(client: &'a RpcClient<T>) -> Self {
+        OptionsContracts { client }
+    }
+}
+
+impl<'a, T: Transport> OptionTermsSource for OptionsContracts<'a, T> {
+    fn terms(
+        &self,
+        contract: Address,
+        id: OptionId,
+        block_number: BlockNumber,
+    ) -> Result<OptionTerms> {
+        let returned = self
+            .client
+            .call(&contract, &OptionTerms::calldata(id), block_number)?;
+        OptionTerms::decode(&returned)
+    }
+}
+
+/// An option someone bought, and what happened to it since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HegicOption {
+    /// the options contract that sold it
+    pub contract: Address,
+    pub id: OptionId,
+    pub holder: Address,
+    pub option_type: OptionType,
+    pub strike: U256,
+    pub amount: U256,
+    pub locked_amount: U256,
+    pub premium: U256,
+    pub settlement_fee: U256,
+    pub expiration: u64,
+    /// the block it was bought in
+    pub created_at: BlockNumber,
+    pub state: OptionState,
+}
+
+impl HegicOption {
+    /// what the holder paid for it, premium and settlement fee
+    pub fn cost(&self) -> U256 {
+        self.premium + self.settlement_fee
+    }
+
+    /// whether it can still be exercised at a unix timestamp
+    pub fn is_active_at(&self, timestamp: u64) -> bool {
+        self.state == OptionState::Active && timestamp <= self.expiration
+    }
+
+    /// What exercising it at `current_price` (USD with 8 decimals, like the strike) would pay, in the underlying.
+    /// Zero when it's out of the money, and never more than the pool locked for it. Like HegicETHOptions#payProfit
+    pub fn profit(&self, current_price: U256) -> U256 {
+        if current_price.is_zero() {
+            return U256::zero();
+        }
+        let in_the_money = match self.option_type {
+            OptionType::Call if self.strike <= current_price => current_price - self.strike,
+            OptionType::Put if self.strike >= current_price => self.strike - current_price,
+            _ => return U256::zero(),
+        };
+        (in_the_money.full_mul(self.amount) / current_price)
+            .try_into()
+            .unwrap_or(U256::MAX)
+            .min(self.locked_amount)
+    }
+}
+
+/// Every option bought and all the liquidity provided, built up from events
+#[derive(Debug, Clone, Default)]
+pub struct OptionBook {
+    // by options contract and id
+    options: HashMap<(Address, OptionId), HegicOption>,
+    // pool tokens held, by pool and liquidity provider
+    write_tokens: HashMap<(Address, Address), U256>,
+}
+
+impl OptionBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn option(&self, contract: Address, id: OptionId) -> Option<&HegicOption> {
+        self.options.get(&(contract, id))
+    }
+
+    /// every option `holder` ever bought, from any options contract, oldest first
+    pub fn options_of(&self, holder: Address) -> Vec<&HegicOption> {
+        let mut options: Vec<_> = self
+            .options
+            .values()
+            .filter(|option| option.holder == holder)
+            .collect();
+        options.sort_by_key(|option| (option.created_at, option.contract, option.id));
+        options
+    }
+
+    /// the options `holder` could exercise at a unix timestamp, oldest first
+    pub fn active_options_of(&self, holder: Address, timestamp: u64) -> Vec<&HegicOption> {
+        self.options_of(holder)
+            .into_iter()
+            .filter(|option| option.is_active_at(timestamp))
+            .collect()
+    }
+
+    /// the tokens of `pool` that `account` holds
+    pub fn write_tokens(&self, pool: Address, account: Address) -> U256 {
+        self.write_tokens
+            .get(&(pool, account))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies an event from block `block_number`, looking up the terms of new options in `terms`
+    pub fn apply<S: OptionTermsSource + ?Sized>(
+        &mut self,
+        block_number: BlockNumber,
+        event: &ContractEvent,
+        terms: &S,
+    ) -> Result<()> {
+        let contract = event.contract;
+        match &event.event {
+            HegicEvent::Create(create) => {
+                ensure!(
+                    !self.options.contains_key(&(contract, create.id)),
+                    "option {} was already created",
+                    create.id
+                );
+                let terms = terms.terms(contract, create.id, block_number)?;
+                ensure!(
+                    terms.holder == create.account,
+                    "option {} belongs to someone else than who bought it",
+                    create.id
+                );
+                self.options.insert(
+                    (contract, create.id),
+                    HegicOption {
+                        contract,
+                        id: create.id,
+                        holder: create.account,
+                        option_type: terms.option_type,
+                        strike: terms.strike,
+                        amount: terms.amount,
+                        locked_amount: terms.locked_amount,
+                        premium: terms.premium,
+                        settlement_fee: create.settlement_fee,
+                        expiration: terms.expiration,
+                        created_at: block_number,
+                        state: OptionState::Active,
+                    },
+                );
+            }
+            HegicEvent::Exercise(Exercise { id, profit }) => {
+                self.active_option(contract, *id)?.state =
+                    OptionState::Exercised { profit: *profit };
+            }
+            HegicEvent::Expire(Expire { id, .. }) => {
+                self.active_option(contract, *id)?.state = OptionState::Expired;
+            }
+            HegicEvent::Provide(provide) => {
+                let held = self
+                    .write_tokens
+                    .entry((contract, provide.account))
+                    .or_default();
+                *held = held
+                    .checked_add(provide.write_amount)
+                    .ok_or(anyhow!("pool tokens overflowed"))?;
+            }
+            HegicEvent::Withdraw(withdraw) => {
+                let held = self
+                    .write_tokens
+                    .entry((contract, withdraw.account))
+                    .or_default();
+                *held = held
+                    .checked_sub(withdraw.write_amount)
+                    .ok_or(anyhow!("withdrew more pool tokens than provided"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn active_option(&mut self, contract: Address, id: OptionId) -> Result<&mut HegicOption> {
+        let option = self
+            .options
+            .get_mut(&(contract, id))
+            .ok_or(anyhow!("option {} was never created", id))?;
+        ensure!(
+            option.state == OptionState::Active,
+            "option {} isn't active",
+            id
+        );
+        Ok(option)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock_node::{MockNode, HEGIC_ETH_FIRST_BLOCK};
+    use super::super::rpc;
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+    const OPTIONS: &str = "0xefc0eeadc1132a12c9487d800112693bf49ecfa2";
+    const POOL: &str = "0x878f15ffc8b894a1ba7647c7176e4c01f74e140b";
+
+    fn events(client: &RpcClient<MockNode>) -> Vec<(BlockNumber, ContractEvent)> {
+        let filter = HegicEvent::log_filter(vec![
+            rpc::address(OPTIONS).unwrap(),
+            rpc::address(POOL).unwrap(),
+        ]);
+        client
+            .get_logs(&filter, HEGIC_ETH_FIRST_BLOCK, HEGIC_ETH_FIRST_BLOCK + 5)
+            .unwrap()
+            .iter()
+            .map(|log| {
+                (
+                    log.block_number,
+                    ContractEvent::from_log(log).unwrap().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_option_and_pool_logs() {
+        let events = events(&MockNode::client("hegic_eth", 1000));
+        assert_eq!(events.len(), 6);
+        let contracts: Vec<_> = events.iter().map(|(_, event)| event.contract).collect();
+        let (options, pool) = (rpc::address(OPTIONS).unwrap(), rpc::address(POOL).unwrap());
+        assert_eq!(
+            contracts,
+            vec![pool, options, options, options, options, pool]
+        );
+        assert_eq!(
+            events[1].1.event,
+            HegicEvent::Create(Create {
+                id: 7.into(),
+                account: Address::from(0xa11ceu32),
+                settlement_fee: U256::from(E18 / 100),
+                total_fee: U256::from(6 * E18 / 100),
+            })
+        );
+        assert_eq!(
+            events[3].1.event,
+            HegicEvent::Exercise(Exercise {
+                id: 7.into(),
+                profit: U256::from(E18 / 10),
+            })
+        );
+        assert!(matches!(events[5].1.event, HegicEvent::Withdraw(_)));
+    }
+
+    #[test]
+    fn builds_positions_per_holder() {
+        let client = MockNode::client("hegic_eth", 1000);
+        let contract = OptionsContracts::new(&client);
+        let (options, pool) = (rpc::address(OPTIONS).unwrap(), rpc::address(POOL).unwrap());
+        let mut book = OptionBook::new();
+        let (alice, bob) = (Address::from(0xa11ceu32), Address::from(0xb0bu32));
+
+        let events = events(&client);
+        // before the exercise and the expiry, both are open
+        for (block_number, event) in events[..3].iter() {
+            book.apply(*block_number, event, &contract).unwrap();
+        }
+        let bought_at = 1_616_000_013;
+        assert_eq!(book.active_options_of(alice, bought_at).len(), 1);
+        let put = *book.active_options_of(bob, bought_at)[0];
+        assert_eq!(put.option_type, OptionType::Put);
+        assert_eq!(put.strike, U256::from(1800u64 * 100_000_000));
+        assert_eq!(put.amount, U256::from(2 * E18));
+        assert_eq!(put.cost(), U256::from(10 * E18 / 100));
+        assert_eq!(put.created_at, HEGIC_ETH_FIRST_BLOCK + 1);
+        assert!(!put.is_active_at(put.expiration + 1));
+
+        for (block_number, event) in events[3..].iter() {
+            book.apply(*block_number, event, &contract).unwrap();
+        }
+        assert_eq!(
+            book.options_of(alice)[0].state,
+            OptionState::Exercised {
+                profit: U256::from(E18 / 10)
+            }
+        );
+        assert_eq!(
+            book.option(options, 8.into()).unwrap().state,
+            OptionState::Expired
+        );
+        assert!(book.active_options_of(bob, bought_at).is_empty());
+        assert_eq!(
+            book.write_tokens(pool, Address::from(0xca401u32)),
+            U256::from(6000 * E18)
+        );
+
+        // an option can only go once
+        assert!(book
+            .apply(HEGIC_ETH_FIRST_BLOCK + 5, &events[4].1, &contract)
+            .is_err());
+        // and be created once
+        assert!(book
+            .apply(HEGIC_ETH_FIRST_BLOCK + 5, &events[1].1, &contract)
+            .is_err());
+        assert_eq!(
+            book.options_of(alice)[0].state,
+            OptionState::Exercised {
+                profit: U256::from(E18 / 10)
+            }
+        );
+        // the same tokens of another pool are someone else's business
+        assert!(book
+            .write_tokens(options, Address::from(0xca401u32))
+            .is_zero());
+    }
+
+    // every option of every contract is a 1 ETH call at $2000, held by whoever bought it
+    struct SameTerms;
+
+    impl OptionTermsSource for SameTerms {
+        fn terms(&self, _: Address, _: OptionId, _: BlockNumber) -> Result<OptionTerms> {
+            Ok(OptionTerms {
+                holder: Address::from(0xa11ceu32),
+                strike: U256::from(2000u64 * 100_000_000),
+                amount: U256::from(E18),
+                locked_amount: U256::from(E18),
+                premium: U256::from(E18 / 20),
+                expiration: 1_617_000_000,
+                option_type: OptionType::Call,
+            })
+        }
+    }
+
+    #[test]
+    fn keeps_options_contracts_apart() {
+        let mut book = OptionBook::new();
+        let alice = Address::from(0xa11ceu32);
+        let (eth_options, wbtc_options) = (Address::from(0xe7au32), Address::from(0xb7cu32));
+        let create = |contract| ContractEvent {
+            contract,
+            event: HegicEvent::Create(Create {
+                id: 1.into(),
+                account: alice,
+                settlement_fee: U256::zero(),
+                total_fee: U256::from(E18 / 20),
+            }),
+        };
+        book.apply(1, &create(eth_options), &SameTerms).unwrap();
+        book.apply(2, &create(wbtc_options), &SameTerms).unwrap();
+        assert_eq!(book.options_of(alice).len(), 2);
+
+        let exercise = ContractEvent {
+            contract: wbtc_options,
+            event: HegicEvent::Exercise(Exercise {
+                id: 1.into(),
+                profit: U256::zero(),
+            }),
+        };
+        book.apply(3, &exercise, &SameTerms).unwrap();
+        assert_eq!(
+            book.option(eth_options, 1.into()).unwrap().state,
+            OptionState::Active
+        );
+        assert_eq!(book.active_options_of(alice, 0).len(), 1);
+    }
+
+    #[test]
+    fn pays_out_like_the_contract() {
+        let usd = |dollars: u64| U256::from(dollars * 100_000_000);
+        let call = HegicOption {
+            contract: Address::zero(),
+            id: 1.into(),
+            holder: Address::zero(),
+            option_type: OptionType::Call,
+            strike: usd(2000),
+            amount: U256::from(E18),
+            locked_amount: U256::from(E18 / 4),
+            premium: U256::zero(),
+            settlement_fee: U256::zero(),
+            expiration: 0,
+            created_at: 0,
+            state: OptionState::Active,
+        };
+        assert_eq!(call.profit(usd(1900)), U256::zero());
+        assert_eq!(call.profit(usd(2500)), U256::from(E18 / 5));
+        // capped by what the pool locked
+        assert_eq!(call.profit(usd(4000)), U256::from(E18 / 4));
+
+        let put = HegicOption {
+            option_type: OptionType::Put,
+            locked_amount: U256::from(E18),
+            ..call
+        };
+        assert_eq!(put.profit(usd(2500)), U256::zero());
+        assert_eq!(put.profit(usd(1600)), U256::from(E18 / 4));
+    }
+}
@@ -0,0 +1,276 @@
+// a fake node for tests. it knows the blocks and logs in a fixture file under fixtures/rpc, answers the handful of
+// methods we use, and behaves like a picky provider: eth_getLogs is refused when it would return too many logs.
+// it can be used directly as a `Transport`, or served over HTTP on localhost.
+
+use super::blocks::BlockNumber;
+use super::rpc::{
+    address, bytes, hash, quantity, unwrap_response, Backoff, NodeError, RpcClient, Transport,
+};
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// the first block in fixtures/rpc/usdc_weth_005.json
+pub const USDC_WETH_005_FIRST_BLOCK: BlockNumber = 15_000_000;
+/// the first block in fixtures/rpc/hegic_eth.json
+pub const HEGIC_ETH_FIRST_BLOCK: BlockNumber = 12_000_000;
+
+/// retries like normal, but without the waiting
+pub fn no_waiting() -> Backoff {
+    Backoff {
+        retries: 3,
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    }
+}
+
+#[derive(Deserialize)]
+struct Fixture {
+    max_logs_per_request: usize,
+    blocks: Vec<Value>,
+    logs: Vec<Value>,
+    // eth_call answers, by contract and calldata. the block is ignored
+    #[serde(default)]
+    calls: Vec<Value>,
+}
+
+pub struct MockNode {
+    fixture: Fixture,
+    // how many of the next requests to drop on the floor
+    failures: Mutex<u32>,
+    // every eth_getLogs range asked for
+    log_requests: Mutex<Vec<(BlockNumber, BlockNumber)>>,
+    // an HTTP status to answer the next so many requests over HTTP with, instead of JSON-RPC
+    http_status: Mutex<(u16, u32)>,
+}
+
+impl MockNode {
+    /// loads fixtures/rpc/<name>.json
+    pub fn from_fixture(name: &str) -> Self {
+        let path = format!("{}/fixtures/rpc/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let file = std::fs::read_to_string(&path).expect("missing fixture");
+        MockNode {
+            fixture: serde_json::from_str(&file).expect("bad fixture"),
+            failures: Mutex::new(0),
+            log_requests: Mutex::new(Vec::new()),
+            http_status: Mutex::new((200, 0)),
+        }
+    }
+
+    /// a client for the node of fixtures/rpc/<name>.json, asking for at most `max_window` blocks of logs at once
+    pub fn client(name: &str, max_window: BlockNumber) -> RpcClient<MockNode> {
+        RpcClient::new(MockNode::from_fixture(name), no_waiting(), max_window)
+    }
+
+    /// lets eth_getLogs return up to `n` logs, whatever the fixture says
+    pub fn allowing_logs(mut self, n: usize) -> Self {
+        self.fixture.max_logs_per_request = n;
+        self
+    }
+
+    /// makes the next `n` requests fail like the connection dropped
+    pub fn fail_next(&self, n: u32) {
+        *self.failures.lock().unwrap() = n;
+    }
+
+    /// makes the next `n` requests over HTTP get a bare `status`, like a gateway in front of the node would send
+    pub fn answer_http_with(&self, status: u16, n: u32) {
+        *self.http_status.lock().unwrap() = (status, n);
+    }
+
+    pub fn log_requests(&self) -> Vec<(BlockNumber, BlockNumber)> {
+        self.log_requests.lock().unwrap().clone()
+    }
+
+    /// serves JSON-RPC over HTTP on a free localhost port, until the test exits. returns the url
+    pub fn serve(node: Arc<MockNode>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // a broken connection is the client's problem
+                let _ = node.answer_http(stream);
+            }
+        });
+        Ok(url)
+    }
+
+    fn answer_http(&self, mut stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse()?;
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        let request: Value = serde_json::from_slice(&body)?;
+
+        {
+            let mut http_status = self.http_status.lock().unwrap();
+            if http_status.1 > 0 {
+                http_status.1 -= 1;
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Nope\r\nContent-Type: text/plain\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope",
+                    http_status.0
+                )?;
+                return Ok(());
+            }
+        }
+        let (status, response) = match self.request(
+            request["method"].as_str().unwrap_or_default(),
+            request["params"].clone(),
+        ) {
+            Ok(result) => (
+                "200 OK",
+                json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+            ),
+            Err(error) => match error.downcast_ref::<NodeError>() {
+                Some(NodeError { code, message }) => (
+                    "200 OK",
+                    json!({"jsonrpc": "2.0", "id": request["id"], "error": {"code": code, "message": message}}),
+                ),
+                None => ("503 Service Unavailable", json!({})),
+            },
+        };
+        let response = response.to_string();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            response.len(),
+            response
+        )?;
+        Ok(())
+    }
+
+    fn call(&self, call: &Value) -> Result<Value> {
+        let to = address(call["to"].as_str().unwrap_or_default())?;
+        let data = bytes(call["data"].as_str().unwrap_or_default())?;
+        for known in self.fixture.calls.iter() {
+            if address(known["to"].as_str().unwrap_or_default())? == to
+                && bytes(known["data"].as_str().unwrap_or_default())? == data
+            {
+                return Ok(known["result"].clone());
+            }
+        }
+        Err(NodeError {
+            code: -32000,
+            message: "execution reverted".to_string(),
+        }
+        .into())
+    }
+
+    fn block(&self, number: BlockNumber) -> Option<&Value> {
+        self.fixture.blocks.iter().find(|block| {
+            quantity(block["number"].as_str().unwrap_or_default()).ok() == Some(number)
+        })
+    }
+
+    fn latest(&self) -> Result<BlockNumber> {
+        self.fixture
+            .blocks
+            .iter()
+            .map(|block| quantity(block["number"].as_str().unwrap_or_default()))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .max()
+            .ok_or(anyhow!("fixture has no blocks"))
+    }
+
+    fn get_logs(&self, filter: &Value) -> Result<Value> {
+        let block_param = |key: &str| -> Result<BlockNumber> {
+            match filter[key].as_str() {
+                Some("latest") | None => self.latest(),
+                Some(number) => quantity(number),
+            }
+        };
+        let (from, to) = (block_param("fromBlock")?, block_param("toBlock")?);
+        self.log_requests.lock().unwrap().push((from, to));
+
+        let addresses = match &filter["address"] {
+            Value::Null => vec![],
+            Value::String(one) => vec![address(one)?],
+            Value::Array(many) => many
+                .iter()
+                .map(|a| address(a.as_str().unwrap_or_default()))
+                .collect::<Result<_>>()?,
+            other => bail!("bad address filter {}", other),
+        };
+        let topics = filter["topics"].as_array().cloned().unwrap_or_default();
+
+        let mut logs = Vec::new();
+        for log in self.fixture.logs.iter() {
+            let block_number = quantity(log["blockNumber"].as_str().unwrap_or_default())?;
+            let log_address = address(log["address"].as_str().unwrap_or_default())?;
+            let log_topics = log["topics"].as_array().cloned().unwrap_or_default();
+            let topics_match = topics.iter().enumerate().all(|(i, wanted)| {
+                let actual = log_topics.get(i).and_then(Value::as_str).map(hash);
+                match (wanted, actual) {
+                    (Value::Null, _) => true,
+                    (Value::String(one), Some(Ok(actual))) => hash(one).ok() == Some(actual),
+                    (Value::Array(any), Some(Ok(actual))) => any
+                        .iter()
+                        .any(|t| t.as_str().map(hash).and_then(Result::ok) == Some(actual)),
+                    _ => false,
+                }
+            });
+            if (from..=to).contains(&block_number)
+                && (addresses.is_empty() || addresses.contains(&log_address))
+                && topics_match
+            {
+                logs.push(log.clone());
+            }
+        }
+        if logs.len() > self.fixture.max_logs_per_request {
+            return Err(NodeError {
+                code: -32005,
+                message: format!(
+                    "query returned more than {} results",
+                    self.fixture.max_logs_per_request
+                ),
+            }
+            .into());
+        }
+        Ok(Value::Array(logs))
+    }
+}
+
+impl Transport for MockNode {
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                bail!("connection reset by peer");
+            }
+        }
+        match method {
+            "eth_blockNumber" => Ok(json!(format!("{:#x}", self.latest()?))),
+            "eth_getBlockByNumber" => {
+                let number = quantity(params[0].as_str().unwrap_or_default())?;
+                Ok(self.block(number).cloned().unwrap_or(Value::Null))
+            }
+            "eth_getLogs" => self.get_logs(&params[0]),
+            "eth_call" => self.call(&params[0]),
+            _ => unwrap_response(json!({
+                "error": {"code": -32601, "message": format!("the method {} does not exist", method)}
+            })),
+        }
+    }
+}
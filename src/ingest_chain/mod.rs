@@ -3,13 +3,21 @@
 
 // so what we do is we take the on-disk database, check what's already been scanned for and in what version.
 // if something's not there, we do a pass over the relevant blocks to get those events.
-mod blocks;
-mod db_types;
-mod talk_to_sled;
+pub mod abi;
+pub mod blocks;
+pub mod db_types;
+pub mod hegic;
+#[cfg(test)]
+pub mod mock_node;
+pub mod rpc;
+pub mod sync;
+pub mod talk_to_sled;
+pub mod uniswap_v3;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-enum Protocol {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Protocol {
     UniswapV2,
     HegicOptions,
+    UniswapV3,
 }
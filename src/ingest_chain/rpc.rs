@@ -0,0 +1,658 @@
+// this is the part that actually talks to a node. everything goes through `Transport`, so the tests can swap in
+// a fake node and nobody has to sync mainnet to run them.
+
+use super::blocks::BlockNumber;
+use crate::solidints::{U160::U160, U256};
+use anyhow::{anyhow, bail, Result};
+use primitive_types::H256;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub type Address = U160;
+
+/// Something that can send a JSON-RPC request to a node
+pub trait Transport {
+    /// sends `method` with `params`, and returns the `result` of the response.
+    /// if the node answers with an error object, that comes back as a `NodeError`
+    fn request(&self, method: &str, params: Value) -> Result<Value>;
+}
+
+/// An error object the node answered with, as opposed to not answering at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl NodeError {
+    /// whether the node refused an eth_getLogs because the answer would be too big.
+    /// every provider phrases this differently, these are the ones we've run into
+    pub fn is_too_many_results(&self) -> bool {
+        let message = self.message.to_lowercase();
+        [
+            "query returned more than",
+            "too many results",
+            "response size exceeded",
+            "log response size",
+            "block range is too wide",
+            "exceed maximum block range",
+        ]
+        .iter()
+        .any(|needle| message.contains(needle))
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        self.code == 429 || self.message.to_lowercase().contains("rate limit")
+    }
+}
+
+/// An HTTP error status the node answered with, without a JSON-RPC error object in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpError {
+    pub status: u16,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node answered with HTTP {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl HttpError {
+    /// too many requests, or the node (or whatever's in front of it) is having a bad time.
+    /// anything else, like a bad API key or a wrong URL, won't fix itself
+    pub fn is_transient(&self) -> bool {
+        self.status == 429 || (500..600).contains(&self.status)
+    }
+}
+
+/// Pulls the result out of a JSON-RPC response
+pub fn unwrap_response(response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error") {
+        return Err(NodeError {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        }
+        .into());
+    }
+    response.get("result").cloned().ok_or(anyhow!(
+        "response has neither a result nor an error: {}",
+        response
+    ))
+}
+
+/// JSON-RPC over HTTP(S)
+pub struct HttpTransport {
+    url: String,
+    agent: ureq::Agent,
+    next_id: AtomicU64,
+}
+
+impl HttpTransport {
+    pub fn new(url: &str, timeout: Duration) -> Self {
+        HttpTransport {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let response = self.agent.post(&self.url).send_json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }));
+        match response {
+            Ok(response) => unwrap_response(response.into_json()?),
+            // some nodes send error objects with a 4xx/5xx, those are still answers
+            Err(ureq::Error::Status(status, response)) => {
+                match response.into_json::<Value>().ok().map(unwrap_response) {
+                    Some(Err(node_error)) => Err(node_error),
+                    _ => Err(anyhow::Error::new(HttpError { status })
+                        .context(format!("requesting {}", self.url))),
+                }
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// How many times to retry a request that didn't get an answer, and how long to wait in between.
+/// The wait doubles every time, up to `max_delay`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            retries: 5,
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Backoff {
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay)
+    }
+}
+
+/// A log, as eth_getLogs returns it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
+    pub block_number: BlockNumber,
+    pub block_hash: H256,
+    pub transaction_hash: H256,
+    pub transaction_index: u64,
+    pub log_index: u64,
+    /// set when the log's block got reorged out
+    pub removed: bool,
+}
+
+/// The parts of a block header we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub number: BlockNumber,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub timestamp: u64,
+    /// not there before London
+    pub base_fee_per_gas: Option<U256>,
+}
+
+/// Which logs to ask for: any of `addresses` (or anyone, if empty), with the topics in `topics`.
+/// Each position is either anything (`None`) or any of the given topics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    pub addresses: Vec<Address>,
+    pub topics: Vec<Option<Vec<H256>>>,
+}
+
+impl LogFilter {
+    fn to_params(&self, from: BlockNumber, to: BlockNumber) -> Value {
+        let addresses: Vec<String> = self.addresses.iter().map(address_to_hex).collect();
+        let topics: Vec<Value> = self
+            .topics
+            .iter()
+            .map(|topic| match topic {
+                None => Value::Null,
+                Some(any_of) => any_of.iter().map(|t| to_hex(t.as_bytes())).collect(),
+            })
+            .collect();
+        json!([{
+            "fromBlock": quantity_to_hex(from),
+            "toBlock": quantity_to_hex(to),
+            "address": addresses,
+            "topics": topics,
+        }])
+    }
+}
+
+/// A node client that fetches logs and headers, retrying requests that don't get through
+/// and splitting up eth_getLogs calls the node thinks are too big
+pub struct RpcClient<T: Transport> {
+    transport: T,
+    backoff: Backoff,
+    max_window: BlockNumber,
+    // how many blocks we ask for logs at once. shrinks when the node complains, grows back when it doesn't
+    window: Cell<BlockNumber>,
+}
+
+impl<T: Transport> RpcClient<T> {
+    /// # Arguments
+    /// * `transport` Where requests go
+    /// * `backoff` How to retry requests that don't get through
+    /// * `max_window` The most blocks to ask for logs from in one request
+    pub fn new(transport: T, backoff: Backoff, max_window: BlockNumber) -> Self {
+        let max_window = max_window.max(1);
+        RpcClient {
+            transport,
+            backoff,
+            max_window,
+            window: Cell::new(max_window),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+    /// how many blocks the next eth_getLogs will cover, at most
+    pub fn window(&self) -> BlockNumber {
+        self.window.get()
+    }
+
+    /// eth_blockNumber
+    pub fn block_number(&self) -> Result<BlockNumber> {
+        let result = self.request("eth_blockNumber", json!([]))?;
+        quantity(as_str(&result)?)
+    }
+
+    /// eth_call of `data` against the contract at `to`, in the state after block `number`. returns what it returned
+    pub fn call(&self, to: &Address, data: &[u8], number: BlockNumber) -> Result<Vec<u8>> {
+        let result = self.request(
+            "eth_call",
+            json!([{"to": address_to_hex(to), "data": to_hex(data)}, quantity_to_hex(number)]),
+        )?;
+        bytes(as_str(&result)?)
+    }
+
+    /// eth_getBlockByNumber, without the transactions. `None` if the node doesn't have the block (yet)
+    pub fn get_block_by_number(&self, number: BlockNumber) -> Result<Option<BlockHeader>> {
+        let result = self.request(
+            "eth_getBlockByNumber",
+            json!([quantity_to_hex(number), false]),
+        )?;
+        if result.is_null() {
+            return Ok(None);
+        }
+        let raw: RawBlock = serde_json::from_value(result)?;
+        Ok(Some(raw.try_into()?))
+    }
+
+    /// eth_getLogs over `from..=to`, in as many requests as it takes
+    pub fn get_logs(
+        &self,
+        filter: &LogFilter,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<Log>> {
+        let mut logs = Vec::new();
+        let mut start = from;
+        while start <= to {
+            let end = to.min(start.saturating_add(self.window.get() - 1));
+            match self.get_logs_once(filter, start, end) {
+                Ok(mut window_logs) => {
+                    logs.append(&mut window_logs);
+                    self.window
+                        .set(self.max_window.min(self.window.get().saturating_mul(2)));
+                    if end == BlockNumber::MAX {
+                        break;
+                    }
+                    start = end + 1;
+                }
+                Err(error) if too_many_results(&error) => {
+                    if start == end {
+                        return Err(
+                            error.context(format!("block {} alone has too many logs", start))
+                        );
+                    }
+                    // half of the end - start + 1 blocks we just asked for
+                    self.window.set((end - start).div_ceil(2));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(logs)
+    }
+
+    fn get_logs_once(
+        &self,
+        filter: &LogFilter,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<Log>> {
+        let result = self.request("eth_getLogs", filter.to_params(from, to))?;
+        let raw: Vec<RawLog> = serde_json::from_value(result)?;
+        raw.into_iter().map(Log::try_from).collect()
+    }
+
+    /// sends a request, retrying whatever didn't get an answer with backoff
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let mut attempt = 0;
+        loop {
+            match self.transport.request(method, params.clone()) {
+                Ok(result) => return Ok(result),
+                Err(error) if attempt < self.backoff.retries && retryable(&error) => {
+                    thread::sleep(self.backoff.delay(attempt));
+                    attempt += 1;
+                }
+                Err(error) => {
+                    return Err(
+                        error.context(format!("{} failed after {} retries", method, attempt))
+                    )
+                }
+            }
+        }
+    }
+}
+
+fn too_many_results(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<NodeError>()
+        .is_some_and(NodeError::is_too_many_results)
+}
+
+// a node error means the node understood us and said no, asking again won't help. unless it's telling us to slow down.
+// same for HTTP errors, only a 429 or a 5xx is worth another go. anything else never got an answer at all
+fn retryable(error: &anyhow::Error) -> bool {
+    if let Some(node_error) = error.downcast_ref::<NodeError>() {
+        return node_error.is_rate_limited();
+    }
+    error
+        .downcast_ref::<HttpError>()
+        .is_none_or(HttpError::is_transient)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLog {
+    address: String,
+    topics: Vec<String>,
+    data: String,
+    block_number: String,
+    block_hash: String,
+    transaction_hash: String,
+    transaction_index: String,
+    log_index: String,
+    #[serde(default)]
+    removed: bool,
+}
+
+impl TryFrom<RawLog> for Log {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawLog) -> Result<Log> {
+        Ok(Log {
+            address: address(&raw.address)?,
+            topics: raw
+                .topics
+                .iter()
+                .map(|topic| hash(topic))
+                .collect::<Result<_>>()?,
+            data: bytes(&raw.data)?,
+            block_number: quantity(&raw.block_number)?,
+            block_hash: hash(&raw.block_hash)?,
+            transaction_hash: hash(&raw.transaction_hash)?,
+            transaction_index: quantity(&raw.transaction_index)?,
+            log_index: quantity(&raw.log_index)?,
+            removed: raw.removed,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBlock {
+    number: String,
+    hash: String,
+    parent_hash: String,
+    timestamp: String,
+    base_fee_per_gas: Option<String>,
+}
+
+impl TryFrom<RawBlock> for BlockHeader {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawBlock) -> Result<BlockHeader> {
+        Ok(BlockHeader {
+            number: quantity(&raw.number)?,
+            hash: hash(&raw.hash)?,
+            parent_hash: hash(&raw.parent_hash)?,
+            timestamp: quantity(&raw.timestamp)?,
+            base_fee_per_gas: raw
+                .base_fee_per_gas
+                .map(|fee| {
+                    U256::from_str_radix(strip_0x(&fee)?, 16).map_err(|e| anyhow!("{:?}", e))
+                })
+                .transpose()?,
+        })
+    }
+}
+
+// hex encoding, the way JSON-RPC wants it
+
+fn as_str(value: &Value) -> Result<&str> {
+    value
+        .as_str()
+        .ok_or(anyhow!("expected a hex string, got {}", value))
+}
+
+fn strip_0x(s: &str) -> Result<&str> {
+    s.strip_prefix("0x")
+        .ok_or(anyhow!("hex string without 0x: {}", s))
+}
+
+pub fn quantity(s: &str) -> Result<u64> {
+    Ok(u64::from_str_radix(strip_0x(s)?, 16)?)
+}
+
+pub fn quantity_to_hex(n: u64) -> String {
+    format!("{:#x}", n)
+}
+
+pub fn bytes(s: &str) -> Result<Vec<u8>> {
+    let digits = strip_0x(s)?;
+    if digits.len() % 2 != 0 {
+        bail!("odd number of hex digits: {}", s);
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&digits[i..i + 2], 16)?))
+        .collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
+pub fn hash(s: &str) -> Result<H256> {
+    let bytes = bytes(s)?;
+    if bytes.len() != 32 {
+        bail!("not a 32 byte hash: {}", s);
+    }
+    Ok(H256::from_slice(&bytes))
+}
+
+pub fn address(s: &str) -> Result<Address> {
+    let bytes = bytes(s)?;
+    if bytes.len() != 20 {
+        bail!("not a 20 byte address: {}", s);
+    }
+    Ok(Address::from_big_endian(&bytes))
+}
+
+pub fn address_to_hex(address: &Address) -> String {
+    let mut bytes = [0u8; 20];
+    address.to_big_endian(&mut bytes);
+    to_hex(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock_node::{no_waiting, MockNode, USDC_WETH_005_FIRST_BLOCK};
+    use super::*;
+    use std::sync::Arc;
+
+    const POOL: &str = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+
+    fn pool_filter() -> LogFilter {
+        LogFilter {
+            addresses: vec![address(POOL).unwrap()],
+            topics: vec![],
+        }
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(quantity("0xe4e1c0").unwrap(), USDC_WETH_005_FIRST_BLOCK);
+        assert_eq!(quantity_to_hex(USDC_WETH_005_FIRST_BLOCK), "0xe4e1c0");
+        assert_eq!(address_to_hex(&address(POOL).unwrap()), POOL);
+        assert_eq!(bytes("0x00ff10").unwrap(), vec![0, 255, 16]);
+        assert!(bytes("0xabc").is_err());
+        assert!(quantity("12").is_err());
+        assert!(hash(POOL).is_err());
+    }
+
+    #[test]
+    fn talks_to_a_node_over_http() {
+        let node = Arc::new(MockNode::from_fixture("usdc_weth_005"));
+        let url = MockNode::serve(node.clone()).unwrap();
+        let client = RpcClient::new(
+            HttpTransport::new(&url, Duration::from_secs(5)),
+            no_waiting(),
+            1000,
+        );
+
+        assert_eq!(
+            client.block_number().unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+        let header = client
+            .get_block_by_number(USDC_WETH_005_FIRST_BLOCK + 1)
+            .unwrap()
+            .unwrap();
+        let parent = client
+            .get_block_by_number(USDC_WETH_005_FIRST_BLOCK)
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.parent_hash, parent.hash);
+        assert_eq!(header.base_fee_per_gas, Some(U256::from(21_000_000_000u64)));
+        assert_eq!(
+            client
+                .get_block_by_number(USDC_WETH_005_FIRST_BLOCK + 100)
+                .unwrap(),
+            None
+        );
+
+        let logs = client
+            .get_logs(
+                &pool_filter(),
+                USDC_WETH_005_FIRST_BLOCK,
+                USDC_WETH_005_FIRST_BLOCK + 1,
+            )
+            .unwrap();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs[0].block_number, USDC_WETH_005_FIRST_BLOCK);
+        assert_eq!(logs[2].log_index, 7);
+        assert_eq!(logs[0].data.len(), 5 * 32);
+
+        // errors make it through HTTP as node errors, not as something worth retrying
+        let error = client
+            .get_logs(
+                &LogFilter::default(),
+                USDC_WETH_005_FIRST_BLOCK + 5,
+                USDC_WETH_005_FIRST_BLOCK + 5,
+            )
+            .unwrap_err();
+        assert!(too_many_results(&error));
+    }
+
+    #[test]
+    fn splits_windows_the_node_refuses() {
+        let client = MockNode::client("usdc_weth_005", 1000);
+        let logs = client
+            .get_logs(
+                &pool_filter(),
+                USDC_WETH_005_FIRST_BLOCK,
+                USDC_WETH_005_FIRST_BLOCK + 11,
+            )
+            .unwrap();
+
+        // everything from the pool, in order, nothing twice
+        assert_eq!(logs.len(), 8);
+        let positions: Vec<_> = logs.iter().map(|l| (l.block_number, l.log_index)).collect();
+        let mut sorted = positions.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(positions, sorted);
+
+        // it had to split, and never asked for more than the node would give
+        let requests = client.transport().log_requests();
+        assert!(requests.len() > 1);
+        assert!(requests.iter().all(|(from, to)| from <= to));
+        assert_eq!(
+            requests.first(),
+            Some(&(USDC_WETH_005_FIRST_BLOCK, USDC_WETH_005_FIRST_BLOCK + 11))
+        );
+        assert!(client.window() <= 1000);
+    }
+
+    #[test]
+    fn retries_with_backoff_until_it_gets_through() {
+        let client = MockNode::client("usdc_weth_005", 1000);
+        client.transport().fail_next(2);
+        assert_eq!(
+            client.block_number().unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+
+        client.transport().fail_next(4);
+        assert!(client.block_number().is_err());
+
+        let backoff = Backoff {
+            retries: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(400));
+        assert_eq!(backoff.delay(9), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn only_retries_http_errors_that_might_go_away() {
+        let node = Arc::new(MockNode::from_fixture("usdc_weth_005"));
+        let url = MockNode::serve(node.clone()).unwrap();
+        let client = RpcClient::new(
+            HttpTransport::new(&url, Duration::from_secs(5)),
+            no_waiting(),
+            1000,
+        );
+
+        node.answer_http_with(429, 2);
+        assert_eq!(
+            client.block_number().unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+        node.answer_http_with(502, 2);
+        assert_eq!(
+            client.block_number().unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+
+        // a bad API key is asked about once, not once per retry
+        node.answer_http_with(401, 2);
+        let error = client.block_number().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<HttpError>(),
+            Some(&HttpError { status: 401 })
+        );
+        // so the second 401 is still waiting for the next request
+        assert!(client.block_number().is_err());
+        assert_eq!(
+            client.block_number().unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+    }
+}
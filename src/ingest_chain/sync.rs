@@ -0,0 +1,562 @@
+// figures out which blocks we don't have events for yet, goes and gets them, and writes them down.
+// coverage is per protocol, so asking for a protocol we've never synced alongside one we have only fetches the new one.
+// blocks near the tip can still get reorged away, so every sync first checks the hashes we stored against the chain,
+// and throws out everything past where they stop matching. blocks `confirmations` deep are final and never checked again.
+
+use super::blocks::*;
+use super::db_types::*;
+use super::rpc::{BlockHeader, LogFilter, RpcClient, Transport};
+use super::talk_to_sled::SledHandle;
+use super::Protocol;
+use anyhow::{anyhow, bail, ensure, Result};
+use std::collections::{HashMap, HashSet};
+
+/// Somewhere events come from
+pub trait EventSource {
+    /// every event of `protocol` in blocks `first` through `last`
+    fn fetch(
+        &self,
+        protocol: Protocol,
+        first: BlockNumber,
+        last: BlockNumber,
+    ) -> Result<Vec<(Timestamp, Event)>>;
+
+    /// the latest block
+    fn head(&self) -> Result<BlockNumber>;
+
+    /// block `number` on the current chain, if it got that far
+    fn header(&self, number: BlockNumber) -> Result<Option<BlockHeader>>;
+}
+
+/// Events from a node's logs, one filter per protocol
+pub struct RpcEventSource<T: Transport> {
+    client: RpcClient<T>,
+    filters: HashMap<Protocol, LogFilter>,
+}
+
+impl<T: Transport> RpcEventSource<T> {
+    pub fn new(client: RpcClient<T>, filters: HashMap<Protocol, LogFilter>) -> Self {
+        RpcEventSource { client, filters }
+    }
+
+    pub fn client(&self) -> &RpcClient<T> {
+        &self.client
+    }
+}
+
+impl<T: Transport> EventSource for RpcEventSource<T> {
+    fn fetch(
+        &self,
+        protocol: Protocol,
+        first: BlockNumber,
+        last: BlockNumber,
+    ) -> Result<Vec<(Timestamp, Event)>> {
+        let filter = self
+            .filters
+            .get(&protocol)
+            .ok_or(anyhow!("no log filter for {:?}", protocol))?;
+        self.client
+            .get_logs(filter, first, last)?
+            .into_iter()
+            .filter(|log| !log.removed)
+            .map(|log| {
+                Ok((
+                    Timestamp::new(log.block_number, log.log_index),
+                    Event::new(protocol, EventData::from_log(protocol, &log)?),
+                ))
+            })
+            .collect()
+    }
+
+    fn head(&self) -> Result<BlockNumber> {
+        self.client.block_number()
+    }
+
+    fn header(&self, number: BlockNumber) -> Result<Option<BlockHeader>> {
+        self.client.get_block_by_number(number)
+    }
+}
+
+/// Blocks `first` through `last` of one protocol, fetched and stored in one go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchJob {
+    pub protocol: Protocol,
+    pub first: BlockNumber,
+    pub last: BlockNumber,
+}
+
+/// Fills in whatever's missing from the database
+pub struct Syncer<S: EventSource> {
+    source: S,
+    job_size: BlockNumber,
+    confirmations: BlockNumber,
+}
+
+impl<S: EventSource> Syncer<S> {
+    /// # Arguments
+    /// * `source` Where missing events come from
+    /// * `job_size` The most blocks to fetch and store at once. a job that fails halfway leaves everything before it stored
+    /// * `confirmations` How many blocks have to be mined on top of a block before it's final. a reorg deeper than this is an error
+    pub fn new(source: S, job_size: BlockNumber, confirmations: BlockNumber) -> Self {
+        Syncer {
+            source,
+            job_size: job_size.max(1),
+            confirmations,
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// The jobs that would fill in every block of `requested` that some protocol isn't covered for, in block order per protocol
+    pub fn plan(
+        &self,
+        db: &SledHandle,
+        protocols: &HashSet<Protocol>,
+        requested: &Blocks,
+    ) -> Result<Vec<FetchJob>> {
+        let mut jobs = Vec::new();
+        for protocol in protocols.iter() {
+            let missing = requested.clone() - db.coverage(*protocol)?;
+            for range in missing.as_ref().iter() {
+                let (mut first, last) = first_and_last(range);
+                loop {
+                    let job_last = last.min(first.saturating_add(self.job_size - 1));
+                    jobs.push(FetchJob {
+                        protocol: *protocol,
+                        first,
+                        last: job_last,
+                    });
+                    if job_last == last {
+                        break;
+                    }
+                    first = job_last + 1;
+                }
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Compares the hashes of the blocks we have that aren't final yet with the chain, newest first.
+    /// If they stopped matching somewhere, rolls the database back to there and returns the fork point
+    pub fn check_reorg(&self, db: &SledHandle) -> Result<Option<BlockNumber>> {
+        let finalized = db.finalized()?;
+        let mut fork = None;
+        for entry in db.blocks_from_newest() {
+            let (number, record) = entry?;
+            let on_chain = self.source.header(number)?;
+            if on_chain.is_some_and(|header| BlockRecord::from(&header) == record) {
+                break;
+            }
+            if finalized.is_some_and(|finalized| number <= finalized) {
+                bail!(
+                    "block {} got reorged, but it was {} confirmations deep and final",
+                    number,
+                    self.confirmations
+                );
+            }
+            fork = Some(number);
+        }
+        if let Some(fork) = fork {
+            db.roll_back(fork)?;
+        }
+        Ok(fork)
+    }
+
+    /// Fetches and stores everything `requested` is missing, after rolling back anything that got reorged away.
+    /// Then marks everything `confirmations` deep final. returns the jobs it ran
+    pub fn sync(
+        &self,
+        db: &SledHandle,
+        protocols: &HashSet<Protocol>,
+        requested: &Blocks,
+    ) -> Result<Vec<FetchJob>> {
+        let head = self.source.head()?;
+        if let Some(last) = requested
+            .as_ref()
+            .last()
+            .map(|range| first_and_last(range).1)
+        {
+            ensure!(
+                last <= head,
+                "block {} isn't mined yet, the head is {}",
+                last,
+                head
+            );
+        }
+        self.check_reorg(db)?;
+
+        let jobs = self.plan(db, protocols, requested)?;
+        for job in jobs.iter() {
+            // headers before logs: if the chain moves in between, the logs are newer than the hashes,
+            // so the next check_reorg catches it instead of the hashes vouching for orphaned logs
+            let blocks = self.headers(db, job.first, job.last)?;
+            let events = self.source.fetch(job.protocol, job.first, job.last)?;
+            db.add_blocks(&blocks)?;
+            db.add_time_range(
+                vec![job.protocol],
+                &Blocks::between(job.first, job.last),
+                events,
+            )?;
+        }
+        if let Some(finalized) = head.checked_sub(self.confirmations) {
+            db.finalize(finalized)?;
+        }
+        Ok(jobs)
+    }
+
+    // the hashes of blocks `first` through `last`, checked to chain onto each other and onto what's stored
+    fn headers(
+        &self,
+        db: &SledHandle,
+        first: BlockNumber,
+        last: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, BlockRecord)>> {
+        let mut parent = match first.checked_sub(1) {
+            Some(before) => db.block(before)?.map(|record| record.hash),
+            None => None,
+        };
+        let mut blocks = Vec::new();
+        for number in first..=last {
+            let header = self
+                .source
+                .header(number)?
+                .ok_or(anyhow!("node doesn't have block {}", number))?;
+            ensure!(
+                parent.is_none_or(|parent| parent == header.parent_hash),
+                "the chain reorganized around block {} while syncing",
+                number
+            );
+            parent = Some(header.hash);
+            blocks.push((number, BlockRecord::from(&header)));
+        }
+        Ok(blocks)
+    }
+
+    /// `SledHandle::get_time_range`, syncing whatever's missing first
+    pub fn get_time_range(
+        &self,
+        db: &SledHandle,
+        protocols: HashSet<Protocol>,
+        requested: &Blocks,
+    ) -> Result<Vec<(Timestamp, Event)>> {
+        self.sync(db, &protocols, requested)?;
+        db.get_time_range(protocols, requested)?
+            .ok_or(anyhow!("still missing blocks right after syncing them"))
+    }
+}
+
+/// The first block at or after a unix timestamp, between `earliest` and the node's latest block.
+/// For turning dates into blocks
+pub fn block_at_time<T: Transport>(
+    client: &RpcClient<T>,
+    timestamp: u64,
+    earliest: BlockNumber,
+) -> Result<BlockNumber> {
+    let block_time = |number| -> Result<u64> {
+        Ok(client
+            .get_block_by_number(number)?
+            .ok_or(anyhow!("node doesn't have block {}", number))?
+            .timestamp)
+    };
+    let (mut low, mut high) = (earliest, client.block_number()?);
+    if block_time(high)? < timestamp {
+        return Err(anyhow!("no block at or after {} yet", timestamp));
+    }
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if block_time(middle)? < timestamp {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock_node::{no_waiting, MockNode, USDC_WETH_005_FIRST_BLOCK};
+    use super::super::rpc::{self, address};
+    use super::super::uniswap_v3::{PoolEvent, UniswapV3Event};
+    use super::*;
+    use primitive_types::H256;
+    use std::cell::{Cell, RefCell};
+
+    // hands out one event per block and protocol, and remembers what it was asked for.
+    // its chain can fork: from the fork block on, hashes and events carry the fork's id
+    struct CountingSource {
+        head: BlockNumber,
+        fork: Cell<Option<(BlockNumber, u8)>>,
+        fetched: RefCell<Vec<FetchJob>>,
+    }
+
+    impl CountingSource {
+        fn new(head: BlockNumber) -> Self {
+            CountingSource {
+                head,
+                fork: Cell::new(None),
+                fetched: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn fork_id(&self, block: BlockNumber) -> u8 {
+            match self.fork.get() {
+                Some((from, id)) if block >= from => id,
+                _ => 0,
+            }
+        }
+
+        fn hash(&self, block: BlockNumber) -> H256 {
+            let mut hash = H256::zero();
+            hash.0[..8].copy_from_slice(&block.to_be_bytes());
+            hash.0[8] = self.fork_id(block);
+            hash
+        }
+    }
+
+    impl EventSource for CountingSource {
+        fn fetch(
+            &self,
+            protocol: Protocol,
+            first: BlockNumber,
+            last: BlockNumber,
+        ) -> Result<Vec<(Timestamp, Event)>> {
+            self.fetched.borrow_mut().push(FetchJob {
+                protocol,
+                first,
+                last,
+            });
+            Ok((first..=last)
+                .map(|block| {
+                    (
+                        Timestamp::new(block, protocol as u64),
+                        Event::new(
+                            protocol,
+                            EventData::Raw {
+                                topics: vec![],
+                                data: vec![self.fork_id(block)],
+                            },
+                        ),
+                    )
+                })
+                .collect())
+        }
+
+        fn head(&self) -> Result<BlockNumber> {
+            Ok(self.head)
+        }
+
+        fn header(&self, number: BlockNumber) -> Result<Option<BlockHeader>> {
+            Ok((number <= self.head).then(|| BlockHeader {
+                number,
+                hash: self.hash(number),
+                parent_hash: self.hash(number.saturating_sub(1)),
+                timestamp: number * 12,
+                base_fee_per_gas: None,
+            }))
+        }
+    }
+
+    #[test]
+    fn only_fetches_what_is_missing() {
+        let db = SledHandle::temporary().unwrap();
+        let syncer = Syncer::new(CountingSource::new(1000), 10, 0);
+        let v2: HashSet<_> = [Protocol::UniswapV2].into_iter().collect();
+
+        assert_eq!(
+            syncer
+                .sync(&db, &v2, &Blocks::between(100, 114))
+                .unwrap()
+                .len(),
+            2
+        );
+        let plan = syncer
+            .plan(
+                &db,
+                &v2,
+                &Blocks::between(90, 120).union(Blocks::between(200, 200)),
+            )
+            .unwrap();
+        let ranges: Vec<_> = plan.iter().map(|job| (job.first, job.last)).collect();
+        assert_eq!(ranges, vec![(90, 99), (115, 120), (200, 200)]);
+
+        // the other protocol has nothing yet
+        let both: HashSet<_> = [Protocol::UniswapV2, Protocol::HegicOptions]
+            .into_iter()
+            .collect();
+        let events = syncer
+            .get_time_range(&db, both.clone(), &Blocks::between(95, 105))
+            .unwrap();
+        assert_eq!(events.len(), 2 * 11);
+        assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+        let fetched = syncer.source().fetched.borrow().len();
+        assert!(syncer
+            .plan(&db, &both, &Blocks::between(95, 105))
+            .unwrap()
+            .is_empty());
+        syncer
+            .get_time_range(&db, both, &Blocks::between(95, 105))
+            .unwrap();
+        assert_eq!(syncer.source().fetched.borrow().len(), fetched);
+    }
+
+    #[test]
+    fn syncs_from_a_node() {
+        let db = SledHandle::temporary().unwrap();
+        // two USDC/WETH pools, the 0.05% one and the 0.3% one
+        let (pool_005, pool_030) = (
+            address("0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640").unwrap(),
+            address("0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8").unwrap(),
+        );
+        let filter = UniswapV3Event::log_filter(vec![pool_005, pool_030]);
+        // one block has four logs between them, one more than the fixture's node allows
+        let node = MockNode::from_fixture("usdc_weth_005").allowing_logs(4);
+        let source = RpcEventSource::new(
+            RpcClient::new(node, no_waiting(), 1000),
+            [(Protocol::UniswapV3, filter)].into_iter().collect(),
+        );
+        let syncer = Syncer::new(source, 4, 3);
+        let protocols: HashSet<_> = [Protocol::UniswapV3].into_iter().collect();
+
+        let events = syncer
+            .get_time_range(
+                &db,
+                protocols.clone(),
+                &Blocks::between(USDC_WETH_005_FIRST_BLOCK, USDC_WETH_005_FIRST_BLOCK + 11),
+            )
+            .unwrap();
+        assert_eq!(events.len(), 9);
+        assert_eq!(events[0].0, Timestamp::new(USDC_WETH_005_FIRST_BLOCK, 3));
+
+        // and it's all on disk now
+        assert!(db
+            .check_time_range(
+                Protocol::UniswapV3,
+                &Blocks::between(USDC_WETH_005_FIRST_BLOCK, USDC_WETH_005_FIRST_BLOCK + 11)
+            )
+            .unwrap());
+        let from_disk = db
+            .get_time_range(
+                protocols.clone(),
+                &Blocks::between(USDC_WETH_005_FIRST_BLOCK + 5, USDC_WETH_005_FIRST_BLOCK + 5),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_disk.len(), 4);
+
+        // decoded on the way in, and still decoded on the way out
+        let from_disk = db
+            .get_time_range(
+                protocols,
+                &Blocks::between(USDC_WETH_005_FIRST_BLOCK, USDC_WETH_005_FIRST_BLOCK + 11),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_disk, events);
+        let pools: Vec<_> = from_disk
+            .iter()
+            .map(|(_, event)| match event.event_data() {
+                EventData::UniswapV3(event) => event.pool,
+                other => panic!("expected a pool event, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(pools[7], pool_030);
+        assert!(pools
+            .iter()
+            .enumerate()
+            .all(|(i, pool)| i == 7 || *pool == pool_005));
+        match from_disk[0].1.event_data() {
+            EventData::UniswapV3(PoolEvent {
+                event: UniswapV3Event::Swap(swap),
+                ..
+            }) => {
+                assert_eq!(swap.tick, 201000);
+                assert_eq!(swap.liquidity, 21_000_000_000_000_000_000);
+            }
+            other => panic!("expected a swap, got {:?}", other),
+        }
+        match from_disk[3].1.event_data() {
+            EventData::UniswapV3(PoolEvent {
+                event: UniswapV3Event::Mint(mint),
+                ..
+            }) => {
+                assert_eq!((mint.tick_lower, mint.tick_upper), (200940, 201060));
+            }
+            other => panic!("expected a mint, got {:?}", other),
+        }
+
+        // with the hashes of every block it stored, and the ones 3 deep final
+        assert_eq!(
+            db.block(USDC_WETH_005_FIRST_BLOCK + 3)
+                .unwrap()
+                .unwrap()
+                .hash,
+            rpc::hash("0xd81645243adb313e5b6b8113610a8d2264834c6861afb30bb6f388eb4af07ce8")
+                .unwrap()
+        );
+        assert_eq!(db.finalized().unwrap(), Some(USDC_WETH_005_FIRST_BLOCK + 8));
+        assert_eq!(syncer.check_reorg(&db).unwrap(), None);
+    }
+
+    #[test]
+    fn rolls_back_reorged_blocks() {
+        let db = SledHandle::temporary().unwrap();
+        let syncer = Syncer::new(CountingSource::new(120), 5, 3);
+        let v2: HashSet<_> = [Protocol::UniswapV2].into_iter().collect();
+        let everything = Blocks::between(100, 120);
+        let fork_ids = |events: Vec<(Timestamp, Event)>| -> Vec<u8> {
+            events
+                .iter()
+                .map(|(_, event)| match event.event_data() {
+                    EventData::Raw { data, .. } => data[0],
+                    other => panic!("expected a raw event, got {:?}", other),
+                })
+                .collect()
+        };
+
+        syncer.sync(&db, &v2, &everything).unwrap();
+        assert_eq!(db.finalized().unwrap(), Some(117));
+        assert!(syncer.plan(&db, &v2, &everything).unwrap().is_empty());
+
+        // the last two blocks get swapped out
+        syncer.source().fork.set(Some((119, 1)));
+        let jobs = syncer.sync(&db, &v2, &Blocks::between(100, 110)).unwrap();
+        assert!(jobs.is_empty());
+        assert!(!db
+            .check_time_range(Protocol::UniswapV2, &everything)
+            .unwrap());
+        assert_eq!(db.block(119).unwrap(), None);
+        let events = syncer.get_time_range(&db, v2.clone(), &everything).unwrap();
+        assert_eq!(events.len(), 21);
+        assert_eq!(fork_ids(events)[17..], [0, 0, 1, 1]);
+        assert_eq!(syncer.check_reorg(&db).unwrap(), None);
+
+        // but final blocks are final
+        syncer.source().fork.set(Some((115, 2)));
+        assert!(syncer.sync(&db, &v2, &everything).is_err());
+        assert!(db.roll_back(117).is_err());
+        assert!(syncer.sync(&db, &v2, &Blocks::between(100, 121)).is_err());
+    }
+
+    #[test]
+    fn finds_blocks_by_time() {
+        let client = MockNode::client("usdc_weth_005", 1000);
+        // blocks are 12 seconds apart from 1655000000
+        assert_eq!(
+            block_at_time(&client, 1_655_000_000, USDC_WETH_005_FIRST_BLOCK).unwrap(),
+            USDC_WETH_005_FIRST_BLOCK
+        );
+        assert_eq!(
+            block_at_time(&client, 1_655_000_013, USDC_WETH_005_FIRST_BLOCK).unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 2
+        );
+        assert_eq!(
+            block_at_time(&client, 1_655_000_132, USDC_WETH_005_FIRST_BLOCK).unwrap(),
+            USDC_WETH_005_FIRST_BLOCK + 11
+        );
+        assert!(block_at_time(&client, 1_655_000_133, USDC_WETH_005_FIRST_BLOCK).is_err());
+    }
+}
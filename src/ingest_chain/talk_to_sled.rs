@@ -1,9 +1,8 @@
 use super::blocks::*;
 use super::db_types::*;
 use crate::ingest_chain::Protocol;
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use ranges::GenericRange;
-use sled;
 use std::collections::HashSet;
 
 // FIXME reorg this file you degenerate
@@ -14,7 +13,7 @@ fn process_single_event(
     db_out: core::result::Result<(sled::IVec, sled::IVec), sled::Error>,
 ) -> Result<(Timestamp, Event)> {
     let (timestamp_bytes, entry_bytes) = db_out?;
-    let timestamp: Timestamp = bincode::deserialize(&timestamp_bytes)?;
+    let timestamp = Timestamp::from_key(&timestamp_bytes)?;
     let event: Event = bincode::deserialize(&entry_bytes)?;
     Ok((timestamp, event))
 }
@@ -25,13 +24,19 @@ fn process_single_event(
 /// these store timestamped events, meant to be iterated over. they're timestamped by block and then an index of the event inside the block.
 /// this event timestamp is the key in the protocol btree- you iterate over them in order, obviously.
 /// and the data inside (the event) contains a descriptor of which protocol the event pertains to, as well as the event data for you to do what you want with.
+/// last, the chain tree has the hash and parent hash of every block we stored events for, keyed by big endian block number,
+/// so a reorg that swaps blocks out from under us can be noticed and rolled back.
 const HEADERS_TREE_KEY: &[u8] = b"HEADERS_TREE";
 const DATA_TREE_KEY: &[u8] = b"DATA_TREE";
+const CHAIN_TREE_KEY: &[u8] = b"CHAIN_TREE";
+/// in the default tree: the last block that's buried deep enough to never change. big endian
+const FINALIZED_KEY: &[u8] = b"FINALIZED";
 
-struct SledHandle {
+pub struct SledHandle {
     db: sled::Db,
     header_tree: sled::Tree,
     data_tree: sled::Tree,
+    chain_tree: sled::Tree,
 }
 
 // this can fail. what should i do about that?
@@ -52,19 +57,29 @@ fn range_merge(_key: &[u8], old_range: Option<&[u8]>, new_range: &[u8]) -> Optio
 
 impl SledHandle {
     /// opens/creates the database at the requested sled path. ensures all requested protocols are in the headers table.
-    fn new(sled_path: &str) -> Result<Self> {
-        let db = sled::open(sled_path)?;
+    pub fn new(sled_path: &str) -> Result<Self> {
+        Self::from_db(sled::open(sled_path)?)
+    }
+
+    /// a database that's gone when it's dropped
+    #[cfg(test)]
+    pub fn temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self> {
         let handle = SledHandle {
-            db,
             header_tree: db.open_tree(HEADERS_TREE_KEY)?,
             data_tree: db.open_tree(DATA_TREE_KEY)?,
+            chain_tree: db.open_tree(CHAIN_TREE_KEY)?,
+            db,
         };
         // this should set header tree merge to be the rangemap merge
         handle.header_tree.set_merge_operator(range_merge);
         Ok(handle)
     }
 
-    fn add_time_range(
+    pub fn add_time_range(
         &self,
         protocols_covered: Vec<Protocol>,
         block_range: &Blocks,
@@ -74,7 +89,7 @@ impl SledHandle {
         // FIXME make sure this short-circuits if needed.
         let mut batch = sled::Batch::default();
         for (ts, event) in events.iter() {
-            batch.insert(bincode::serialize(&ts)?, bincode::serialize(&event)?);
+            batch.insert(&ts.to_key(), bincode::serialize(&event)?);
         }
         self.data_tree.apply_batch(batch)?;
         for protocol in protocols_covered.iter() {
@@ -86,38 +101,127 @@ impl SledHandle {
         Ok(())
     }
 
-    fn check_time_range(&self, protocol: Protocol, block_range: &Blocks) -> Result<bool> {
-        match self.data_tree.get(bincode::serialize(&protocol)?)? {
-            None => Ok(false),
-            Some(ranges_bytes) => {
-                Ok(*block_range - Blocks::try_from(&ranges_bytes[..])? == Blocks::empty())
-            }
+    pub fn check_time_range(&self, protocol: Protocol, block_range: &Blocks) -> Result<bool> {
+        Ok((block_range.clone() - self.coverage(protocol)?).is_empty())
+    }
+
+    /// all the blocks a protocol's events are on disk for
+    pub fn coverage(&self, protocol: Protocol) -> Result<Blocks> {
+        match self.header_tree.get(bincode::serialize(&protocol)?)? {
+            None => Ok(Blocks::empty()),
+            Some(ranges_bytes) => Blocks::try_from(&ranges_bytes[..]),
+        }
+    }
+
+    /// writes down the hashes of blocks we're storing events for
+    pub fn add_blocks(&self, blocks: &[(BlockNumber, BlockRecord)]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (number, record) in blocks.iter() {
+            batch.insert(&number.to_be_bytes(), bincode::serialize(record)?);
+        }
+        self.chain_tree.apply_batch(batch)?;
+        Ok(())
+    }
+
+    pub fn block(&self, number: BlockNumber) -> Result<Option<BlockRecord>> {
+        match self.chain_tree.get(number.to_be_bytes())? {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
         }
     }
 
-    // FIXME: this is horrendous and clones absolutely everywhere. fix it.
+    /// every block we have a hash for, newest first
+    pub fn blocks_from_newest(
+        &self,
+    ) -> impl Iterator<Item = Result<(BlockNumber, BlockRecord)>> + '_ {
+        self.chain_tree.iter().rev().map(|entry| {
+            let (key, value) = entry?;
+            let number = BlockNumber::from_be_bytes(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("bad chain tree key {:?}", key))?,
+            );
+            Ok((number, bincode::deserialize(&value)?))
+        })
+    }
+
+    /// the last block that can't be reorged away anymore, if any
+    pub fn finalized(&self) -> Result<Option<BlockNumber>> {
+        match self.db.get(FINALIZED_KEY)? {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(BlockNumber::from_be_bytes(
+                bytes
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("bad finalized block {:?}", bytes))?,
+            ))),
+        }
+    }
+
+    /// marks everything up to `number` final. finality only ever moves forward
+    pub fn finalize(&self, number: BlockNumber) -> Result<()> {
+        if self.finalized()?.is_none_or(|finalized| finalized < number) {
+            self.db.insert(FINALIZED_KEY, &number.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// forgets every event, bit of coverage and block hash from block `from` on, i.e. everything past a fork.
+    /// refuses to touch final blocks
+    pub fn roll_back(&self, from: BlockNumber) -> Result<()> {
+        if let Some(finalized) = self.finalized()? {
+            ensure!(
+                from > finalized,
+                "can't roll back to {}, everything up to {} is final",
+                from,
+                finalized
+            );
+        }
+        // coverage goes first, so if this dies halfway whatever's left just looks missing and gets fetched again
+        let orphaned = Blocks::between(from, BlockNumber::MAX);
+        for entry in self.header_tree.iter() {
+            let (protocol, ranges_bytes) = entry?;
+            let kept = Blocks::try_from(&ranges_bytes[..])? - orphaned.clone();
+            let block_bytes: Vec<u8> = (&kept).try_into()?;
+            self.header_tree.insert(protocol, block_bytes)?;
+        }
+
+        let mut batch = sled::Batch::default();
+        for entry in self.data_tree.range(Timestamp::new(from, 0).to_key()..) {
+            batch.remove(entry?.0);
+        }
+        self.data_tree.apply_batch(batch)?;
+
+        let mut batch = sled::Batch::default();
+        for entry in self.chain_tree.range(from.to_be_bytes()..) {
+            batch.remove(entry?.0);
+        }
+        self.chain_tree.apply_batch(batch)?;
+        Ok(())
+    }
+
     fn get_contiguous_time_range(
         &self,
-        protocol_filter: HashSet<Protocol>,
+        protocol_filter: &HashSet<Protocol>,
         block_range: GenericRange<BlockNumber>,
     ) -> Result<Vec<(Timestamp, Event)>> {
-        Ok(block_range
-            .into_iter()
-            .map(|block| {
-                self.data_tree
-                    .scan_prefix(block.to_be_bytes())
-                    .map(|x| process_single_event(x))
-                    .collect::<Result<Vec<(Timestamp, Event)>>>()
-            })
-            .collect::<Result<Vec<Vec<(Timestamp, Event)>>>>()?
-            .concat())
+        let (first, last) = first_and_last(&block_range);
+        let mut events = Vec::new();
+        for entry in self
+            .data_tree
+            .range(Timestamp::new(first, 0).to_key()..=Timestamp::new(last, u64::MAX).to_key())
+        {
+            let (timestamp, event) = process_single_event(entry)?;
+            if protocol_filter.contains(&event.protocol()) {
+                events.push((timestamp, event));
+            }
+        }
+        Ok(events)
     }
 
     /// outer Result is for general errors while doing the thing.
     /// inner option is for "ya dun goofed, events weren't ingested for this time range first"
     // FIXME: this is horrendous and clones absolutely everywhere. fix it. you are SHAMELESS
-
-    // FIXME this doesnt filter protocols
     pub fn get_time_range(
         &self,
         protocol_filter: HashSet<Protocol>,
@@ -133,8 +237,8 @@ impl SledHandle {
         Ok(Some(
             block_ranges
                 .as_ref()
-                .into_iter()
-                .map(|block_range| self.get_contiguous_time_range(protocol_filter, *block_range))
+                .iter()
+                .map(|block_range| self.get_contiguous_time_range(&protocol_filter, *block_range))
                 .collect::<Result<Vec<Vec<(Timestamp, Event)>>>>()?
                 .concat(),
         ))
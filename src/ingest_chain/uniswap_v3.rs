@@ -0,0 +1,439 @@
+// the events a UniswapV3Pool emits, decoded into the same types the simulator uses so they can be replayed against it.
+// see UniswapV3Pool.sol / IUniswapV3PoolEvents.sol for where these come from.
+
+use super::abi::{self, event_topic};
+use super::rpc::{Log, LogFilter};
+use crate::solidints::{I256::I256, U160::U160, U256};
+use crate::unisim::tick::Tick;
+use crate::unisim::Address;
+use anyhow::Result;
+use primitive_types::H256;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    pub static ref INITIALIZE_TOPIC: H256 = event_topic("Initialize(uint160,int24)");
+    pub static ref MINT_TOPIC: H256 =
+        event_topic("Mint(address,address,int24,int24,uint128,uint256,uint256)");
+    pub static ref COLLECT_TOPIC: H256 =
+        event_topic("Collect(address,address,int24,int24,uint128,uint128)");
+    pub static ref BURN_TOPIC: H256 =
+        event_topic("Burn(address,int24,int24,uint128,uint256,uint256)");
+    pub static ref SWAP_TOPIC: H256 =
+        event_topic("Swap(address,address,int256,int256,uint160,uint128,int24)");
+    pub static ref FLASH_TOPIC: H256 =
+        event_topic("Flash(address,address,uint256,uint256,uint256,uint256)");
+    pub static ref INCREASE_OBSERVATION_CARDINALITY_NEXT_TOPIC: H256 =
+        event_topic("IncreaseObservationCardinalityNext(uint16,uint16)");
+    pub static ref SET_FEE_PROTOCOL_TOPIC: H256 =
+        event_topic("SetFeeProtocol(uint8,uint8,uint8,uint8)");
+    pub static ref COLLECT_PROTOCOL_TOPIC: H256 =
+        event_topic("CollectProtocol(address,address,uint128,uint128)");
+}
+
+/// Emitted exactly once by a pool when #initialize is first called on the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Initialize {
+    /// The initial sqrt price of the pool, as a Q64.96
+    pub sqrt_price_x96: U160,
+    /// The initial tick of the pool, i.e. log base 1.0001 of the starting price of the pool
+    pub tick: Tick,
+}
+
+/// Emitted when liquidity is minted for a given position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mint {
+    /// The address that minted the liquidity
+    pub sender: Address,
+    /// The owner of the position and recipient of any minted liquidity
+    pub owner: Address,
+    pub tick_lower: Tick,
+    pub tick_upper: Tick,
+    /// The amount of liquidity minted to the position range
+    pub amount: u128,
+    /// How much token0 was required for the minted liquidity
+    pub amount_0: U256,
+    /// How much token1 was required for the minted liquidity
+    pub amount_1: U256,
+}
+
+/// Emitted when fees are collected by the owner of a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collect {
+    pub owner: Address,
+    pub recipient: Address,
+    pub tick_lower: Tick,
+    pub tick_upper: Tick,
+    pub amount_0: u128,
+    pub amount_1: u128,
+}
+
+/// Emitted when a position's liquidity is removed. Does not withdraw any fees earned, that's `Collect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Burn {
+    pub owner: Address,
+    pub tick_lower: Tick,
+    pub tick_upper: Tick,
+    pub amount: u128,
+    pub amount_0: U256,
+    pub amount_1: U256,
+}
+
+/// Emitted by the pool for any swaps between token0 and token1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Swap {
+    /// The address that initiated the swap call, and that received the callback
+    pub sender: Address,
+    pub recipient: Address,
+    /// The delta of the token0 balance of the pool
+    pub amount_0: I256,
+    /// The delta of the token1 balance of the pool
+    pub amount_1: I256,
+    /// The sqrt(price) of the pool after the swap, as a Q64.96
+    pub sqrt_price_x96: U160,
+    /// The liquidity of the pool after the swap
+    pub liquidity: u128,
+    /// The log base 1.0001 of price of the pool after the swap
+    pub tick: Tick,
+}
+
+/// Emitted by the pool for any flashes of token0/token1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flash {
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount_0: U256,
+    pub amount_1: U256,
+    /// The amount of token0 paid for the flash, which can exceed the amount0 plus the fee
+    pub paid_0: U256,
+    pub paid_1: U256,
+}
+
+/// Emitted by the pool when the number of observations it can store goes up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncreaseObservationCardinalityNext {
+    pub observation_cardinality_next_old: u16,
+    pub observation_cardinality_next_new: u16,
+}
+
+/// Emitted when the protocol fee is changed by the factory owner. Each is the denominator of the
+/// protocol's share of the swap fee, 0 meaning no protocol fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetFeeProtocol {
+    pub fee_protocol_0_old: u8,
+    pub fee_protocol_1_old: u8,
+    pub fee_protocol_0_new: u8,
+    pub fee_protocol_1_new: u8,
+}
+
+/// Emitted when the collected protocol fees are withdrawn by the factory owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectProtocol {
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount_0: u128,
+    pub amount_1: u128,
+}
+
+/// A pool event, and the pool that emitted it, so it can be replayed against the right `UniV3Pool`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolEvent {
+    pub pool: Address,
+    pub event: UniswapV3Event,
+}
+
+impl PoolEvent {
+    pub fn from_log(log: &Log) -> Result<Option<Self>> {
+        Ok(
+            UniswapV3Event::decode(&log.topics, &log.data)?.map(|event| PoolEvent {
+                pool: log.address,
+                event,
+            }),
+        )
+    }
+}
+
+/// Any event a UniswapV3Pool emits that we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UniswapV3Event {
+    Initialize(Initialize),
+    Mint(Mint),
+    Collect(Collect),
+    Burn(Burn),
+    Swap(Swap),
+    Flash(Flash),
+    IncreaseObservationCardinalityNext(IncreaseObservationCardinalityNext),
+    SetFeeProtocol(SetFeeProtocol),
+    CollectProtocol(CollectProtocol),
+}
+
+impl UniswapV3Event {
+    /// Decodes a pool log from its topics and data
+    ///
+    /// returns None for logs that aren't pool events, and errors if the log doesn't fit its event
+    pub fn decode(topics: &[H256], data: &[u8]) -> Result<Option<Self>> {
+        use abi::{address, int24, int256, topic, uint128, uint16, uint160, uint256, uint8, word};
+
+        let signature = match topics.first() {
+            None => return Ok(None),
+            Some(signature) => signature,
+        };
+        let event = if *signature == *INITIALIZE_TOPIC {
+            UniswapV3Event::Initialize(Initialize {
+                sqrt_price_x96: uint160(word(data, 0)?)?,
+                tick: int24(word(data, 1)?)?,
+            })
+        } else if *signature == *MINT_TOPIC {
+            UniswapV3Event::Mint(Mint {
+                sender: address(word(data, 0)?)?,
+                owner: address(topic(topics, 1)?)?,
+                tick_lower: int24(topic(topics, 2)?)?,
+                tick_upper: int24(topic(topics, 3)?)?,
+                amount: uint128(word(data, 1)?)?,
+                amount_0: uint256(word(data, 2)?),
+                amount_1: uint256(word(data, 3)?),
+            })
+        } else if *signature == *COLLECT_TOPIC {
+            UniswapV3Event::Collect(Collect {
+                owner: address(topic(topics, 1)?)?,
+                recipient: address(word(data, 0)?)?,
+                tick_lower: int24(topic(topics, 2)?)?,
+                tick_upper: int24(topic(topics, 3)?)?,
+                amount_0: uint128(word(data, 1)?)?,
+                amount_1: uint128(word(data, 2)?)?,
+            })
+        } else if *signature == *BURN_TOPIC {
+            UniswapV3Event::Burn(Burn {
+                owner: address(topic(topics, 1)?)?,
+                tick_lower: int24(topic(topics, 2)?)?,
+                tick_upper: int24(topic(topics, 3)?)?,
+                amount: uint128(word(data, 0)?)?,
+                amount_0: uint256(word(data, 1)?),
+                amount_1: uint256(word(data, 2)?),
+            })
+        } else if *signature == *SWAP_TOPIC {
+            UniswapV3Event::Swap(Swap {
+                sender: address(topic(topics, 1)?)?,
+                recipient: address(topic(topics, 2)?)?,
+                amount_0: int256(word(data, 0)?),
+                amount_1: int256(word(data, 1)?),
+                sqrt_price_x96: uint160(word(data, 2)?)?,
+                liquidity: uint128(word(data, 3)?)?,
+                tick: int24(word(data, 4)?)?,
+            })
+        } else if *signature == *FLASH_TOPIC {
+            UniswapV3Event::Flash(Flash {
+                sender: address(topic(topics, 1)?)?,
+                recipient: address(topic(topics, 2)?)?,
+                amount_0: uint256(word(data, 0)?),
+                amount_1: uint256(word(data, 1)?),
+                paid_0: uint256(word(data, 2)?),
+                paid_1: uint256(word(data, 3)?),
+            })
+        } else if *signature == *INCREASE_OBSERVATION_CARDINALITY_NEXT_TOPIC {
+            UniswapV3Event::IncreaseObservationCardinalityNext(IncreaseObservationCardinalityNext {
+                observation_cardinality_next_old: uint16(word(data, 0)?)?,
+                observation_cardinality_next_new: uint16(word(data, 1)?)?,
+            })
+        } else if *signature == *SET_FEE_PROTOCOL_TOPIC {
+            UniswapV3Event::SetFeeProtocol(SetFeeProtocol {
+                fee_protocol_0_old: uint8(word(data, 0)?)?,
+                fee_protocol_1_old: uint8(word(data, 1)?)?,
+                fee_protocol_0_new: uint8(word(data, 2)?)?,
+                fee_protocol_1_new: uint8(word(data, 3)?)?,
+            })
+        } else if *signature == *COLLECT_PROTOCOL_TOPIC {
+            UniswapV3Event::CollectProtocol(CollectProtocol {
+                sender: address(topic(topics, 1)?)?,
+                recipient: address(topic(topics, 2)?)?,
+                amount_0: uint128(word(data, 0)?)?,
+                amount_1: uint128(word(data, 1)?)?,
+            })
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(event))
+    }
+
+    /// A filter for every event above, from any of `pools`
+    pub fn log_filter(pools: Vec<Address>) -> LogFilter {
+        LogFilter {
+            addresses: pools,
+            topics: vec![Some(vec![
+                *INITIALIZE_TOPIC,
+                *MINT_TOPIC,
+                *COLLECT_TOPIC,
+                *BURN_TOPIC,
+                *SWAP_TOPIC,
+                *FLASH_TOPIC,
+                *INCREASE_OBSERVATION_CARDINALITY_NEXT_TOPIC,
+                *SET_FEE_PROTOCOL_TOPIC,
+                *COLLECT_PROTOCOL_TOPIC,
+            ])],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock_node::{MockNode, USDC_WETH_005_FIRST_BLOCK};
+    use super::super::rpc;
+    use super::*;
+
+    const POOL: &str = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+
+    fn logs() -> Vec<Log> {
+        let client = MockNode::client("usdc_weth_005", 1);
+        client
+            .get_logs(
+                &UniswapV3Event::log_filter(vec![rpc::address(POOL).unwrap()]),
+                USDC_WETH_005_FIRST_BLOCK,
+                USDC_WETH_005_FIRST_BLOCK + 11,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn topics_are_the_ones_pools_emit() {
+        assert_eq!(
+            *SWAP_TOPIC,
+            rpc::hash("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")
+                .unwrap()
+        );
+        assert_eq!(
+            *MINT_TOPIC,
+            rpc::hash("0x7a53080ba414158be7ec69b987b5fb7d07dee101fe85488f0853ae16239d0bde")
+                .unwrap()
+        );
+        assert_eq!(
+            *SET_FEE_PROTOCOL_TOPIC,
+            rpc::hash("0x973d8d92bb299f4af6ce49b52a8adb85ae46b9f214c4c4fc06ac77401237b133")
+                .unwrap()
+        );
+        assert_eq!(
+            *COLLECT_PROTOCOL_TOPIC,
+            rpc::hash("0x596b573906218d3411850b26a6b437d6c4522fdb43d2d2386263f86d50b8b151")
+                .unwrap()
+        );
+        assert_eq!(
+            *INCREASE_OBSERVATION_CARDINALITY_NEXT_TOPIC,
+            rpc::hash("0xac49e518f90a358f652e4400164f05a5d8f7e35e7747279bc3a93dbf584e125a")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn decodes_pool_logs() {
+        let events = logs()
+            .iter()
+            .map(|log| PoolEvent::from_log(log).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 8);
+        let pool = rpc::address(POOL).unwrap();
+        assert!(events.iter().all(|event| event.pool == pool));
+
+        let router = rpc::address("0xe592427a0aece92de3edee1f18e0157c05861564").unwrap();
+        match events[0].event {
+            UniswapV3Event::Swap(swap) => {
+                assert_eq!(swap.sender, router);
+                assert_eq!(swap.amount_0, I256::from(-2_000_000_000i64));
+                assert_eq!(swap.amount_1, I256::from(1_000_000_000_000_000_000u64));
+                assert_eq!(swap.liquidity, 21_000_000_000_000_000_000);
+                assert_eq!(swap.tick, 201000);
+            }
+            other => panic!("expected a swap, got {:?}", other),
+        }
+        match events[3].event {
+            UniswapV3Event::Mint(mint) => {
+                assert_eq!(mint.owner, mint.sender);
+                assert_eq!((mint.tick_lower, mint.tick_upper), (200940, 201060));
+                assert_eq!(mint.amount, 1_000_000_000_000_000);
+                assert_eq!(mint.amount_0, U256::from(3_000_000));
+            }
+            other => panic!("expected a mint, got {:?}", other),
+        }
+
+        // and they survive the trip through sled
+        let bytes = bincode::serialize(&events).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Vec<PoolEvent>>(&bytes).unwrap(),
+            events
+        );
+    }
+
+    // a log's data, one word per value
+    fn data_of(values: &[u128]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| {
+                let mut word = [0u8; 32];
+                U256::from(*value).to_big_endian(&mut word);
+                word
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_observation_cardinality_increases() {
+        let topics = [*INCREASE_OBSERVATION_CARDINALITY_NEXT_TOPIC];
+        assert_eq!(
+            UniswapV3Event::decode(&topics, &data_of(&[1, 500])).unwrap(),
+            Some(UniswapV3Event::IncreaseObservationCardinalityNext(
+                IncreaseObservationCardinalityNext {
+                    observation_cardinality_next_old: 1,
+                    observation_cardinality_next_new: 500,
+                }
+            ))
+        );
+        assert!(UniswapV3Event::decode(&topics, &data_of(&[1, 65536])).is_err());
+    }
+
+    #[test]
+    fn decodes_fee_protocol_changes() {
+        let topics = [*SET_FEE_PROTOCOL_TOPIC];
+        assert_eq!(
+            UniswapV3Event::decode(&topics, &data_of(&[0, 0, 4, 10])).unwrap(),
+            Some(UniswapV3Event::SetFeeProtocol(SetFeeProtocol {
+                fee_protocol_0_old: 0,
+                fee_protocol_1_old: 0,
+                fee_protocol_0_new: 4,
+                fee_protocol_1_new: 10,
+            }))
+        );
+        assert!(UniswapV3Event::decode(&topics, &data_of(&[0, 0, 4])).is_err());
+    }
+
+    #[test]
+    fn decodes_protocol_fee_collections() {
+        let (owner, treasury) = (Address::from(0x0eeu32), Address::from(0x7ea5u32));
+        let topic_of = |address: Address| {
+            let mut word = [0u8; 32];
+            U256::from(address).to_big_endian(&mut word);
+            H256(word)
+        };
+        let topics = [*COLLECT_PROTOCOL_TOPIC, topic_of(owner), topic_of(treasury)];
+        assert_eq!(
+            UniswapV3Event::decode(&topics, &data_of(&[3_000_000, u128::MAX])).unwrap(),
+            Some(UniswapV3Event::CollectProtocol(CollectProtocol {
+                sender: owner,
+                recipient: treasury,
+                amount_0: 3_000_000,
+                amount_1: u128::MAX,
+            }))
+        );
+        // the recipient is indexed, so it has to be there
+        assert!(UniswapV3Event::decode(&topics[..2], &data_of(&[0, 0])).is_err());
+    }
+
+    #[test]
+    fn rejects_logs_that_dont_fit() {
+        let log = &logs()[0];
+        assert!(UniswapV3Event::decode(&log.topics, &log.data[..4 * 32]).is_err());
+        let mut bad_tick = log.data.clone();
+        bad_tick[4 * 32] = 1;
+        assert!(UniswapV3Event::decode(&log.topics, &bad_tick).is_err());
+        assert_eq!(
+            UniswapV3Event::decode(&[H256::zero()], &log.data).unwrap(),
+            None
+        );
+    }
+}
@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate lazy_static;
+pub mod ingest_chain;
 pub mod solidints;
 pub mod solidmath;
 pub mod unisim;
@@ -1,11 +1,3 @@
-#[macro_use]
-extern crate lazy_static;
-
-mod ingest_chain;
-mod solidints;
-mod solidmath;
-mod unisim;
-
 fn main() {
     println!("Hello, world!");
 }
@@ -1,12 +1,22 @@
 use super::U256;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering};
-use std::ops::{Add, Neg, Sub};
+use std::fmt;
+use std::ops::{
+    Add, AddAssign, BitAnd, BitOr, BitXor, Div, DivAssign, Mul, MulAssign, Neg, Not, Rem, Shl, Shr,
+    Sub, SubAssign,
+};
+use std::str::FromStr;
 
 #[cfg(feature = "arbitrary")]
 use arbitrary;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Signed 256-bit integer with the semantics of Solidity's `int256`.
+/// The value is kept as its two's complement bit pattern inside a U256.
+/// The operators revert (panic) on overflow like Solidity 0.8 checked arithmetic,
+/// the `wrapping_*` methods behave like 0.7 / `unchecked` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct I256(U256);
 
 lazy_static! {
@@ -21,52 +31,262 @@ impl PartialOrd for I256 {
 
 impl Ord for I256 {
     fn cmp(&self, other: &Self) -> Ordering {
-        let I256(self_as_u256) = self;
-        let I256(other_as_u256) = other;
+        match (self.is_negative(), other.is_negative()) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            // same sign: two's complement orders exactly like the raw bits
+            _ => self.0.cmp(&other.0),
+        }
+    }
+}
+
+impl I256 {
+    /// type(int256).min, i.e. -2^255
+    pub const MIN: Self = I256(U256([0, 0, 0, 1 << 63]));
+    /// type(int256).max, i.e. 2^255 - 1
+    pub const MAX: Self = I256(U256([u64::MAX, u64::MAX, u64::MAX, u64::MAX >> 1]));
+
+    pub fn zero() -> Self {
+        I256(U256::zero())
+    }
+    pub fn one() -> Self {
+        I256(U256::one())
+    }
+    pub fn minus_one() -> Self {
+        I256(U256::MAX)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
 
-        let self_positive: bool = (*self_as_u256 & *MASK) == U256::zero();
-        let other_positive: bool = (*other_as_u256 & *MASK) == U256::zero();
+    /// reinterprets a raw two's complement bit pattern (what `int256(x)` does in solidity)
+    pub const fn from_raw(raw: U256) -> Self {
+        I256(raw)
+    }
+    /// the raw two's complement bit pattern (what `uint256(x)` does in solidity)
+    pub const fn into_raw(self) -> U256 {
+        self.0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.bit(255)
+    }
+    pub fn is_positive(&self) -> bool {
+        !self.is_negative() && !self.is_zero()
+    }
+    /// -1, 0 or 1 depending on the sign
+    pub fn signum(&self) -> Self {
+        if self.is_negative() {
+            Self::minus_one()
+        } else if self.is_zero() {
+            Self::zero()
+        } else {
+            Self::one()
+        }
+    }
+
+    /// absolute value as a U256. never overflows, |MIN| = 2^255 fits.
+    pub fn unsigned_abs(&self) -> U256 {
+        if self.is_negative() {
+            (!self.0).overflowing_add(U256::one()).0
+        } else {
+            self.0
+        }
+    }
+    /// absolute value. panics for MIN.
+    pub fn abs(self) -> Self {
+        self.checked_abs().expect("I256 overflow in abs")
+    }
+    pub fn checked_abs(self) -> Option<Self> {
+        if self.is_negative() {
+            self.checked_neg()
+        } else {
+            Some(self)
+        }
+    }
+    pub fn wrapping_abs(self) -> Self {
+        I256(self.unsigned_abs())
+    }
+
+    pub fn overflowing_neg(self) -> (Self, bool) {
+        (
+            I256((!self.0).overflowing_add(U256::one()).0),
+            self == Self::MIN,
+        )
+    }
+    pub fn checked_neg(self) -> Option<Self> {
+        checked(self.overflowing_neg())
+    }
+    pub fn wrapping_neg(self) -> Self {
+        self.overflowing_neg().0
+    }
+
+    pub fn overflowing_add(self, other: Self) -> (Self, bool) {
+        let result = I256(self.0.overflowing_add(other.0).0);
+        let overflow =
+            self.is_negative() == other.is_negative() && result.is_negative() != self.is_negative();
+        (result, overflow)
+    }
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_add(other))
+    }
+    pub fn wrapping_add(self, other: Self) -> Self {
+        self.overflowing_add(other).0
+    }
+
+    pub fn overflowing_sub(self, other: Self) -> (Self, bool) {
+        let result = I256(self.0.overflowing_sub(other.0).0);
+        let overflow =
+            self.is_negative() != other.is_negative() && result.is_negative() != self.is_negative();
+        (result, overflow)
+    }
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_sub(other))
+    }
+    pub fn wrapping_sub(self, other: Self) -> Self {
+        self.overflowing_sub(other).0
+    }
+
+    pub fn overflowing_mul(self, other: Self) -> (Self, bool) {
+        // the low 256 bits of the product are the same for signed and unsigned operands
+        let result = I256(self.0.overflowing_mul(other.0).0);
+        let (magnitude, magnitude_overflow) =
+            self.unsigned_abs().overflowing_mul(other.unsigned_abs());
+        let negative = self.is_negative() != other.is_negative();
+        let overflow = magnitude_overflow
+            || if negative {
+                magnitude > Self::MIN.0
+            } else {
+                magnitude >= Self::MIN.0
+            };
+        (result, overflow)
+    }
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_mul(other))
+    }
+    pub fn wrapping_mul(self, other: Self) -> Self {
+        self.overflowing_mul(other).0
+    }
+
+    /// division truncating towards zero, like solidity. panics on a zero divisor.
+    /// the only overflowing case is MIN / -1, which wraps back to MIN.
+    pub fn overflowing_div(self, other: Self) -> (Self, bool) {
+        assert!(!other.is_zero(), "I256 division by zero");
+        let quotient = I256(self.unsigned_abs() / other.unsigned_abs());
+        let result = if self.is_negative() != other.is_negative() {
+            quotient.wrapping_neg()
+        } else {
+            quotient
+        };
+        (result, self == Self::MIN && other == Self::minus_one())
+    }
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            None
+        } else {
+            checked(self.overflowing_div(other))
+        }
+    }
+    pub fn wrapping_div(self, other: Self) -> Self {
+        self.overflowing_div(other).0
+    }
 
-        let positive_comparison = match (self_positive, other_positive) {
-            (true, false) => return Ordering::Greater,
-            (false, true) => return Ordering::Less,
-            (a, _) => a,
+    /// remainder with the sign of the dividend, like solidity's `%` (SMOD). panics on a zero divisor.
+    /// SMOD never overflows: MIN % -1 is 0.
+    pub fn overflowing_rem(self, other: Self) -> (Self, bool) {
+        assert!(!other.is_zero(), "I256 remainder by zero");
+        let remainder = I256(self.unsigned_abs() % other.unsigned_abs());
+        let result = if self.is_negative() {
+            remainder.wrapping_neg()
+        } else {
+            remainder
         };
+        (result, false)
+    }
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            None
+        } else {
+            checked(self.overflowing_rem(other))
+        }
+    }
+    pub fn wrapping_rem(self, other: Self) -> Self {
+        self.overflowing_rem(other).0
+    }
+
+    /// shift left, None if the shift is 256 or more
+    pub fn checked_shl(self, shift: usize) -> Option<Self> {
+        if shift < 256 {
+            Some(self << shift)
+        } else {
+            None
+        }
+    }
+    /// arithmetic shift right, None if the shift is 256 or more
+    pub fn checked_shr(self, shift: usize) -> Option<Self> {
+        if shift < 256 {
+            Some(self >> shift)
+        } else {
+            None
+        }
+    }
 
-        let U256(self_u64s) = self_as_u256;
-        let U256(other_u64s) = other_as_u256;
+    /// parses a decimal string with an optional leading sign
+    pub fn from_dec_str(s: &str) -> Result<Self> {
+        Self::parse_with_sign(s, |digits| {
+            U256::from_dec_str(digits).map_err(|e| anyhow!("{:?}", e))
+        })
+    }
+    /// parses a hex string with an optional leading sign and optional 0x prefix.
+    /// the digits are the magnitude, not the two's complement bits: "-0x1" is -1.
+    pub fn from_hex_str(s: &str) -> Result<Self> {
+        Self::parse_with_sign(s, |digits| {
+            let digits = digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+                .unwrap_or(digits);
+            U256::from_str_radix(digits, 16).map_err(|e| anyhow!("{:?}", e))
+        })
+    }
 
-        for i in 3..=0 {
-            if self_u64s[i] < other_u64s[i] {
-                return if positive_comparison {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                };
-            } else if self_u64s[i] > other_u64s[i] {
-                return if positive_comparison {
-                    Ordering::Greater
-                } else {
-                    Ordering::Less
-                };
-            }
+    fn parse_with_sign(s: &str, parse_magnitude: impl Fn(&str) -> Result<U256>) -> Result<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        ensure!(!digits.is_empty(), "empty I256 string");
+        let magnitude = parse_magnitude(digits)?;
+        if negative {
+            ensure!(magnitude <= Self::MIN.0, "I256 underflowed parsing {}", s);
+            Ok(I256(magnitude).wrapping_neg())
+        } else {
+            ensure!(magnitude <= Self::MAX.0, "I256 overflowed parsing {}", s);
+            Ok(I256(magnitude))
         }
+    }
+}
 
-        Ordering::Equal
+fn checked((value, overflow): (I256, bool)) -> Option<I256> {
+    if overflow {
+        None
+    } else {
+        Some(value)
     }
 }
+
 impl Neg for I256 {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        Self(!self.0 + U256::one())
+        self.checked_neg().expect("I256 overflow in negation")
     }
 }
+
 impl Add for I256 {
     type Output = I256;
 
     fn add(self, other: Self) -> Self::Output {
-        Self(self.0 + other.0)
+        self.checked_add(other).expect("I256 overflow in addition")
     }
 }
 
@@ -74,7 +294,175 @@ impl Sub for I256 {
     type Output = I256;
 
     fn sub(self, other: Self) -> Self::Output {
-        Self(self.0 - other.0)
+        self.checked_sub(other)
+            .expect("I256 overflow in subtraction")
+    }
+}
+
+impl Mul for I256 {
+    type Output = I256;
+
+    fn mul(self, other: Self) -> Self::Output {
+        self.checked_mul(other)
+            .expect("I256 overflow in multiplication")
+    }
+}
+
+impl Div for I256 {
+    type Output = I256;
+
+    fn div(self, other: Self) -> Self::Output {
+        self.checked_div(other)
+            .expect("I256 overflow or division by zero")
+    }
+}
+
+impl Rem for I256 {
+    type Output = I256;
+
+    fn rem(self, other: Self) -> Self::Output {
+        self.checked_rem(other).expect("I256 remainder by zero")
+    }
+}
+
+impl AddAssign for I256 {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for I256 {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for I256 {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign for I256 {
+    fn div_assign(&mut self, other: Self) {
+        *self = *self / other;
+    }
+}
+
+/// SHL: bits shifted past the top are lost, shifts of 256 or more give 0
+impl Shl<usize> for I256 {
+    type Output = I256;
+
+    fn shl(self, shift: usize) -> Self::Output {
+        if shift >= 256 {
+            Self::zero()
+        } else {
+            I256(self.0 << shift)
+        }
+    }
+}
+
+/// SAR: arithmetic shift, rounds towards negative infinity
+impl Shr<usize> for I256 {
+    type Output = I256;
+
+    fn shr(self, shift: usize) -> Self::Output {
+        match (self.is_negative(), shift >= 256) {
+            (false, true) => Self::zero(),
+            (true, true) => Self::minus_one(),
+            (false, false) => I256(self.0 >> shift),
+            (true, false) => I256(!(!self.0 >> shift)),
+        }
+    }
+}
+
+impl Not for I256 {
+    type Output = I256;
+
+    fn not(self) -> Self::Output {
+        I256(!self.0)
+    }
+}
+
+impl BitAnd for I256 {
+    type Output = I256;
+
+    fn bitand(self, other: Self) -> Self::Output {
+        I256(self.0 & other.0)
+    }
+}
+
+impl BitOr for I256 {
+    type Output = I256;
+
+    fn bitor(self, other: Self) -> Self::Output {
+        I256(self.0 | other.0)
+    }
+}
+
+impl BitXor for I256 {
+    type Output = I256;
+
+    fn bitxor(self, other: Self) -> Self::Output {
+        I256(self.0 ^ other.0)
+    }
+}
+
+impl From<i128> for I256 {
+    fn from(value: i128) -> I256 {
+        let magnitude = I256(U256::from(value.unsigned_abs()));
+        if value < 0 {
+            magnitude.wrapping_neg()
+        } else {
+            magnitude
+        }
+    }
+}
+
+impl From<i64> for I256 {
+    fn from(value: i64) -> I256 {
+        I256::from(value as i128)
+    }
+}
+
+impl From<i32> for I256 {
+    fn from(value: i32) -> I256 {
+        I256::from(value as i128)
+    }
+}
+
+impl From<u128> for I256 {
+    fn from(value: u128) -> I256 {
+        I256(U256::from(value))
+    }
+}
+
+impl From<u64> for I256 {
+    fn from(value: u64) -> I256 {
+        I256(U256::from(value))
+    }
+}
+
+impl TryFrom<I256> for i128 {
+    type Error = anyhow::Error;
+    fn try_from(i256: I256) -> Result<i128> {
+        ensure!(
+            i256 >= I256::from(i128::MIN) && i256 <= I256::from(i128::MAX),
+            "I256 out of range for i128"
+        );
+        let magnitude = i256.unsigned_abs().low_u128();
+        Ok(if i256.is_negative() {
+            (magnitude as i128).wrapping_neg()
+        } else {
+            magnitude as i128
+        })
+    }
+}
+
+impl TryFrom<I256> for i64 {
+    type Error = anyhow::Error;
+    fn try_from(i256: I256) -> Result<i64> {
+        Ok(i64::try_from(i128::try_from(i256)?)?)
     }
 }
 
@@ -101,6 +489,36 @@ impl TryFrom<I256> for U256 {
     }
 }
 
+impl fmt::Display for I256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            write!(f, "-{}", self.unsigned_abs())
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// hex formatting shows the two's complement bits, like the std integer types
+impl fmt::LowerHex for I256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// accepts decimal, or hex with a 0x prefix, both with an optional sign
+impl FromStr for I256 {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<I256> {
+        let unsigned = s.trim_start_matches(['-', '+']);
+        if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+            I256::from_hex_str(s)
+        } else {
+            I256::from_dec_str(s)
+        }
+    }
+}
+
 #[cfg(feature = "arbitrary")]
 impl arbitrary::Arbitrary<'_> for I256 {
     fn arbitrary(u: &mut arbitrary::Unstructured) -> arbitrary::Result<Self> {
@@ -108,20 +526,112 @@ impl arbitrary::Arbitrary<'_> for I256 {
     }
 }
 
-impl I256 {
-    pub fn zero() -> Self {
-        I256(U256::zero())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i(x: i128) -> I256 {
+        I256::from(x)
     }
-    pub fn is_zero(&self) -> bool {
-        *self == I256(U256::zero())
+
+    #[test]
+    fn ordering() {
+        assert!(i(-1) < i(0));
+        assert!(i(-2) < i(-1));
+        assert!(i(1) < i(2));
+        assert!(I256::MIN < i(i128::MIN));
+        assert!(I256::MAX > i(i128::MAX));
+        assert!(I256::MIN < I256::MAX);
+        let big = I256::from_raw(U256::one() << 200);
+        assert!(big > i(1));
+        assert!(-big < i(-1));
+    }
+
+    #[test]
+    fn add_sub_overflow() {
+        assert_eq!(i(5) + i(-7), i(-2));
+        assert_eq!(i(-5) - i(-7), i(2));
+        assert_eq!(I256::MAX.checked_add(i(1)), None);
+        assert_eq!(I256::MAX.wrapping_add(i(1)), I256::MIN);
+        assert_eq!(I256::MIN.checked_sub(i(1)), None);
+        assert_eq!(I256::MIN.overflowing_sub(i(1)), (I256::MAX, true));
+        assert_eq!(I256::MIN.checked_add(i(-1)), None);
+        assert_eq!(I256::MIN.checked_add(I256::MAX), Some(i(-1)));
+    }
+
+    #[test]
+    fn mul_div_rem() {
+        assert_eq!(i(-3) * i(7), i(-21));
+        assert_eq!(i(-3) * i(-7), i(21));
+        assert_eq!(I256::MIN.checked_mul(i(1)), Some(I256::MIN));
+        assert_eq!(I256::MIN.checked_mul(i(-1)), None);
+        assert_eq!(I256::MAX.checked_mul(i(2)), None);
+        let half_min = I256::MIN / i(2);
+        assert_eq!(half_min.checked_mul(i(2)), Some(I256::MIN));
+        assert_eq!(half_min.checked_mul(i(-2)), None);
+
+        assert_eq!(i(-7) / i(2), i(-3));
+        assert_eq!(i(7) / i(-2), i(-3));
+        assert_eq!(i(-7) % i(2), i(-1));
+        assert_eq!(i(7) % i(-2), i(1));
+        assert_eq!(I256::MIN.checked_div(i(-1)), None);
+        assert_eq!(I256::MIN.wrapping_div(i(-1)), I256::MIN);
+        assert_eq!(I256::MIN % i(-1), i(0));
+        assert_eq!(i(1).checked_div(i(0)), None);
+        assert_eq!(i(1).checked_rem(i(0)), None);
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(i(-1) >> 10, i(-1));
+        assert_eq!(i(-5) >> 1, i(-3));
+        assert_eq!(i(5) >> 1, i(2));
+        assert_eq!(i(-5) >> 300, i(-1));
+        assert_eq!(i(1) << 255, I256::MIN);
+        assert_eq!(i(1) << 256, i(0));
+        assert_eq!(i(1).checked_shl(256), None);
+    }
+
+    #[test]
+    fn sign_helpers() {
+        assert_eq!(i(-9).signum(), i(-1));
+        assert_eq!(i(0).signum(), i(0));
+        assert_eq!(i(9).signum(), i(1));
+        assert_eq!(i(-9).abs(), i(9));
+        assert_eq!(I256::MIN.checked_abs(), None);
+        assert_eq!(I256::MIN.unsigned_abs(), U256::one() << 255);
+    }
+
+    #[test]
+    fn conversions() {
+        for x in [0, 1, -1, i128::MAX, i128::MIN, 1 << 100, -(1 << 100)] {
+            assert_eq!(i128::try_from(i(x)).unwrap(), x);
+        }
+        assert!(i128::try_from(I256::MAX).is_err());
+        assert!(i64::try_from(i(i64::MIN as i128 - 1)).is_err());
+        assert_eq!(i64::try_from(i(-42)).unwrap(), -42);
+        assert!(U256::try_from(i(-1)).is_err());
+        assert!(I256::try_from(U256::MAX).is_err());
+        assert_eq!(I256::from_raw(U256::MAX), i(-1));
     }
-    pub const MAX: Self = todo!();
-}
 
-#[cfg(test)]
-mod tests {
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn strings() {
+        let max = "57896044618658097711785492504343953926634992332820282019728792003956564819967";
+        let min = "-57896044618658097711785492504343953926634992332820282019728792003956564819968";
+        assert_eq!(I256::MAX.to_string(), max);
+        assert_eq!(I256::MIN.to_string(), min);
+        assert_eq!(max.parse::<I256>().unwrap(), I256::MAX);
+        assert_eq!(min.parse::<I256>().unwrap(), I256::MIN);
+        assert!(
+            "57896044618658097711785492504343953926634992332820282019728792003956564819968"
+                .parse::<I256>()
+                .is_err()
+        );
+        assert_eq!("-0x10".parse::<I256>().unwrap(), i(-16));
+        assert_eq!("+12".parse::<I256>().unwrap(), i(12));
+        assert_eq!(format!("{:x}", i(-1)), "f".repeat(64));
+        assert!("-".parse::<I256>().is_err());
+        assert!("12a".parse::<I256>().is_err());
     }
 }
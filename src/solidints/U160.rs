@@ -1,11 +1,17 @@
 use super::U256;
-use core::ops::Mul;
+use anyhow::{anyhow, ensure, Result};
 /// Implementations of integer types not available through the primitive_types crate
-use core::ops::Sub;
+use core::ops::{
+    Add, AddAssign, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub, SubAssign,
+};
+use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering};
+use std::fmt;
+use std::str::FromStr;
 
-// Unsigned int with 5 x 32-bit words
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+// Unsigned int with 5 x 32-bit words, least significant word first.
+// Arithmetic goes through U256 and gets checked against MAX on the way back, like solidity's uint160.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default, Serialize, Deserialize)]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct U160(pub [u32; 5]);
 
@@ -20,13 +26,164 @@ impl From<u128> for U160 {
     }
 }
 
+impl From<u64> for U160 {
+    fn from(value: u64) -> U160 {
+        U160::from(value as u128)
+    }
+}
+
+impl From<u32> for U160 {
+    fn from(value: u32) -> U160 {
+        U160::from(value as u128)
+    }
+}
+
+impl TryFrom<U160> for u128 {
+    type Error = anyhow::Error;
+    fn try_from(u160: U160) -> Result<u128> {
+        ensure!(u160.0[4] == 0, "U160 overflowed on conversion to u128");
+        Ok(U256::from(u160).low_u128())
+    }
+}
+
 impl U160 {
-    pub const MAX: Self = U160([u32::max_value(); 5]);
-    pub fn zero() -> Self {
+    pub const MAX: Self = U160([u32::MAX; 5]);
+    pub const fn zero() -> Self {
         U160([0; 5])
     }
+    pub const fn one() -> Self {
+        U160([1, 0, 0, 0, 0])
+    }
     pub fn is_zero(&self) -> bool {
-        return *self == U160([0; 5]);
+        *self == U160([0; 5])
+    }
+
+    /// keeps the low 160 bits, i.e. solidity's `uint160(x)` on a uint256
+    pub fn from_u256_wrapping(value: U256) -> Self {
+        let U256(words) = value;
+        U160([
+            words[0] as u32,
+            (words[0] >> 32) as u32,
+            words[1] as u32,
+            (words[1] >> 32) as u32,
+            words[2] as u32,
+        ])
+    }
+
+    /// reads up to 20 big endian bytes. panics on longer slices, like U256::from_big_endian.
+    pub fn from_big_endian(bytes: &[u8]) -> Self {
+        assert!(bytes.len() <= 20, "too many bytes for a U160");
+        U160::from_u256_wrapping(U256::from_big_endian(bytes))
+    }
+    /// writes the value as 20 big endian bytes. panics if `bytes` isn't 20 long.
+    pub fn to_big_endian(&self, bytes: &mut [u8]) {
+        assert_eq!(bytes.len(), 20, "U160 is 20 bytes");
+        let mut wide = [0u8; 32];
+        U256::from(*self).to_big_endian(&mut wide);
+        bytes.copy_from_slice(&wide[12..]);
+    }
+
+    pub fn from_dec_str(s: &str) -> Result<Self> {
+        U256::from_dec_str(s)
+            .map_err(|e| anyhow!("{:?}", e))?
+            .try_into()
+    }
+    /// parses hex digits, with or without a 0x prefix
+    pub fn from_hex_str(s: &str) -> Result<Self> {
+        let digits = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        ensure!(!digits.is_empty(), "empty U160 string");
+        U256::from_str_radix(digits, 16)
+            .map_err(|e| anyhow!("{:?}", e))?
+            .try_into()
+    }
+
+    pub fn bits(&self) -> usize {
+        U256::from(*self).bits()
+    }
+    pub fn bit(&self, index: usize) -> bool {
+        index < 160 && self.0[index / 32] & (1 << (index % 32)) != 0
+    }
+    pub fn leading_zeros(&self) -> u32 {
+        U256::from(*self).leading_zeros() - 96
+    }
+
+    /// wraps U256 results back into 160 bits and reports whether anything was lost
+    fn overflowing_from(value: U256, overflow: bool) -> (Self, bool) {
+        (
+            U160::from_u256_wrapping(value),
+            overflow || value > U256::from(U160::MAX),
+        )
+    }
+
+    pub fn overflowing_add(self, other: Self) -> (Self, bool) {
+        U160::overflowing_from(U256::from(self) + U256::from(other), false)
+    }
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_add(other))
+    }
+    pub fn wrapping_add(self, other: Self) -> Self {
+        self.overflowing_add(other).0
+    }
+
+    pub fn overflowing_sub(self, other: Self) -> (Self, bool) {
+        let (value, overflow) = U256::from(self).overflowing_sub(U256::from(other));
+        (U160::from_u256_wrapping(value), overflow)
+    }
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_sub(other))
+    }
+    pub fn wrapping_sub(self, other: Self) -> Self {
+        self.overflowing_sub(other).0
+    }
+
+    pub fn overflowing_mul(self, other: Self) -> (Self, bool) {
+        let (value, overflow) = U256::from(self).overflowing_mul(U256::from(other));
+        U160::overflowing_from(value, overflow)
+    }
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        checked(self.overflowing_mul(other))
+    }
+    pub fn wrapping_mul(self, other: Self) -> Self {
+        self.overflowing_mul(other).0
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        U256::from(self)
+            .checked_div(U256::from(other))
+            .map(U160::from_u256_wrapping)
+    }
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        U256::from(self)
+            .checked_rem(U256::from(other))
+            .map(U160::from_u256_wrapping)
+    }
+
+    /// shift left, None if the shift is 160 or more
+    pub fn checked_shl(self, shift: usize) -> Option<Self> {
+        if shift < 160 {
+            Some(self << shift)
+        } else {
+            None
+        }
+    }
+    /// shift right, None if the shift is 160 or more
+    pub fn checked_shr(self, shift: usize) -> Option<Self> {
+        if shift < 160 {
+            Some(self >> shift)
+        } else {
+            None
+        }
+    }
+}
+
+fn checked((value, overflow): (U160, bool)) -> Option<U160> {
+    if overflow {
+        None
+    } else {
+        Some(value)
     }
 }
 
@@ -36,46 +193,126 @@ impl PartialOrd for U160 {
     }
 }
 
-// TODO watch the endianness here too
 impl Ord for U160 {
     fn cmp(&self, other: &Self) -> Ordering {
         let U160(me) = self;
         let U160(you) = other;
 
-        for i in 4..=0 {
-            if me[i] < you[i] {
-                return Ordering::Less;
-            } else if me[i] > you[i] {
-                return Ordering::Greater;
+        // most significant word is last
+        for i in (0..5).rev() {
+            match me[i].cmp(&you[i]) {
+                Ordering::Equal => continue,
+                unequal => return unequal,
             }
         }
         Ordering::Equal
     }
 }
 
+impl Add for U160 {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("U160 overflow in addition")
+    }
+}
+
 impl Sub for U160 {
     type Output = Self;
-    // TODO: should be doing fancy tricks here to make this not be really slow. however, i am lazy, this is okay for now.
     fn sub(self, other: Self) -> Self {
-        let U160(me) = self;
-        let U160(you) = other;
-        let mut ret = me.clone();
-        let mut borrow: u32 = 0;
-        for i in 0..5 {
-            let result: i64 = ((me[i]) - (you[i]) - (borrow)).into();
-            if result < 0 {
-                ret[i] = (result + (1 << 32)) as u32;
-                borrow = 1;
-            } else {
-                ret[i] = result as u32;
-                borrow = 0;
-            }
+        self.checked_sub(other)
+            .expect("U160 underflow in subtraction")
+    }
+}
+
+impl Mul for U160 {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other)
+            .expect("U160 overflow in multiplication")
+    }
+}
+
+impl Div for U160 {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        self.checked_div(other).expect("U160 division by zero")
+    }
+}
+
+impl Rem for U160 {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self {
+        self.checked_rem(other).expect("U160 remainder by zero")
+    }
+}
+
+impl AddAssign for U160 {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for U160 {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+/// bits shifted past bit 159 are lost, like solidity's `<<` on a uint160
+impl Shl<usize> for U160 {
+    type Output = Self;
+    fn shl(self, shift: usize) -> Self {
+        if shift >= 160 {
+            U160::zero()
+        } else {
+            U160::from_u256_wrapping(U256::from(self) << shift)
+        }
+    }
+}
+
+impl Shr<usize> for U160 {
+    type Output = Self;
+    fn shr(self, shift: usize) -> Self {
+        if shift >= 160 {
+            U160::zero()
+        } else {
+            U160::from_u256_wrapping(U256::from(self) >> shift)
         }
-        U160(ret)
     }
 }
 
-// TODO watch the endianness here
+impl Not for U160 {
+    type Output = Self;
+    fn not(self) -> Self {
+        let U160(me) = self;
+        U160(me.map(|word| !word))
+    }
+}
+
+impl BitAnd for U160 {
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self {
+        let (U160(me), U160(you)) = (self, other);
+        U160(std::array::from_fn(|i| me[i] & you[i]))
+    }
+}
+
+impl BitOr for U160 {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        let (U160(me), U160(you)) = (self, other);
+        U160(std::array::from_fn(|i| me[i] | you[i]))
+    }
+}
+
+impl BitXor for U160 {
+    type Output = Self;
+    fn bitxor(self, other: Self) -> Self {
+        let (U160(me), U160(you)) = (self, other);
+        U160(std::array::from_fn(|i| me[i] ^ you[i]))
+    }
+}
+
 impl From<U160> for U256 {
     fn from(u160: U160) -> U256 {
         let U160(u) = u160;
@@ -87,29 +324,142 @@ impl From<U160> for U256 {
     }
 }
 
-// TODO watch the endianness here
-impl TryInto<U160> for U256 {
+impl TryFrom<U256> for U160 {
     type Error = anyhow::Error;
-    fn try_into(self) -> anyhow::Result<U160> {
-        if self > U160::MAX.into() {
-            Err(anyhow::anyhow!("U256 overflowed on conversion to U160"))
+    fn try_from(value: U256) -> Result<U160> {
+        if value > U160::MAX.into() {
+            Err(anyhow!("U256 overflowed on conversion to U160"))
         } else {
-            todo!()
+            Ok(U160::from_u256_wrapping(value))
         }
     }
 }
 
+/// wrapping, like the unchecked `amount * sqrtPX96` in SqrtPriceMath.
+/// callers there detect the overflow by dividing the product back.
 impl Mul<U160> for U256 {
     type Output = U256;
-    fn mul(self, _other: U160) -> Self::Output {
-        todo!()
+    fn mul(self, other: U160) -> Self::Output {
+        self.overflowing_mul(other.into()).0
+    }
+}
+
+impl fmt::Display for U160 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&U256::from(*self), f)
+    }
+}
+
+impl fmt::LowerHex for U160 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&U256::from(*self), f)
+    }
+}
+
+/// parses hex, like U256's FromStr
+impl FromStr for U160 {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<U160> {
+        U160::from_hex_str(s)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn max_u256() -> U256 {
+        (U256::one() << 160) - 1
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(U160::from(1u128 << 100) > U160::from(5u128));
+        assert!(U160([0, 0, 0, 0, 1]) > U160::from(u128::MAX));
+        assert!(U160::zero() < U160::one());
+        assert_eq!(U160::MAX.cmp(&U160::MAX), Ordering::Equal);
+    }
+
+    #[test]
+    fn u256_round_trip() {
+        for value in [
+            U256::zero(),
+            U256::from(12345),
+            U256::one() << 130,
+            max_u256(),
+        ] {
+            let u160 = U160::try_from(value).unwrap();
+            assert_eq!(U256::from(u160), value);
+        }
+        assert!(U160::try_from(U256::one() << 160).is_err());
+        assert_eq!(U160::from_u256_wrapping(U256::one() << 160), U160::zero());
+    }
+
+    #[test]
+    fn sub_borrows_across_words() {
+        let a = U160([0, 0, 1, 0, 0]);
+        assert_eq!(a - U160::one(), U160([u32::MAX, u32::MAX, 0, 0, 0]));
+        assert_eq!(U160::zero().checked_sub(U160::one()), None);
+        assert_eq!(U160::zero().wrapping_sub(U160::one()), U160::MAX);
+    }
+
+    #[test]
+    fn add_mul_div() {
+        assert_eq!(U160::MAX.checked_add(U160::one()), None);
+        assert_eq!(U160::MAX.wrapping_add(U160::one()), U160::zero());
+        let big = U160::one() << 100;
+        assert_eq!(big.checked_mul(big), None);
+        assert_eq!(
+            U256::from(big * U160::from(7u128)),
+            (U256::one() << 100) * 7
+        );
+        assert_eq!(U160::from(100u128) / U160::from(7u128), U160::from(14u128));
+        assert_eq!(U160::from(100u128) % U160::from(7u128), U160::from(2u128));
+        assert_eq!(U160::one().checked_div(U160::zero()), None);
+    }
+
+    #[test]
+    fn shifts_and_bits() {
+        assert_eq!(U160::one() << 159 << 1, U160::zero());
+        assert_eq!((U160::one() << 159) >> 159, U160::one());
+        assert_eq!((U160::one() << 70).bits(), 71);
+        assert!((U160::one() << 70).bit(70));
+        assert_eq!(U160::one().leading_zeros(), 159);
+        assert_eq!(!U160::zero(), U160::MAX);
+        assert_eq!(U160::MAX & U160::one(), U160::one());
+    }
+
+    #[test]
+    fn bytes_and_strings() {
+        let value = U160::from_hex_str("0x0102030405060708090a0b0c0d0e0f1011121314").unwrap();
+        let mut bytes = [0u8; 20];
+        value.to_big_endian(&mut bytes);
+        assert_eq!(bytes.to_vec(), (1..=20).collect::<Vec<u8>>());
+        assert_eq!(U160::from_big_endian(&bytes), value);
+        assert_eq!(
+            format!("{:x}", value),
+            "102030405060708090a0b0c0d0e0f1011121314"
+        );
+        assert_eq!(
+            "ffffffffffffffffffffffffffffffffffffffff"
+                .parse::<U160>()
+                .unwrap(),
+            U160::MAX
+        );
+        assert!("1ffffffffffffffffffffffffffffffffffffffff"
+            .parse::<U160>()
+            .is_err());
+        assert_eq!(
+            U160::from_dec_str("1461501637330902918203684832716283019655932542975").unwrap(),
+            U160::MAX
+        );
+        assert_eq!(U160::MAX.to_string(), max_u256().to_string());
+    }
+
     #[test]
-    fn it_works() {
-        todo!()
+    fn u256_times_u160_wraps() {
+        let product = U256::MAX * U160::from(2u128);
+        assert_eq!(product, U256::MAX - 1);
+        assert_eq!(U256::from(3) * U160::from(5u128), U256::from(15));
     }
 }
@@ -1,3 +1,7 @@
+#[allow(non_snake_case)]
 pub mod I256;
+#[allow(non_snake_case)]
 pub mod U160;
+pub mod sized;
 pub use primitive_types::U256;
+pub use sized::{Arithmetic, IntN, UintN};
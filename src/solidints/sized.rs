@@ -0,0 +1,473 @@
+//! Solidity's odd-width integers (uint24, int24, int56, uint128, ...), generic over the bit width.
+//! Values are kept in a u128/i128 and always stay inside the declared width, so widths up to 128 bits are supported.
+//! The 160 and 256 bit types live in U160, I256 and primitive_types::U256.
+use super::{I256::I256, U160::U160, U256};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Rem, Sub};
+
+/// How arithmetic treats overflow.
+/// Solidity 0.8 reverts by default (`Checked`), 0.7 and `unchecked {}` blocks silently wrap (`Wrapping`).
+/// Uniswap V3 is compiled with 0.7, so accumulators like feeGrowthGlobal and tickCumulative wrap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    Checked,
+    Wrapping,
+}
+
+/// `uintN`, for N a multiple of 8 up to 128
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(try_from = "u128", into = "u128")]
+pub struct UintN<const BITS: u32>(u128);
+
+/// `intN`, for N a multiple of 8 up to 128
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(try_from = "i128", into = "i128")]
+pub struct IntN<const BITS: u32>(i128);
+
+pub type Uint8 = UintN<8>;
+pub type Uint16 = UintN<16>;
+pub type Uint24 = UintN<24>;
+pub type Uint32 = UintN<32>;
+pub type Uint40 = UintN<40>;
+pub type Uint56 = UintN<56>;
+pub type Uint64 = UintN<64>;
+pub type Uint96 = UintN<96>;
+pub type Uint128 = UintN<128>;
+pub type Uint160 = U160;
+pub type Uint256 = U256;
+
+pub type Int8 = IntN<8>;
+pub type Int16 = IntN<16>;
+pub type Int24 = IntN<24>;
+pub type Int32 = IntN<32>;
+pub type Int56 = IntN<56>;
+pub type Int64 = IntN<64>;
+pub type Int96 = IntN<96>;
+pub type Int128 = IntN<128>;
+pub type Int256 = I256;
+
+fn overflowed<T>(value: Option<T>, what: &str) -> Result<T> {
+    value.ok_or_else(|| anyhow!("{} overflowed", what))
+}
+
+impl<const BITS: u32> UintN<BITS> {
+    const VALID_WIDTH: () = assert!(BITS >= 8 && BITS <= 128 && BITS.is_multiple_of(8));
+
+    pub const MIN: Self = UintN(0);
+    pub const MAX: Self = UintN(u128::MAX >> (128 - BITS));
+
+    /// reverts if `value` doesn't fit, like an explicit range check before a downcast
+    pub fn new(value: u128) -> Result<Self> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_WIDTH;
+        ensure!(
+            value <= Self::MAX.0,
+            "{} does not fit in uint{}",
+            value,
+            BITS
+        );
+        Ok(UintN(value))
+    }
+    /// keeps the low BITS bits, i.e. solidity's `uintN(x)`
+    pub fn wrapping_new(value: u128) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_WIDTH;
+        UintN(value & Self::MAX.0)
+    }
+    pub fn zero() -> Self {
+        UintN(0)
+    }
+    pub fn get(self) -> u128 {
+        self.0
+    }
+
+    fn fit(value: Option<u128>) -> Option<Self> {
+        value.and_then(|v| Self::new(v).ok())
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_add(other.0))
+    }
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_sub(other.0))
+    }
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_mul(other.0))
+    }
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_div(other.0))
+    }
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_rem(other.0))
+    }
+    pub fn wrapping_add(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_add(other.0))
+    }
+    pub fn wrapping_sub(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_sub(other.0))
+    }
+    pub fn wrapping_mul(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_mul(other.0))
+    }
+    /// `uintTO(x)`: exact when widening, keeps the low TO bits when narrowing
+    pub fn wrapping_cast<const TO: u32>(self) -> UintN<TO> {
+        UintN::<TO>::wrapping_new(self.0)
+    }
+
+    /// `a + b` as compiled under the given arithmetic mode
+    pub fn add_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_add(other), "uint addition"),
+            Arithmetic::Wrapping => Ok(self.wrapping_add(other)),
+        }
+    }
+    /// `a - b` as compiled under the given arithmetic mode
+    pub fn sub_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_sub(other), "uint subtraction"),
+            Arithmetic::Wrapping => Ok(self.wrapping_sub(other)),
+        }
+    }
+    /// `a * b` as compiled under the given arithmetic mode
+    pub fn mul_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_mul(other), "uint multiplication"),
+            Arithmetic::Wrapping => Ok(self.wrapping_mul(other)),
+        }
+    }
+}
+
+impl<const BITS: u32> IntN<BITS> {
+    const VALID_WIDTH: () = assert!(BITS >= 8 && BITS <= 128 && BITS.is_multiple_of(8));
+
+    pub const MIN: Self = IntN(i128::MIN >> (128 - BITS));
+    pub const MAX: Self = IntN(i128::MAX >> (128 - BITS));
+
+    /// reverts if `value` doesn't fit, like an explicit range check before a downcast
+    pub fn new(value: i128) -> Result<Self> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_WIDTH;
+        ensure!(
+            value >= Self::MIN.0 && value <= Self::MAX.0,
+            "{} does not fit in int{}",
+            value,
+            BITS
+        );
+        Ok(IntN(value))
+    }
+    /// keeps the low BITS bits and sign extends, i.e. solidity's `intN(x)`
+    pub fn wrapping_new(value: i128) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_WIDTH;
+        let unused = 128 - BITS;
+        IntN((value << unused) >> unused)
+    }
+    pub fn zero() -> Self {
+        IntN(0)
+    }
+    pub fn get(self) -> i128 {
+        self.0
+    }
+
+    fn fit(value: Option<i128>) -> Option<Self> {
+        value.and_then(|v| Self::new(v).ok())
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_add(other.0))
+    }
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_sub(other.0))
+    }
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_mul(other.0))
+    }
+    /// truncates towards zero. None on a zero divisor or MIN / -1.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        Self::fit(self.0.checked_div(other.0))
+    }
+    /// sign follows the dividend. None only on a zero divisor, SMOD gives MIN % -1 == 0.
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        if other.0 == -1 {
+            Some(IntN(0))
+        } else {
+            Self::fit(self.0.checked_rem(other.0))
+        }
+    }
+    pub fn checked_neg(self) -> Option<Self> {
+        Self::fit(self.0.checked_neg())
+    }
+    pub fn wrapping_add(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_add(other.0))
+    }
+    pub fn wrapping_sub(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_sub(other.0))
+    }
+    pub fn wrapping_mul(self, other: Self) -> Self {
+        Self::wrapping_new(self.0.wrapping_mul(other.0))
+    }
+    pub fn wrapping_neg(self) -> Self {
+        Self::wrapping_new(self.0.wrapping_neg())
+    }
+    /// `intTO(x)`: exact when widening, keeps the low TO bits when narrowing
+    pub fn wrapping_cast<const TO: u32>(self) -> IntN<TO> {
+        IntN::<TO>::wrapping_new(self.0)
+    }
+
+    /// `a + b` as compiled under the given arithmetic mode
+    pub fn add_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_add(other), "int addition"),
+            Arithmetic::Wrapping => Ok(self.wrapping_add(other)),
+        }
+    }
+    /// `a - b` as compiled under the given arithmetic mode
+    pub fn sub_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_sub(other), "int subtraction"),
+            Arithmetic::Wrapping => Ok(self.wrapping_sub(other)),
+        }
+    }
+    /// `a * b` as compiled under the given arithmetic mode
+    pub fn mul_as(self, other: Self, mode: Arithmetic) -> Result<Self> {
+        match mode {
+            Arithmetic::Checked => overflowed(self.checked_mul(other), "int multiplication"),
+            Arithmetic::Wrapping => Ok(self.wrapping_mul(other)),
+        }
+    }
+}
+
+// the operators are 0.8 checked arithmetic: they panic where solidity would revert
+
+impl<const BITS: u32> Add for UintN<BITS> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("uint overflow in addition")
+    }
+}
+
+impl<const BITS: u32> Sub for UintN<BITS> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .expect("uint underflow in subtraction")
+    }
+}
+
+impl<const BITS: u32> Mul for UintN<BITS> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other)
+            .expect("uint overflow in multiplication")
+    }
+}
+
+impl<const BITS: u32> Div for UintN<BITS> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        self.checked_div(other).expect("uint division by zero")
+    }
+}
+
+impl<const BITS: u32> Rem for UintN<BITS> {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self {
+        self.checked_rem(other).expect("uint remainder by zero")
+    }
+}
+
+impl<const BITS: u32> Add for IntN<BITS> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("int overflow in addition")
+    }
+}
+
+impl<const BITS: u32> Sub for IntN<BITS> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .expect("int overflow in subtraction")
+    }
+}
+
+impl<const BITS: u32> Mul for IntN<BITS> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other)
+            .expect("int overflow in multiplication")
+    }
+}
+
+impl<const BITS: u32> Div for IntN<BITS> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        self.checked_div(other)
+            .expect("int overflow or division by zero")
+    }
+}
+
+impl<const BITS: u32> Rem for IntN<BITS> {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self {
+        self.checked_rem(other).expect("int remainder by zero")
+    }
+}
+
+impl<const BITS: u32> std::ops::Neg for IntN<BITS> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        self.checked_neg().expect("int overflow in negation")
+    }
+}
+
+impl<const BITS: u32> TryFrom<u128> for UintN<BITS> {
+    type Error = anyhow::Error;
+    fn try_from(value: u128) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl<const BITS: u32> From<UintN<BITS>> for u128 {
+    fn from(value: UintN<BITS>) -> u128 {
+        value.0
+    }
+}
+
+impl<const BITS: u32> From<UintN<BITS>> for U256 {
+    fn from(value: UintN<BITS>) -> U256 {
+        U256::from(value.0)
+    }
+}
+
+impl<const BITS: u32> TryFrom<U256> for UintN<BITS> {
+    type Error = anyhow::Error;
+    fn try_from(value: U256) -> Result<Self> {
+        ensure!(
+            value.bits() <= BITS as usize,
+            "U256 does not fit in uint{}",
+            BITS
+        );
+        Self::new(value.low_u128())
+    }
+}
+
+impl<const BITS: u32> TryFrom<i128> for IntN<BITS> {
+    type Error = anyhow::Error;
+    fn try_from(value: i128) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl<const BITS: u32> From<IntN<BITS>> for i128 {
+    fn from(value: IntN<BITS>) -> i128 {
+        value.0
+    }
+}
+
+impl<const BITS: u32> From<IntN<BITS>> for I256 {
+    fn from(value: IntN<BITS>) -> I256 {
+        I256::from(value.0)
+    }
+}
+
+impl<const BITS: u32> TryFrom<I256> for IntN<BITS> {
+    type Error = anyhow::Error;
+    fn try_from(value: I256) -> Result<Self> {
+        Self::new(i128::try_from(value)?)
+    }
+}
+
+impl<const BITS: u32> fmt::Display for UintN<BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<const BITS: u32> fmt::Display for IntN<BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds() {
+        assert_eq!(Uint24::MAX.get(), (1 << 24) - 1);
+        assert_eq!(Int24::MIN.get(), -(1 << 23));
+        assert_eq!(Int24::MAX.get(), (1 << 23) - 1);
+        assert_eq!(Int56::MIN.get(), -(1 << 55));
+        assert_eq!(Uint128::MAX.get(), u128::MAX);
+        assert_eq!(Int128::MIN.get(), i128::MIN);
+        assert!(Uint24::new(1 << 24).is_err());
+        assert!(Int24::new(1 << 23).is_err());
+        assert!(Int24::new(-(1 << 23)).is_ok());
+    }
+
+    #[test]
+    fn wrapping_matches_solidity_casts() {
+        assert_eq!(Uint24::wrapping_new((1 << 24) + 5).get(), 5);
+        assert_eq!(Int24::wrapping_new(1 << 23), Int24::MIN);
+        assert_eq!(Int24::wrapping_new(-1).get(), -1);
+        assert_eq!(Int8::wrapping_new(200).get(), -56);
+        assert_eq!(Int24::MIN.wrapping_cast::<56>().get(), -(1 << 23));
+        assert_eq!(
+            Int56::new(1 << 23).unwrap().wrapping_cast::<24>(),
+            Int24::MIN
+        );
+        assert_eq!(Uint56::MAX.wrapping_cast::<24>(), Uint24::MAX);
+    }
+
+    #[test]
+    fn checked_vs_wrapping() {
+        let max = Int56::MAX;
+        let one = Int56::new(1).unwrap();
+        assert!(max.add_as(one, Arithmetic::Checked).is_err());
+        assert_eq!(max.add_as(one, Arithmetic::Wrapping).unwrap(), Int56::MIN);
+        assert_eq!(Int56::MIN.wrapping_sub(one), max);
+
+        let zero = Uint128::zero();
+        let one = Uint128::new(1).unwrap();
+        assert!(zero.sub_as(one, Arithmetic::Checked).is_err());
+        assert_eq!(
+            zero.sub_as(one, Arithmetic::Wrapping).unwrap(),
+            Uint128::MAX
+        );
+        assert_eq!(Uint128::MAX.wrapping_mul(Uint128::MAX), one);
+        assert_eq!(
+            Int128::MIN.wrapping_mul(Int128::new(-1).unwrap()),
+            Int128::MIN
+        );
+    }
+
+    #[test]
+    fn division() {
+        let i = |x| Int24::new(x).unwrap();
+        assert_eq!(i(-7) / i(2), i(-3));
+        assert_eq!(i(-7) % i(2), i(-1));
+        assert_eq!(Int24::MIN.checked_div(i(-1)), None);
+        assert_eq!(Int24::MIN % i(-1), i(0));
+        assert_eq!(i(1).checked_div(i(0)), None);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(I256::from(Int24::MIN), I256::from(-(1i128 << 23)));
+        assert_eq!(Int24::try_from(I256::from(-5i128)).unwrap().get(), -5);
+        assert!(Uint24::try_from(U256::one() << 24).is_err());
+        assert_eq!(U256::from(Uint24::MAX), U256::from((1u32 << 24) - 1));
+        let bytes = bincode::serialize(&Int24::MIN).unwrap();
+        assert_eq!(bincode::deserialize::<Int24>(&bytes).unwrap(), Int24::MIN);
+        let too_big = bincode::serialize(&(1i128 << 23)).unwrap();
+        assert!(bincode::deserialize::<Int24>(&too_big).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use crate::solidints::U256;
+use anyhow::{ensure, Result};
+
+/// @notice Returns the index of the most significant bit of the number,
+///     where the least significant bit is at index 0 and the most significant bit is at index 255
+/// @dev The function satisfies the property:
+///     x >= 2**mostSignificantBit(x) and x < 2**(mostSignificantBit(x)+1)
+/// @param x the value for which to compute the most significant bit, must be greater than 0
+/// @return r the index of the most significant bit
+pub fn most_significant_bit(x: U256) -> Result<u8> {
+    ensure!(!x.is_zero(), "msb of zero");
+    Ok((x.bits() - 1) as u8)
+}
+
+/// @notice Returns the index of the least significant bit of the number,
+///     where the least significant bit is at index 0 and the most significant bit is at index 255
+/// @dev The function satisfies the property:
+///     (x & 2**leastSignificantBit(x)) != 0 and (x & (2**(leastSignificantBit(x)) - 1)) == 0)
+/// @param x the value for which to compute the least significant bit, must be greater than 0
+/// @return r the index of the least significant bit
+pub fn least_significant_bit(x: U256) -> Result<u8> {
+    ensure!(!x.is_zero(), "lsb of zero");
+    Ok(x.trailing_zeros() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn significant_bits() {
+        assert!(most_significant_bit(U256::zero()).is_err());
+        assert!(least_significant_bit(U256::zero()).is_err());
+        assert_eq!(most_significant_bit(U256::one()).unwrap(), 0);
+        assert_eq!(least_significant_bit(U256::one()).unwrap(), 0);
+        assert_eq!(most_significant_bit(U256::MAX).unwrap(), 255);
+        assert_eq!(least_significant_bit(U256::MAX).unwrap(), 0);
+        for i in 0..256 {
+            let power = U256::one() << i;
+            assert_eq!(most_significant_bit(power).unwrap() as usize, i);
+            assert_eq!(least_significant_bit(power).unwrap() as usize, i);
+        }
+    }
+}
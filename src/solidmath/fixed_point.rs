@@ -13,8 +13,11 @@ lazy_static! {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn q_constants() {
+        assert_eq!(*Q96, U256::one() << FP96_RESOLUTION);
+        assert_eq!(*Q128, U256::one() << 128);
     }
 }
@@ -38,8 +38,29 @@ pub fn unsafe_div_rounding_up(x: U256, y: U256) -> Result<U256> {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn mul_div_full_precision() {
+        let q128 = U256::one() << 128;
+        assert!(muldiv(q128, 5.into(), U256::zero()).is_err());
+        assert!(muldiv(q128, q128, U256::one()).is_err());
+        assert_eq!(muldiv(U256::MAX, U256::MAX, U256::MAX).unwrap(), U256::MAX);
+        // 0.5 * 1.5 / 0.75 in Q128
+        assert_eq!(muldiv(q128 / 2, q128 * 3 / 2, q128 * 3 / 4).unwrap(), q128);
+        assert_eq!(
+            mul_div_rounding_up(q128, 1.into(), 3.into()).unwrap(),
+            q128 / 3 + 1
+        );
+        assert_eq!(
+            mul_div_rounding_up(6.into(), 1.into(), 3.into()).unwrap(),
+            2.into()
+        );
+        assert!(mul_div_rounding_up(U256::MAX, U256::MAX, U256::MAX - 1).is_err());
+        assert_eq!(
+            unsafe_div_rounding_up(7.into(), 2.into()).unwrap(),
+            4.into()
+        );
+        assert!(unsafe_div_rounding_up(7.into(), U256::zero()).is_err());
     }
 }
@@ -0,0 +1,213 @@
+use crate::solidints::{U160::U160, U256};
+use anyhow::{anyhow, Result};
+
+use super::fixed_point::{FP96_RESOLUTION, Q96};
+use super::full_math;
+
+/// @notice Downcasts uint256 to uint128
+/// @param x The uint258 to be downcasted
+/// @return y The passed value, downcasted to uint128
+fn to_u128(x: U256) -> Result<u128> {
+    if x.bits() > 128 {
+        return Err(anyhow!("liquidity overflowed uint128"));
+    }
+    Ok(x.low_u128())
+}
+
+/// orders a pair of sqrt prices, like the `if (sqrtRatioAX96 > sqrtRatioBX96)` swap at the top of every function
+fn sorted(sqrt_ratio_ax96: U160, sqrt_ratio_bx96: U160) -> (U256, U256) {
+    let (a, b) = if sqrt_ratio_ax96 > sqrt_ratio_bx96 {
+        (sqrt_ratio_bx96, sqrt_ratio_ax96)
+    } else {
+        (sqrt_ratio_ax96, sqrt_ratio_bx96)
+    };
+    (a.into(), b.into())
+}
+
+/// @notice Computes the amount of liquidity received for a given amount of token0 and price range
+/// @dev Calculates amount0 * (sqrt(upper) * sqrt(lower)) / (sqrt(upper) - sqrt(lower))
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param amount0 The amount0 being sent in
+/// @return liquidity The amount of returned liquidity
+pub fn get_liquidity_for_amount0(
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    amount0: U256,
+) -> Result<u128> {
+    let (a, b) = sorted(sqrt_ratio_ax96, sqrt_ratio_bx96);
+    let intermediate = full_math::muldiv(a, b, *Q96)?;
+    to_u128(full_math::muldiv(amount0, intermediate, b - a)?)
+}
+
+/// @notice Computes the amount of liquidity received for a given amount of token1 and price range
+/// @dev Calculates amount1 / (sqrt(upper) - sqrt(lower)).
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param amount1 The amount1 being sent in
+/// @return liquidity The amount of returned liquidity
+pub fn get_liquidity_for_amount1(
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    amount1: U256,
+) -> Result<u128> {
+    let (a, b) = sorted(sqrt_ratio_ax96, sqrt_ratio_bx96);
+    to_u128(full_math::muldiv(amount1, *Q96, b - a)?)
+}
+
+/// @notice Computes the maximum amount of liquidity received for a given amount of token0, token1, the current
+/// pool prices and the prices at the tick boundaries
+/// @param sqrt_ratio_x96 A sqrt price representing the current pool prices
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param amount0 The amount of token0 being sent in
+/// @param amount1 The amount of token1 being sent in
+/// @return liquidity The maximum amount of liquidity received
+pub fn get_liquidity_for_amounts(
+    sqrt_ratio_x96: U160,
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    amount0: U256,
+    amount1: U256,
+) -> Result<u128> {
+    let (a, b) = if sqrt_ratio_ax96 > sqrt_ratio_bx96 {
+        (sqrt_ratio_bx96, sqrt_ratio_ax96)
+    } else {
+        (sqrt_ratio_ax96, sqrt_ratio_bx96)
+    };
+
+    if sqrt_ratio_x96 <= a {
+        get_liquidity_for_amount0(a, b, amount0)
+    } else if sqrt_ratio_x96 < b {
+        let liquidity0 = get_liquidity_for_amount0(sqrt_ratio_x96, b, amount0)?;
+        let liquidity1 = get_liquidity_for_amount1(a, sqrt_ratio_x96, amount1)?;
+        Ok(liquidity0.min(liquidity1))
+    } else {
+        get_liquidity_for_amount1(a, b, amount1)
+    }
+}
+
+/// @notice Computes the amount of token0 for a given amount of liquidity and a price range
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param liquidity The liquidity being valued
+/// @return amount0 The amount of token0
+pub fn get_amount0_for_liquidity(
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    liquidity: u128,
+) -> Result<U256> {
+    let (a, b) = sorted(sqrt_ratio_ax96, sqrt_ratio_bx96);
+    full_math::muldiv(U256::from(liquidity) << FP96_RESOLUTION, b - a, b)?
+        .checked_div(a)
+        .ok_or(anyhow!("sqrt ratio was zero."))
+}
+
+/// @notice Computes the amount of token1 for a given amount of liquidity and a price range
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param liquidity The liquidity being valued
+/// @return amount1 The amount of token1
+pub fn get_amount1_for_liquidity(
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    liquidity: u128,
+) -> Result<U256> {
+    let (a, b) = sorted(sqrt_ratio_ax96, sqrt_ratio_bx96);
+    full_math::muldiv(liquidity.into(), b - a, *Q96)
+}
+
+/// @notice Computes the token0 and token1 value for a given amount of liquidity, the current
+/// pool prices and the prices at the tick boundaries
+/// @param sqrt_ratio_x96 A sqrt price representing the current pool prices
+/// @param sqrt_ratio_ax96 A sqrt price representing the first tick boundary
+/// @param sqrt_ratio_bx96 A sqrt price representing the second tick boundary
+/// @param liquidity The liquidity being valued
+/// @return amount0 The amount of token0
+/// @return amount1 The amount of token1
+pub fn get_amounts_for_liquidity(
+    sqrt_ratio_x96: U160,
+    sqrt_ratio_ax96: U160,
+    sqrt_ratio_bx96: U160,
+    liquidity: u128,
+) -> Result<(U256, U256)> {
+    let (a, b) = if sqrt_ratio_ax96 > sqrt_ratio_bx96 {
+        (sqrt_ratio_bx96, sqrt_ratio_ax96)
+    } else {
+        (sqrt_ratio_ax96, sqrt_ratio_bx96)
+    };
+
+    if sqrt_ratio_x96 <= a {
+        Ok((get_amount0_for_liquidity(a, b, liquidity)?, U256::zero()))
+    } else if sqrt_ratio_x96 < b {
+        Ok((
+            get_amount0_for_liquidity(sqrt_ratio_x96, b, liquidity)?,
+            get_amount1_for_liquidity(a, sqrt_ratio_x96, liquidity)?,
+        ))
+    } else {
+        Ok((U256::zero(), get_amount1_for_liquidity(a, b, liquidity)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(s: &str) -> U160 {
+        U160::from_dec_str(s).unwrap()
+    }
+
+    // encodePriceSqrt(1, 1), (100, 110), (110, 100)
+    fn range() -> (U160, U160, U160) {
+        (
+            price("79228162514264337593543950336"),
+            price("75541088972021052632782079082"),
+            price("83095197869223157896060286990"),
+        )
+    }
+
+    #[test]
+    fn liquidity_for_amounts() {
+        let (current, lower, upper) = range();
+        let liquidity =
+            |p: U160| get_liquidity_for_amounts(p, lower, upper, 100.into(), 200.into()).unwrap();
+        // in range
+        assert_eq!(liquidity(current), 2148);
+        // below, encodePriceSqrt(99, 110), and on the lower boundary
+        assert_eq!(liquidity(price("75162434512514379355924140470")), 1048);
+        assert_eq!(liquidity(lower), 1048);
+        // above, encodePriceSqrt(111, 100), and on the upper boundary
+        assert_eq!(liquidity(price("83472048772503575395058907992")), 2097);
+        assert_eq!(liquidity(upper), 2097);
+        // the order of the boundaries doesn't matter
+        assert_eq!(
+            get_liquidity_for_amounts(current, upper, lower, 100.into(), 200.into()).unwrap(),
+            2148
+        );
+    }
+
+    #[test]
+    fn amounts_for_liquidity() {
+        let (current, lower, upper) = range();
+        let amounts = |p: U160, l: u128| get_amounts_for_liquidity(p, lower, upper, l).unwrap();
+        assert_eq!(amounts(current, 2148), (99.into(), 99.into()));
+        assert_eq!(
+            amounts(price("75162434512514379355924140470"), 1048),
+            (99.into(), 0.into())
+        );
+        assert_eq!(amounts(lower, 1048), (99.into(), 0.into()));
+        assert_eq!(
+            amounts(price("83472048772503575395058907992"), 2097),
+            (0.into(), 199.into())
+        );
+        assert_eq!(amounts(upper, 2097), (0.into(), 199.into()));
+    }
+
+    #[test]
+    fn liquidity_overflow_and_empty_range() {
+        let (current, lower, _) = range();
+        assert!(get_liquidity_for_amount1(lower, current, U256::MAX >> 40).is_err());
+        assert!(get_liquidity_for_amount0(lower, lower, 1.into()).is_err());
+        assert!(get_amount0_for_liquidity(U160::zero(), lower, 1).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bit_math;
 pub mod fixed_point;
 pub mod full_math;
+pub mod liquidity_amounts;
 pub mod sqrt_price_math;
+pub mod tick_math;
@@ -1,8 +1,7 @@
 use crate::solidints::U256;
 use crate::solidints::{I256::I256, U160::U160};
 // FIXME why the bad paths
-use anyhow::{ensure, Result};
-use std::ops::Add;
+use anyhow::{anyhow, ensure, Result};
 
 use super::fixed_point;
 use super::full_math;
@@ -28,24 +27,26 @@ pub fn get_next_sqrt_price_from_amount0_rounding_up(
     if amount == U256::zero() {
         return Ok(sqrt_px96);
     };
-    let numerator1: U256 = U256::try_from(liquidity)? << fixed_point::FP96_RESOLUTION;
+    let numerator1: U256 = U256::from(liquidity) << fixed_point::FP96_RESOLUTION;
 
     if add {
         let product = amount * sqrt_px96;
         if product / amount == sqrt_px96.into() {
-            let denominator: U256 = numerator1 + product;
-            if denominator >= numerator1 {
+            let (denominator, overflowed) = numerator1.overflowing_add(product);
+            if !overflowed {
                 // always fits in 160 bits
                 return full_math::mul_div_rounding_up(numerator1, sqrt_px96.into(), denominator)?
                     .try_into();
             }
         }
 
-        return (full_math::unsafe_div_rounding_up(
+        full_math::unsafe_div_rounding_up(
             numerator1,
-            (numerator1 / sqrt_px96).add(amount),
-        ))?
-        .try_into();
+            (numerator1 / sqrt_px96)
+                .checked_add(amount)
+                .ok_or(anyhow!("denominator overflowed"))?,
+        )?
+        .try_into()
     } else {
         // if the product overflows, we know the denominator underflows
         // in addition, we must check that the denominator does not underflow
@@ -55,8 +56,7 @@ pub fn get_next_sqrt_price_from_amount0_rounding_up(
             "todo better error message fdjskla"
         );
         let denominator: U256 = numerator1 - product;
-        return full_math::mul_div_rounding_up(numerator1, sqrt_px96.into(), denominator)?
-            .try_into();
+        full_math::mul_div_rounding_up(numerator1, sqrt_px96.into(), denominator)?.try_into()
     }
 }
 
@@ -70,7 +70,7 @@ pub fn get_next_sqrt_price_from_amount0_rounding_up(
 /// @param amount How much of token1 to add, or remove, from virtual reserves
 /// @param add Whether to add, or remove, the amount of token1
 /// @return The price after adding or removing `amount`
-fn get_next_sqrt_price_from_amount1_rounding_down(
+pub fn get_next_sqrt_price_from_amount1_rounding_down(
     sqrt_px96: U160,
     liquidity: u128,
     amount: U256,
@@ -85,7 +85,10 @@ fn get_next_sqrt_price_from_amount1_rounding_down(
             full_math::muldiv(amount, *fixed_point::Q96, liquidity.into())?
         };
 
-        (U256::from(sqrt_px96) + quotient).try_into()
+        U256::from(sqrt_px96)
+            .checked_add(quotient)
+            .ok_or(anyhow!("sqrt price overflowed"))?
+            .try_into()
     } else {
         let quotient: U256 = if amount <= U160::MAX.into() {
             full_math::unsafe_div_rounding_up(
@@ -170,19 +173,19 @@ pub fn get_amount0_delta_helper(
         (sqrt_ratio_ax96, sqrt_ratio_bx96)
     };
 
-    let numerator1: U256 = (liquidity << fixed_point::FP96_RESOLUTION).into();
+    let numerator1: U256 = U256::from(liquidity) << fixed_point::FP96_RESOLUTION;
     let numerator2: U256 = (sqrt_ratio_bx96 - sqrt_ratio_ax96).into();
 
     ensure!(sqrt_ratio_ax96 > U160::zero(), "price zero");
 
-    return if round_up {
+    if round_up {
         full_math::unsafe_div_rounding_up(
             full_math::mul_div_rounding_up(numerator1, numerator2, sqrt_ratio_bx96.into())?,
             sqrt_ratio_ax96.into(),
         )
     } else {
         Ok(full_math::muldiv(numerator1, numerator2, sqrt_ratio_bx96.into())? / sqrt_ratio_ax96)
-    };
+    }
 }
 
 /// @notice Gets the amount1 delta between two prices
@@ -233,7 +236,7 @@ pub fn get_amount0_delta(
         Ok(-(get_amount0_delta_helper(
             sqrt_ratio_ax96,
             sqrt_ratio_bx96,
-            -liquidity as u128,
+            liquidity.unsigned_abs(),
             false,
         )?
         .try_into()?))
@@ -257,7 +260,7 @@ pub fn get_amount1_delta(
         Ok(-(get_amount1_delta_helper(
             sqrt_ratio_ax96,
             sqrt_ratio_bx96,
-            -liquidity as u128,
+            liquidity.unsigned_abs(),
             false,
         )?
         .try_into()?))
@@ -269,8 +272,108 @@ pub fn get_amount1_delta(
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn price(s: &str) -> U160 {
+        U160::from_dec_str(s).unwrap()
+    }
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    // encodePriceSqrt(1, 1)
+    fn price_one() -> U160 {
+        U160::one() << 96
+    }
+
+    // encodePriceSqrt(121, 100)
+    fn price_121_100() -> U160 {
+        price("87150978765690771352898345369")
+    }
+
+    #[test]
+    fn next_price_from_input() {
+        assert!(get_next_sqrt_price_from_input(U160::zero(), 0, E18.into(), false).is_err());
+        assert!(get_next_sqrt_price_from_input(U160::one(), 0, E18.into(), true).is_err());
+        // returns input price if amount in is zero
+        assert_eq!(
+            get_next_sqrt_price_from_input(price_one(), E18 / 10, U256::zero(), true).unwrap(),
+            price_one()
+        );
+        assert_eq!(
+            get_next_sqrt_price_from_input(price_one(), E18, (E18 / 10).into(), false).unwrap(),
+            price("87150978765690771352898345369")
+        );
+        assert_eq!(
+            get_next_sqrt_price_from_input(price_one(), E18, (E18 / 10).into(), true).unwrap(),
+            price("72025602285694852357767227579")
+        );
+        // amountIn > type(uint96).max and zeroForOne
+        assert_eq!(
+            get_next_sqrt_price_from_input(price_one(), 10 * E18, U256::one() << 100, true)
+                .unwrap(),
+            price("624999999995069620")
+        );
+        // can return 1 with enough amountIn and zeroForOne
+        assert_eq!(
+            get_next_sqrt_price_from_input(price_one(), 1, U256::MAX / 2, true).unwrap(),
+            U160::one()
+        );
+    }
+
+    #[test]
+    fn next_price_from_output() {
+        // fails if output amount is exactly the virtual reserves of token0 or token1
+        let p = price("20282409603651670423947251286016");
+        assert!(get_next_sqrt_price_from_output(p, 1024, 4.into(), false).is_err());
+        assert!(get_next_sqrt_price_from_output(p, 1024, 262144.into(), true).is_err());
+        assert_eq!(
+            get_next_sqrt_price_from_output(p, 1024, 262143.into(), true).unwrap(),
+            price("77371252455336267181195264")
+        );
+        assert_eq!(
+            get_next_sqrt_price_from_output(price_one(), E18, (E18 / 10).into(), false).unwrap(),
+            price("88031291682515930659493278152")
+        );
+        assert_eq!(
+            get_next_sqrt_price_from_output(price_one(), E18, (E18 / 10).into(), true).unwrap(),
+            price("71305346262837903834189555302")
+        );
+    }
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn amount0_delta() {
+        assert_eq!(
+            get_amount0_delta_helper(price_one(), price("79623317895830914510639640423"), 0, true)
+                .unwrap(),
+            U256::zero()
+        );
+        assert_eq!(
+            get_amount0_delta_helper(price_one(), price_121_100(), E18, true).unwrap(),
+            U256::from(90909090909090910u128)
+        );
+        assert_eq!(
+            get_amount0_delta_helper(price_one(), price_121_100(), E18, false).unwrap(),
+            U256::from(90909090909090909u128)
+        );
+        assert_eq!(
+            get_amount0_delta(price_one(), price_121_100(), -(E18 as i128)).unwrap(),
+            -I256::from(90909090909090909u128)
+        );
+    }
+
+    #[test]
+    fn amount1_delta() {
+        assert_eq!(
+            get_amount1_delta_helper(price_one(), price_121_100(), E18, true).unwrap(),
+            U256::from(100000000000000000u128)
+        );
+        assert_eq!(
+            get_amount1_delta_helper(price_one(), price_121_100(), E18, false).unwrap(),
+            U256::from(99999999999999999u128)
+        );
+        assert_eq!(
+            get_amount1_delta(price_121_100(), price_one(), E18 as i128).unwrap(),
+            I256::from(100000000000000000u128)
+        );
     }
 }
@@ -0,0 +1,197 @@
+use crate::solidints::{I256::I256, U160::U160, U256};
+use anyhow::{ensure, Result};
+
+/// @dev The minimum tick that may be passed to #get_sqrt_ratio_at_tick computed from log base 1.0001 of 2**-128
+pub const MIN_TICK: i32 = -887272;
+/// @dev The maximum tick that may be passed to #get_sqrt_ratio_at_tick computed from log base 1.0001 of 2**128
+pub const MAX_TICK: i32 = -MIN_TICK;
+
+/// @dev The minimum value that can be returned from #get_sqrt_ratio_at_tick. Equivalent to get_sqrt_ratio_at_tick(MIN_TICK)
+pub const MIN_SQRT_RATIO: U160 = U160([0x0002_76a3, 0x1, 0, 0, 0]); // 4295128739
+/// @dev The maximum value that can be returned from #get_sqrt_ratio_at_tick. Equivalent to get_sqrt_ratio_at_tick(MAX_TICK)
+pub const MAX_SQRT_RATIO: U160 = U160([
+    0x6398_8d26,
+    0x5d95_1d52,
+    0x5064_8849,
+    0xefd1_fc6a,
+    0xfffd_8963,
+]); // 1461446703485210103287273052203988822378723970342
+
+/// 2**128 / sqrt(1.0001)**(2**i) as Q128.128, for bit i of the absolute tick
+const SQRT_RATIO_FACTORS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+/// @notice Calculates sqrt(1.0001^tick) * 2^96
+/// @dev Throws if |tick| > max tick
+/// @param tick The input tick for the above formula
+/// @return sqrtPriceX96 A Fixed point Q64.96 number representing the sqrt of the ratio of the two assets (token1/token0)
+/// at the given tick
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U160> {
+    let abs_tick = tick.unsigned_abs();
+    ensure!(abs_tick <= MAX_TICK as u32, "T");
+
+    let mut ratio: U256 = if abs_tick & 0x1 != 0 {
+        U256::from(SQRT_RATIO_FACTORS[0])
+    } else {
+        U256::one() << 128
+    };
+    for (bit, factor) in SQRT_RATIO_FACTORS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // this divides by 1<<32 rounding up to go from a Q128.128 to a Q128.96.
+    // we then downcast because we know the result always fits within 160 bits due to our tick input constraint
+    // we round up in the division so get_tick_at_sqrt_ratio of the output price is always consistent
+    let round_up = if (ratio % (U256::one() << 32)).is_zero() {
+        0
+    } else {
+        1
+    };
+    ((ratio >> 32) + round_up).try_into()
+}
+
+/// @notice Calculates the greatest tick value such that get_sqrt_ratio_at_tick(tick) <= ratio
+/// @dev Throws in case sqrt_price_x96 < MIN_SQRT_RATIO, as MIN_SQRT_RATIO is the lowest value get_sqrt_ratio_at_tick may
+/// ever return.
+/// @param sqrt_price_x96 The sqrt ratio for which to compute the tick as a Q64.96
+/// @return tick The greatest tick for which the ratio is less than or equal to the input ratio
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U160) -> Result<i32> {
+    // second inequality must be < because the price can never reach the price at the max tick
+    ensure!(
+        sqrt_price_x96 >= MIN_SQRT_RATIO && sqrt_price_x96 < MAX_SQRT_RATIO,
+        "R"
+    );
+    let ratio = U256::from(sqrt_price_x96) << 32;
+
+    // most significant bit, which the solidity version finds by bisection
+    let msb = ratio.bits() - 1;
+
+    let mut r = if msb >= 128 {
+        ratio >> (msb - 127)
+    } else {
+        ratio << (127 - msb)
+    };
+
+    let mut log_2 = (I256::from(msb as i128) - I256::from(128i128)) << 64;
+
+    // 14 more bits of the fractional part of log_2, one per squaring
+    for shift in (50..=63).rev() {
+        r = (r * r) >> 127;
+        let f = r >> 128;
+        log_2 = log_2 | I256::from_raw(f << shift);
+        r >>= f.as_usize();
+    }
+
+    let log_sqrt10001 = log_2 * I256::from(255738958999603826347141u128); // 128.128 number
+
+    let tick_low = i32::try_from(i128::try_from(
+        (log_sqrt10001 - I256::from(3402992956809132418596140100660247210u128)) >> 128,
+    )?)?;
+    let tick_hi = i32::try_from(i128::try_from(
+        (log_sqrt10001 + I256::from_dec_str("291339464771989622907027621153398088495")?) >> 128,
+    )?)?;
+
+    Ok(if tick_low == tick_hi {
+        tick_low
+    } else if get_sqrt_ratio_at_tick(tick_hi)? <= sqrt_price_x96 {
+        tick_hi
+    } else {
+        tick_low
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> U160 {
+        U160::from_dec_str(s).unwrap()
+    }
+
+    #[test]
+    fn sqrt_ratio_bounds() {
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK).unwrap(), MIN_SQRT_RATIO);
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK).unwrap(), MAX_SQRT_RATIO);
+        assert_eq!(MIN_SQRT_RATIO, U160::from(4295128739u128));
+        assert_eq!(
+            MAX_SQRT_RATIO,
+            dec("1461446703485210103287273052203988822378723970342")
+        );
+        assert!(get_sqrt_ratio_at_tick(MIN_TICK - 1).is_err());
+        assert!(get_sqrt_ratio_at_tick(MAX_TICK + 1).is_err());
+    }
+
+    #[test]
+    fn sqrt_ratio_known_values() {
+        assert_eq!(get_sqrt_ratio_at_tick(0).unwrap(), U160::one() << 96);
+        assert_eq!(
+            get_sqrt_ratio_at_tick(MIN_TICK + 1).unwrap(),
+            U160::from(4295343490u128)
+        );
+        assert_eq!(
+            get_sqrt_ratio_at_tick(MAX_TICK - 1).unwrap(),
+            dec("1461373636630004318706518188784493106690254656249")
+        );
+    }
+
+    #[test]
+    fn tick_at_bounds() {
+        assert_eq!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO).unwrap(), MIN_TICK);
+        assert_eq!(
+            get_tick_at_sqrt_ratio(U160::from(4295343490u128)).unwrap(),
+            MIN_TICK + 1
+        );
+        assert_eq!(
+            get_tick_at_sqrt_ratio(MAX_SQRT_RATIO - U160::one()).unwrap(),
+            MAX_TICK - 1
+        );
+        assert_eq!(
+            get_tick_at_sqrt_ratio(dec("1461373636630004318706518188784493106690254656249"))
+                .unwrap(),
+            MAX_TICK - 1
+        );
+        assert!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO - U160::one()).is_err());
+        assert!(get_tick_at_sqrt_ratio(MAX_SQRT_RATIO).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let ticks = (MIN_TICK + 1..MAX_TICK)
+            .step_by(7919)
+            .chain([-60, -1, 0, 1, 60, 887271]);
+        for tick in ticks {
+            let ratio = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(get_tick_at_sqrt_ratio(ratio).unwrap(), tick);
+            assert_eq!(
+                get_tick_at_sqrt_ratio(ratio - U160::one()).unwrap(),
+                tick - 1
+            );
+        }
+    }
+}
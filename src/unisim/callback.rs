@@ -0,0 +1,101 @@
+use crate::solidints::{I256::I256, U256};
+use anyhow::{anyhow, bail, Result};
+
+/// Whoever calls into a pool, standing in for IUniswapV3SwapCallback, IUniswapV3MintCallback and
+/// IUniswapV3FlashCallback. The pool hands over tokens first, then asks the caller to pay, then checks its
+/// balances, so each callback returns how much of token0 and token1 it actually transferred to the pool.
+/// Callbacks can't get back into the pool they were called from, it's locked onchain anyway.
+/// The defaults revert, like calling a contract that doesn't implement the interface.
+pub trait UniV3Callback {
+    /// Called after executing a swap
+    /// * `amount_0_delta` The amount of token0 that was sent (negative) or must be received (positive) by the pool
+    /// * `amount_1_delta` The amount of token1 that was sent (negative) or must be received (positive) by the pool
+    ///
+    /// returns the amounts of token0 and token1 transferred to the pool
+    fn uniswap_v3_swap_callback(
+        &mut self,
+        amount_0_delta: I256,
+        amount_1_delta: I256,
+    ) -> Result<(U256, U256)> {
+        let _ = (amount_0_delta, amount_1_delta);
+        bail!("swap callback not implemented")
+    }
+
+    /// Called after minting liquidity to a position
+    /// * `amount_0_owed` The amount of token0 due to the pool for the minted liquidity
+    /// * `amount_1_owed` The amount of token1 due to the pool for the minted liquidity
+    ///
+    /// returns the amounts of token0 and token1 transferred to the pool
+    fn uniswap_v3_mint_callback(
+        &mut self,
+        amount_0_owed: U256,
+        amount_1_owed: U256,
+    ) -> Result<(U256, U256)> {
+        let _ = (amount_0_owed, amount_1_owed);
+        bail!("mint callback not implemented")
+    }
+
+    /// Called after transferring the flash loaned tokens
+    /// * `amount_0` The amount of token0 that was lent, and has to be repaid
+    /// * `amount_1` The amount of token1 that was lent, and has to be repaid
+    /// * `fee_0` The fee amount in token0 due to the pool by the end of the flash
+    /// * `fee_1` The fee amount in token1 due to the pool by the end of the flash
+    ///
+    /// returns the amounts of token0 and token1 transferred to the pool
+    fn uniswap_v3_flash_callback(
+        &mut self,
+        amount_0: U256,
+        amount_1: U256,
+        fee_0: U256,
+        fee_1: U256,
+    ) -> Result<(U256, U256)> {
+        let _ = (amount_0, amount_1, fee_0, fee_1);
+        bail!("flash callback not implemented")
+    }
+}
+
+/// A caller that always pays exactly what the pool asks for, like the periphery contracts do
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PayExact;
+
+impl UniV3Callback for PayExact {
+    fn uniswap_v3_swap_callback(
+        &mut self,
+        amount_0_delta: I256,
+        amount_1_delta: I256,
+    ) -> Result<(U256, U256)> {
+        let owed = |delta: I256| {
+            if delta.is_positive() {
+                delta.into_raw()
+            } else {
+                U256::zero()
+            }
+        };
+        Ok((owed(amount_0_delta), owed(amount_1_delta)))
+    }
+
+    fn uniswap_v3_mint_callback(
+        &mut self,
+        amount_0_owed: U256,
+        amount_1_owed: U256,
+    ) -> Result<(U256, U256)> {
+        Ok((amount_0_owed, amount_1_owed))
+    }
+
+    fn uniswap_v3_flash_callback(
+        &mut self,
+        amount_0: U256,
+        amount_1: U256,
+        fee_0: U256,
+        fee_1: U256,
+    ) -> Result<(U256, U256)> {
+        Ok((
+            amount_0
+                .checked_add(fee_0)
+                .ok_or(anyhow!("flash repayment of token0 overflowed"))?,
+            amount_1
+                .checked_add(fee_1)
+                .ok_or(anyhow!("flash repayment of token1 overflowed"))?,
+        ))
+    }
+}
@@ -1,48 +1,155 @@
+use crate::solidints::U256;
+use crate::solidmath::{fixed_point::Q128, full_math};
+use anyhow::{anyhow, Result};
 use std::ops::{Add, Sub};
 
-#[derive(Debug, Clone, Default)]
-pub struct Fee {
-    pub(crate) token_0: f64,
-    pub(crate) token_1: f64,
+/// Fee growth per unit of liquidity in token0 and token1, as Q128.128 values.
+/// Like feeGrowthGlobal/feeGrowthOutside onchain these only have relative meaning,
+/// and adding or subtracting them wraps around 2**256 by design.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FeeGrowth {
+    pub token_0: U256,
+    pub token_1: U256,
 }
-impl Fee {
-    pub(crate) fn new(token_0: f64, token_1: f64) -> Self {
+
+impl FeeGrowth {
+    pub fn new(token_0: U256, token_1: U256) -> Self {
         Self { token_0, token_1 }
     }
-    pub(crate) fn zero() -> Self {
+    pub fn zero() -> Self {
         Self {
-            token_0: 0.0,
-            token_1: 0.0,
+            token_0: U256::zero(),
+            token_1: U256::zero(),
         }
     }
+
+    /// The fee growth per unit of liquidity from charging `fee_amount` to `liquidity`,
+    /// as the swap accumulates it: mulDiv(feeAmount, Q128, liquidity)
+    pub fn from_fee_amount(fee_amount: U256, liquidity: u128) -> Result<U256> {
+        full_math::muldiv(fee_amount, *Q128, liquidity.into())
+    }
+
+    /// The tokens owed for holding `liquidity` over this much fee growth, rounded down.
+    /// Truncated to uint128 the same way Position.sol does it
+    pub fn tokens_owed(self, liquidity: u128) -> Result<(u128, u128)> {
+        Ok((
+            full_math::muldiv(self.token_0, liquidity.into(), *Q128)?.low_u128(),
+            full_math::muldiv(self.token_1, liquidity.into(), *Q128)?.low_u128(),
+        ))
+    }
 }
 
-impl Add for Fee {
+impl Add for FeeGrowth {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
         Self {
-            token_0: self.token_0 + other.token_0,
-            token_1: self.token_1 + other.token_1,
+            token_0: self.token_0.overflowing_add(other.token_0).0,
+            token_1: self.token_1.overflowing_add(other.token_1).0,
         }
     }
 }
 
-impl Sub for Fee {
+impl Sub for FeeGrowth {
     type Output = Self;
 
     fn sub(self, other: Self) -> Self::Output {
         Self {
-            token_0: self.token_0 - other.token_0,
-            token_1: self.token_1 - other.token_1,
+            token_0: self.token_0.overflowing_sub(other.token_0).0,
+            token_1: self.token_1.overflowing_sub(other.token_1).0,
+        }
+    }
+}
+
+/// The fee tiers the factory enables by default, in hundredths of a bip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeeAmount {
+    Lowest = 100,
+    Low = 500,
+    Medium = 3000,
+    High = 10000,
+}
+
+impl FeeAmount {
+    pub const ALL: [FeeAmount; 4] = [
+        FeeAmount::Lowest,
+        FeeAmount::Low,
+        FeeAmount::Medium,
+        FeeAmount::High,
+    ];
+
+    /// the swap fee in hundredths of a bip, i.e. 1e-6
+    pub fn fee(self) -> u32 {
+        self as u32
+    }
+
+    /// the tick spacing the factory pairs with this fee
+    pub fn tick_spacing(self) -> i32 {
+        match self {
+            FeeAmount::Lowest => 1,
+            FeeAmount::Low => 10,
+            FeeAmount::Medium => 60,
+            FeeAmount::High => 200,
         }
     }
 }
 
+impl TryFrom<u32> for FeeAmount {
+    type Error = anyhow::Error;
+
+    fn try_from(fee: u32) -> Result<Self> {
+        FeeAmount::ALL
+            .into_iter()
+            .find(|amount| amount.fee() == fee)
+            .ok_or(anyhow!("fee {} is not an enabled fee tier", fee))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_sub_wrap() {
+        let total = FeeGrowth::new(1.into(), 2.into()) + FeeGrowth::new(3.into(), 1.into());
+        assert_eq!(total, FeeGrowth::new(4.into(), 3.into()));
+        assert_eq!(FeeGrowth::zero(), FeeGrowth::default());
+
+        // the growth inside can underflow and wrap, and still come out right once added back up
+        let below = FeeGrowth::new(5.into(), 5.into());
+        let inside = FeeGrowth::new(2.into(), 7.into()) - below;
+        assert_eq!(inside.token_0, U256::MAX - 2);
+        assert_eq!(inside + below, FeeGrowth::new(2.into(), 7.into()));
+    }
+
+    #[test]
+    fn converts_between_amounts_and_growth() {
+        let growth = FeeGrowth::from_fee_amount(3000.into(), 4000).unwrap();
+        assert_eq!(growth, *Q128 * 3 / 4);
+        let owed = FeeGrowth::new(growth, growth * 2)
+            .tokens_owed(4000)
+            .unwrap();
+        assert_eq!(owed, (3000, 6000));
+        // rounds down
+        let growth = FeeGrowth::from_fee_amount(1.into(), 3).unwrap();
+        assert_eq!(
+            FeeGrowth::new(growth, 0.into()).tokens_owed(3).unwrap(),
+            (0, 0)
+        );
+        assert!(FeeGrowth::from_fee_amount(1.into(), 0).is_err());
+    }
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn fee_tiers() {
+        let spacings: Vec<_> = FeeAmount::ALL
+            .iter()
+            .map(|amount| (amount.fee(), amount.tick_spacing()))
+            .collect();
+        assert_eq!(
+            spacings,
+            vec![(100, 1), (500, 10), (3000, 60), (10000, 200)]
+        );
+        assert_eq!(FeeAmount::try_from(3000).unwrap(), FeeAmount::Medium);
+        assert!(FeeAmount::try_from(2500).is_err());
     }
 }
@@ -0,0 +1,438 @@
+//! What pool operations cost in gas, and who paid it.
+//! Each operation uses a configurable number of gas units, swaps a bit more for every initialized tick they cross,
+//! and the units are paid for at the gas price of the block the operation happens in, in wei.
+//! Gas prices come from anything implementing `GasPrice`, e.g. base fees from ingested blocks.
+//! Only operations that go through are charged, a revert costs nothing here.
+
+use super::callback::UniV3Callback;
+use super::{Address, Tick, UniV3Pool};
+use crate::solidints::{I256::I256, U160::U160, U256};
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+
+/// A pool operation, as far as gas is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Mint,
+    Burn,
+    Collect,
+    Swap { initialized_ticks_crossed: u32 },
+    Flash,
+}
+
+/// The gas units each operation uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GasUnits {
+    pub mint: u64,
+    pub burn: u64,
+    pub collect: u64,
+    /// a swap that stays between initialized ticks
+    pub swap: u64,
+    /// on top of `swap`, for every initialized tick crossed
+    pub per_tick_crossed: u64,
+    pub flash: u64,
+}
+
+impl Default for GasUnits {
+    /// ballpark mainnet figures for calling the pool through the periphery contracts
+    fn default() -> Self {
+        Self {
+            mint: 180_000,
+            burn: 120_000,
+            collect: 70_000,
+            swap: 110_000,
+            per_tick_crossed: 25_000,
+            flash: 80_000,
+        }
+    }
+}
+
+impl GasUnits {
+    pub fn units(&self, operation: Operation) -> Result<u64> {
+        Ok(match operation {
+            Operation::Mint => self.mint,
+            Operation::Burn => self.burn,
+            Operation::Collect => self.collect,
+            Operation::Swap {
+                initialized_ticks_crossed,
+            } => self
+                .per_tick_crossed
+                .checked_mul(initialized_ticks_crossed as u64)
+                .and_then(|crossing| crossing.checked_add(self.swap))
+                .ok_or(anyhow!("gas units overflowed"))?,
+            Operation::Flash => self.flash,
+        })
+    }
+}
+
+/// Where gas prices come from
+pub trait GasPrice {
+    /// the price of a unit of gas in a block, in wei
+    fn gas_price(&self, block_number: u64) -> Result<U256>;
+}
+
+/// The same gas price in every block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedGasPrice(pub U256);
+
+impl GasPrice for FixedGasPrice {
+    fn gas_price(&self, _block_number: u64) -> Result<U256> {
+        Ok(self.0)
+    }
+}
+
+/// Gas prices from some blocks, each holding until the next one. Blocks before the first have no price
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasPriceSeries {
+    prices: BTreeMap<u64, U256>,
+}
+
+impl GasPriceSeries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets the gas price from `block_number` on
+    pub fn insert(&mut self, block_number: u64, gas_price: U256) {
+        self.prices.insert(block_number, gas_price);
+    }
+}
+
+impl FromIterator<(u64, U256)> for GasPriceSeries {
+    fn from_iter<I: IntoIterator<Item = (u64, U256)>>(iter: I) -> Self {
+        Self {
+            prices: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl GasPrice for GasPriceSeries {
+    fn gas_price(&self, block_number: u64) -> Result<U256> {
+        self.prices
+            .range(..=block_number)
+            .next_back()
+            .map(|(_, price)| *price)
+            .ok_or(anyhow!("no gas price for block {}", block_number))
+    }
+}
+
+/// Who is acting, and in which block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sender {
+    pub account: Address,
+    pub block_number: u64,
+}
+
+/// Runs pool operations and charges their gas to whoever ran them
+#[derive(Debug, Clone)]
+pub struct GasModel<P: GasPrice> {
+    units: GasUnits,
+    prices: P,
+    // wei spent on gas so far, by account
+    charged: HashMap<Address, U256>,
+}
+
+impl<P: GasPrice> GasModel<P> {
+    pub fn new(units: GasUnits, prices: P) -> Self {
+        Self {
+            units,
+            prices,
+            charged: HashMap::new(),
+        }
+    }
+
+    pub fn units(&self) -> &GasUnits {
+        &self.units
+    }
+    pub fn prices(&self) -> &P {
+        &self.prices
+    }
+    /// everything `account` has spent on gas, in wei
+    pub fn charged(&self, account: Address) -> U256 {
+        self.charged.get(&account).copied().unwrap_or_default()
+    }
+
+    /// What an operation costs in a block, in wei
+    pub fn cost(&self, operation: Operation, block_number: u64) -> Result<U256> {
+        let gas_price = self.prices.gas_price(block_number)?;
+        gas_price
+            .checked_mul(self.units.units(operation)?.into())
+            .ok_or(anyhow!("gas cost overflowed"))
+    }
+
+    /// Charges `sender` for an operation
+    ///
+    /// returns what it cost, in wei
+    pub fn charge(&mut self, sender: Sender, operation: Operation) -> Result<U256> {
+        let cost = self.cost(operation, sender.block_number)?;
+        let charged = self.charged.entry(sender.account).or_default();
+        *charged = charged
+            .checked_add(cost)
+            .ok_or(anyhow!("gas charged overflowed"))?;
+        Ok(cost)
+    }
+
+    /// `UniV3Pool::mint` to the sender's own position, charging the sender
+    ///
+    /// returns the amounts paid into the pool and the gas cost
+    pub fn mint<C: UniV3Callback + ?Sized>(
+        &mut self,
+        pool: &mut UniV3Pool,
+        sender: Sender,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount: u128,
+        callback: &mut C,
+    ) -> Result<((U256, U256), U256)> {
+        // the price has to be there before anything happens to the pool,
+        // and if charging fails anyway the operation is rolled back like a revert
+        self.cost(Operation::Mint, sender.block_number)?;
+        pool.atomically(|pool| {
+            let amounts = pool.mint(sender.account, tick_lower, tick_upper, amount, callback)?;
+            Ok((amounts, self.charge(sender, Operation::Mint)?))
+        })
+    }
+
+    /// `UniV3Pool::burn` from the sender's own position, charging the sender
+    pub fn burn(
+        &mut self,
+        pool: &mut UniV3Pool,
+        sender: Sender,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount: u128,
+    ) -> Result<((U256, U256), U256)> {
+        self.cost(Operation::Burn, sender.block_number)?;
+        pool.atomically(|pool| {
+            let amounts = pool.burn(sender.account, tick_lower, tick_upper, amount)?;
+            Ok((amounts, self.charge(sender, Operation::Burn)?))
+        })
+    }
+
+    /// `UniV3Pool::collect` from the sender's own position, charging the sender
+    pub fn collect(
+        &mut self,
+        pool: &mut UniV3Pool,
+        sender: Sender,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount_0_requested: u128,
+        amount_1_requested: u128,
+    ) -> Result<((u128, u128), U256)> {
+        self.cost(Operation::Collect, sender.block_number)?;
+        pool.atomically(|pool| {
+            let amounts = pool.collect(
+                sender.account,
+                tick_lower,
+                tick_upper,
+                amount_0_requested,
+                amount_1_requested,
+            )?;
+            Ok((amounts, self.charge(sender, Operation::Collect)?))
+        })
+    }
+
+    /// `UniV3Pool::swap`, charging the sender for every initialized tick it crosses as well
+    pub fn swap<C: UniV3Callback + ?Sized>(
+        &mut self,
+        pool: &mut UniV3Pool,
+        sender: Sender,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: U160,
+        callback: &mut C,
+    ) -> Result<((I256, I256), U256)> {
+        // what it costs depends on the ticks crossed, so only the cheapest case can be checked up front
+        self.cost(
+            Operation::Swap {
+                initialized_ticks_crossed: 0,
+            },
+            sender.block_number,
+        )?;
+        pool.atomically(|pool| {
+            let outcome = pool.swap_with_outcome(
+                zero_for_one,
+                amount_specified,
+                sqrt_price_limit_x96,
+                callback,
+            )?;
+            let cost = self.charge(
+                sender,
+                Operation::Swap {
+                    initialized_ticks_crossed: outcome.initialized_ticks_crossed,
+                },
+            )?;
+            Ok(((outcome.amount_0, outcome.amount_1), cost))
+        })
+    }
+
+    /// `UniV3Pool::flash`, charging the sender
+    pub fn flash<C: UniV3Callback + ?Sized>(
+        &mut self,
+        pool: &mut UniV3Pool,
+        sender: Sender,
+        amount_0: U256,
+        amount_1: U256,
+        callback: &mut C,
+    ) -> Result<((U256, U256), U256)> {
+        self.cost(Operation::Flash, sender.block_number)?;
+        pool.atomically(|pool| {
+            let paid = pool.flash(amount_0, amount_1, callback)?;
+            Ok((paid, self.charge(sender, Operation::Flash)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::callback::PayExact;
+    use super::*;
+    use crate::solidmath::tick_math;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+    const GWEI: u64 = 1_000_000_000;
+
+    fn sender(block_number: u64) -> Sender {
+        Sender {
+            account: Address::from(0xc0ffeeu32),
+            block_number,
+        }
+    }
+
+    #[test]
+    fn gas_price_series_holds_until_the_next_price() {
+        let series: GasPriceSeries = [(10, U256::from(20 * GWEI)), (20, U256::from(50 * GWEI))]
+            .into_iter()
+            .collect();
+        assert!(series.gas_price(9).is_err());
+        assert_eq!(series.gas_price(10).unwrap(), U256::from(20 * GWEI));
+        assert_eq!(series.gas_price(19).unwrap(), U256::from(20 * GWEI));
+        assert_eq!(series.gas_price(1000).unwrap(), U256::from(50 * GWEI));
+    }
+
+    #[test]
+    fn charges_operations_to_the_sender() {
+        let mut series = GasPriceSeries::new();
+        series.insert(1, U256::from(10 * GWEI));
+        series.insert(3, U256::from(100 * GWEI));
+        let units = GasUnits::default();
+        let mut gas = GasModel::new(units, series);
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+
+        // no price, no mint, and the pool is left alone
+        assert!(gas
+            .mint(&mut pool, sender(0), -60, 60, E18, &mut PayExact)
+            .is_err());
+        assert_eq!(pool.liquidity(), 0);
+
+        let (_, cost) = gas
+            .mint(&mut pool, sender(1), -60, 60, E18, &mut PayExact)
+            .unwrap();
+        assert_eq!(cost, U256::from(units.mint * 10 * GWEI));
+        gas.mint(&mut pool, sender(1), -600, 600, E18, &mut PayExact)
+            .unwrap();
+
+        // a swap that crosses -60 costs one tick more than one that doesn't
+        let swap = |gas: &mut GasModel<GasPriceSeries>, pool: &mut UniV3Pool, amount: u128| {
+            gas.swap(
+                pool,
+                sender(3),
+                true,
+                I256::from(amount),
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut PayExact,
+            )
+            .unwrap()
+            .1
+        };
+        let small = swap(&mut gas, &mut pool.fork(), 1000);
+        let large = swap(&mut gas, &mut pool, E18 / 50);
+        assert_eq!(small, U256::from(units.swap * 100 * GWEI));
+        assert_eq!(
+            large - small,
+            U256::from(units.per_tick_crossed * 100 * GWEI)
+        );
+
+        let (_, cost) = gas
+            .flash(&mut pool, sender(3), 1000.into(), 0.into(), &mut PayExact)
+            .unwrap();
+        assert_eq!(cost, U256::from(units.flash * 100 * GWEI));
+
+        assert_eq!(
+            gas.charged(sender(0).account),
+            U256::from(
+                (2 * units.mint * 10
+                    + (2 * units.swap + units.per_tick_crossed + units.flash) * 100)
+                    * GWEI
+            )
+        );
+        assert_eq!(gas.charged(Address::zero()), U256::zero());
+    }
+
+    #[test]
+    fn failed_charges_roll_the_pool_back() {
+        let units = GasUnits {
+            per_tick_crossed: u64::MAX,
+            ..GasUnits::default()
+        };
+        assert!(units
+            .units(Operation::Swap {
+                initialized_ticks_crossed: 2
+            })
+            .is_err());
+        let mut gas = GasModel::new(units, FixedGasPrice(U256::from(30 * GWEI)));
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        gas.mint(&mut pool, sender(1), -60, 60, E18, &mut PayExact)
+            .unwrap();
+        gas.mint(&mut pool, sender(1), -600, 600, E18, &mut PayExact)
+            .unwrap();
+        let charged = gas.charged(sender(1).account);
+        let before = pool.state.clone();
+
+        // crossing -60 costs more gas than there is
+        assert!(gas
+            .swap(
+                &mut pool,
+                sender(1),
+                true,
+                I256::from(E18 / 50),
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut PayExact,
+            )
+            .is_err());
+        assert_eq!(pool.state, before);
+        assert_eq!(gas.charged(sender(1).account), charged);
+    }
+
+    #[test]
+    fn narrow_ranges_pay_for_rebalancing() {
+        let mut gas = GasModel::new(GasUnits::default(), FixedGasPrice(U256::from(30 * GWEI)));
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let position = (sender(1), -60, 60);
+        gas.mint(
+            &mut pool,
+            position.0,
+            position.1,
+            position.2,
+            E18,
+            &mut PayExact,
+        )
+        .unwrap();
+        gas.burn(&mut pool, position.0, position.1, position.2, E18)
+            .unwrap();
+        gas.collect(
+            &mut pool,
+            position.0,
+            position.1,
+            position.2,
+            u128::MAX,
+            u128::MAX,
+        )
+        .unwrap();
+        let units = GasUnits::default();
+        assert_eq!(
+            gas.charged(position.0.account),
+            U256::from((units.mint + units.burn + units.collect) * 30 * GWEI)
+        );
+    }
+}
@@ -3,29 +3,28 @@ use anyhow::{anyhow, Result};
 /// Add a signed liquidity delta to liquidity and revert if it overflows or underflows
 /// * x The liquidity before change
 /// * y The delta by which liquidity should be changed
+///
 /// Returns the liquidity delta
-pub fn addDelta(x: u128, y: i128) -> Result<u128> {
+pub fn add_delta(x: u128, y: i128) -> Result<u128> {
     if y < 0 {
-        let z = x - (-y as u128);
-        if !(z < x) {
-            Err(anyhow!("LS"))
-        } else {
-            Ok(z)
-        }
+        x.checked_sub(y.unsigned_abs()).ok_or(anyhow!("LS"))
     } else {
-        let z = x + (y as u128);
-        if !(z >= x) {
-            Err(anyhow!("LA"))
-        } else {
-            Ok(z)
-        }
+        x.checked_add(y as u128).ok_or(anyhow!("LA"))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn add_delta_bounds() {
+        assert_eq!(add_delta(1, 0).unwrap(), 1);
+        assert_eq!(add_delta(1, -1).unwrap(), 0);
+        assert_eq!(add_delta(1, 1).unwrap(), 2);
+        assert_eq!(add_delta(u128::MAX, i128::MIN).unwrap(), u128::MAX >> 1);
+        assert_eq!(add_delta(u128::MAX - 15, 15).unwrap(), u128::MAX);
+        assert_eq!(add_delta(u128::MAX - 14, 15).unwrap_err().to_string(), "LA");
+        assert_eq!(add_delta(3, -4).unwrap_err().to_string(), "LS");
     }
 }
@@ -1,355 +1,1863 @@
-// mod fee;
-// mod liq_math;
-// mod swap_math;
-// mod tick;
-
-// use crate::unisim::fee::Fee;
-// use anyhow::{anyhow, Result};
-// use std::cell::RefCell;
-// use std::collections::{HashMap, HashSet};
-// use tick::*;
-
-// type Address = String; // lmao, for now
-
-// struct PositionID {
-//     address: Address,
-//     lower_bound: Tick,
-//     upper_bound: Tick,
-// }
-
-// struct Position {
-//     // the amount of liquidity owned by this position
-//     liquidity: u128,
-//     // fee growth per unit of liquidity as of the last update to liquidity or fees owed
-//     fee_growth_inside_last: Fee,
-//     // the fees owed to the position owner in token0/token1
-//     tokensOwed0: u128,
-//     tokensOwed1: u128,
-// }
-
-// struct UniV3PoolMutableState {
-//     // current virtual liquidity within tick
-//     liquidity: f64,
-//     // the current price
-//     sqrt_price: f64,
-//     // the current tick
-//     tick: Tick,
-//     // current total fees globally
-//     fee_growth_global: Fee,
-//     // protocol fees total accumulated
-//     protocol_fees: Fee,
-//     // all the ticks with their info
-//     tick_chart: TickTable,
-//     // all the positions
-//     positions: HashMap<PositionID, Position>,
-//     // just my positions (to calculate gains/losses)
-//     my_positions: HashSet<PositionID>,
-//     // pool balance of token 0
-//     balance_0: f64,
-//     // pool balance of token 1
-//     balance_1: f64,
-// }
-
-// impl UniV3PoolMutableState {
-//     fn new(start_price: f64) -> Self {
-//         Self {
-//             liquidity: 0.0,
-//             sqrt_price: start_price.sqrt(),
-//             tick: tick::tick_from_sqrt_price(start_price.sqrt()),
-//             fee_growth_global: Fee::zero(),
-//             protocol_fees: Fee::zero(),
-//             tick_chart: HashMap::new(),
-//             positions: HashMap::new(),
-//             my_positions: HashSet::new(),
-//             balance_0: 0.0,
-//             balance_1: 0.0,
-//         }
-//     }
-// }
-
-// struct UniV3Pool {
-//     // spacing between ticks...
-//     tick_spacing: i32,
-//     // the current lp fee as a percentage of the swap fee taken on withdrawal
-//     // represented as an integer denominator (1/x)%
-//     lp_fees: f64,
-//     // fee that goes to the protocol
-//     protocol_fees: f64,
-//     // and the internal mutable state
-//     state: RefCell<UniV3PoolMutableState>,
-// }
-
-// enum SwapEvent {
-//     SetPosition {
-//         position_id: PositionID,
-//         delta_liquidity: f64,
-//     },
-//     Swap0to1 {
-//         amount: f64,
-//     },
-//     Swap1to0 {
-//         amount: f64,
-//     },
-// }
-
-// impl UniV3Pool {
-//     pub fn new(start_price: f64, tick_spacing: i32, lp_fees: f64, protocol_fees: f64) -> Self {
-//         Self {
-//             tick_spacing,
-//             lp_fees,
-//             protocol_fees,
-//             state: RefCell::new(UniV3PoolMutableState::new(start_price)),
-//         }
-//     }
-//     fn next_highest_tick(&self, tick: Tick) -> Tick {
-//         return tick + self.tick_spacing;
-//     }
-//     fn next_lowest_tick(&self, tick: Tick) -> Tick {
-//         return tick - self.tick_spacing;
-//     }
-// }
-
-// // the top level state of the swap, the results of which are recorded in storage at the end
-// struct SwapState {
-//     // the amount remaining to be swapped in/out of the input/output asset
-//     amount_specified_remaining: f64,
-//     // the amount already swapped out/in of the output/input asset
-//     amount_calculated: f64,
-//     // current sqrt(price)
-//     sqrt_price: f64,
-//     // the tick associated with the current price
-//     tick: Tick,
-//     // the global fee growth of the input token
-//     fee_growth_global: f64,
-//     // amount of input token paid as protocol fee
-//     protocol_fee: f64,
-//     // the current liquidity in range
-//     liquidity: f64,
-// }
-
-// struct StepComputations {
-//     // the price at the beginning of the step
-//     sqrt_price_start: f64,
-//     // the next tick to swap to from the current tick in the swap direction
-//     tick_next: Tick,
-//     // whether tick_next is initialized or not
-//     initialized: bool,
-//     // sqrt(price) for the next tick (1/0)
-//     sqrt_price_next: f64,
-//     // how much is being swapped in in this step
-//     amount_in: f64,
-//     // how much is being swapped out
-//     amount_out: f64,
-//     // how much fee is being paid in
-//     fee_amount: f64,
-// }
-
-// // TODO: document me!
-// impl UniV3Pool {
-//     pub fn set_position(&mut self, position_id: PositionID, state: Position, mine: bool) {}
-//     pub fn get_my_holdings_value(&self, in_token_1: bool) -> f64 {
-//         return 0.0;
-//     }
-//     pub fn get_my_fees_value(&self) -> (u128, u128) {
-//         (0, 0)
-//     }
-//     pub fn mint() {}
-//     pub fn burn() {}
-//     pub fn swap(
-//         &self,
-//         zero_for_one: bool,
-//         amount_specified: f64,
-//         sqrt_price_limit: f64,
-//         block_timestamp: u32,
-//     ) -> Result<(f64, f64)> {
-//         let old_mutable_state = self.state.borrow();
-
-//         let mut state = SwapState {
-//             amount_specified_remaining: amount_specified,
-//             amount_calculated: 0.0,
-//             sqrt_price: old_mutable_state.sqrt_price,
-//             tick: old_mutable_state.tick,
-//             fee_growth_global: old_mutable_state.fee_growth_global,
-//             protocol_fee: old_mutable_state.protocol_fees,
-//             liquidity: self.state.borrow().liquidity,
-//         };
-
-//         // continue swapping as long as we haven't used the entire input/output and haven't reached the price limit
-//         while state.amount_specified_remaining >= 0.0 && state.sqrt_price != sqrt_price_limit {
-//             step.sqrt_price_start = state.sqrt_price;
-
-//             let (tick_next, initialized) = tick_bitmap.nextInitializedTickWithinOneWord(
-//                 state.tick,
-//                 self.tick_spacing,
-//                 zero_for_one,
-//             );
-
-//             // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-//             if step.tick_next < tick::MIN_TICK {
-//                 step.tick_next = tick::MIN_TICK;
-//             } else if step.tick_next > tick::MAX_TICK {
-//                 step.tick_next = tick::MAX_TICK;
-//             }
-
-//             // get the price for the next tick
-//             step.sqrt_price_next = TickMath.getSqrtRatioAtTick(step.tick_next);
-
-//             // compute values to swap to the target tick, price limit, or point where input/output amount is exhausted
-//             let (state_sqrt_price, step_amount_in, step_amount_out, step_fee_amount) =
-//                 swap_math::compute_swap_step(
-//                     state.sqrt_price,
-//                     {
-//                         let direction = if zero_for_one {
-//                             step.sqrt_price_next < sqrt_price_limit
-//                         } else {
-//                             step.sqrt_price_next > sqrt_price_limit
-//                         };
-//                         if direction {
-//                             sqrt_price_limit
-//                         } else {
-//                             step.sqrt_price_next
-//                         }
-//                     },
-//                     state.liquidity,
-//                     state.amount_specified_remaining,
-//                     fee,
-//                 );
-
-//             if exact_input {
-//                 state.amount_specified_remaining -= step.amount_in + step.fee_amount;
-//                 state.amount_calculated -= step.amount_out;
-//             } else {
-//                 state.amount_specified_remaining += step.amount_out.toInt256();
-//                 state.amount_calculated += step.amount_in + step.fee_amount;
-//             }
-
-//             // if the protocol fee is on, calculate how much is owed, decrement feeAmount, and increment protocolFee
-//             if cache.fee_protocol > 0 {
-//                 let delta = step.fee_amount / cache.fee_protocol;
-//                 step.fee_amount -= delta;
-//                 state.protocol_fee += uint128(delta);
-//             }
-
-//             // update global fee tracker
-//             if state.liquidity.is_positive() {
-//                 state.fee_growth_global += step.fee_amount.div(state.liquidity);
-//             };
-
-//             // shift tick if we reached the next price
-//             if state.sqrt_price == step.sqrt_price_next {
-//                 // if the tick is initialized, run the tick transition
-//                 if step.initialized {
-//                     // check for the placeholder value, which we replace with the actual value the first time the swap
-//                     // crosses an initialized tick
-//                     let mut liquidity_net = tick::cross(
-//                         self.tick_chart,
-//                         step.tick_next,
-//                         if zero_for_one {
-//                             Fee {
-//                                 token_0: state.fee_growth_global.token_0,
-//                                 token_1: fee_growth_global.token_1,
-//                             }
-//                         } else {
-//                             Fee {
-//                                 token_0: fee_growth_global.token_0,
-//                                 token_1: state.fee_growth_global.token_1,
-//                             }
-//                         },
-//                         cache.seconds_per_liquidity_cumulative.clone(),
-//                         cache.tick_cumulative,
-//                         cache.block_timestamp,
-//                     );
-//                     // if we're moving leftward, we interpret liquidity_net as the opposite sign
-//                     // safe because liquidity_net cannot be type(int128).min
-//                     if zero_for_one {
-//                         liquidity_net = -liquidity_net
-//                     };
-
-//                     state.liquidity =
-//                         LiquidityMath.addDelta(state.liquidity.clone(), liquidity_net);
-//                 }
-
-//                 state.tick = if zero_for_one {
-//                     step.tick_next - 1
-//                 } else {
-//                     step.tick_next
-//                 };
-//             } else if state.sqrt_price != step.sqrt_price_start {
-//                 // recompute unless we're on a lower tick boundary (i.e. already transitioned ticks), and haven't moved
-//                 state.tick = TickMath.getTickAtSqrtRatio(state.sqrt_price);
-//             }
-//         }
-
-//         // update tick if the tick change
-//         if state.tick != slot0Start.tick {
-//             (self.sqrt_price, self.tick) = (state.sqrt_price, state.tick);
-//         } else {
-//             // otherwise just update the price
-//             self.state.borrow().sqrt_price = state.sqrt_price;
-//         }
-
-//         // update liquidity if it changed
-//         if cache.liquidity_start != state.liquidity {
-//             liquidity = state.liquidity.clone()
-//         };
-
-//         // update fee growth global and, if necessary, protocol fees
-//         // overflow is acceptable, protocol has to withdraw before it hits type(uint128).max fees
-//         if zero_for_one {
-//             fee_growth_global0 = state.fee_growth_global;
-//             if state.protocol_fee.is_positive() {
-//                 protocolFees.token0 += state.protocol_fee
-//             };
-//         } else {
-//             fee_growth_global1 = state.fee_growth_global;
-//             if state.protocol_fee.is_positive() {
-//                 protocolFees.token1 += state.protocol_fee
-//             };
-//         }
-
-//         let (amount_0, amount_1) = if zero_for_one == exact_input {
-//             (
-//                 amount_specified - state.amount_specified_remaining,
-//                 state.amount_calculated,
-//             )
-//         } else {
-//             (
-//                 state.amount_calculated,
-//                 amountSpecified - state.amount_specified_remaining,
-//             )
-//         };
-
-//         // do the transfers and collect payment
-//         if zero_for_one {
-//             self.balance_1 -= amount_1;
-//             self.balance_0 += amount_0;
-//             if amount_1.is_negative() {
-//                 // TransferHelper.safeTransfer(token1, recipient, uint256(-amount_1))
-//                 self.balance_1 -= amount_1;
-//             };
-
-//             let balance_0_before = balance0();
-//             IUniswapV3SwapCallback(msg.sender).uniswapV3SwapCallback(amount_0, amount_1, data);
-//             if !(balance_0_before.add(uint256(amount_0)) <= balance0()) {
-//                 Err(anyhow!("IIA"))?
-//             }
-//         } else {
-//             if amount_0.is_negative() {
-//                 // TransferHelper.safeTransfer(token0, recipient, uint256(-amount_0))
-//                 self.balance_0 -= amount_0;
-//             };
-
-//             IUniswapV3SwapCallback(msg.sender).uniswapV3SwapCallback(amount_0, amount_1, data);
-//         }
-
-//         Ok((amount_0, amount_1))
-//     }
-// }
-
-// #[cfg(test)]
-// mod tests {
-//     #[test]
-//     fn it_works() {
-//         unimplemented!()
-//     }
-// }
+pub mod callback;
+pub mod fee;
+pub mod gas;
+pub mod liq_math;
+pub mod oracle;
+pub mod portfolio;
+pub mod position;
+pub mod quoter;
+pub mod router;
+pub mod swap_math;
+pub mod tick;
+pub mod tick_bitmap;
+pub mod v2;
+pub mod valuation;
+
+use crate::solidints::sized::Int56;
+use crate::solidints::{I256::I256, U160::U160, U256};
+use crate::solidmath::{full_math, sqrt_price_math, tick_math};
+use crate::unisim::fee::{FeeAmount, FeeGrowth};
+use anyhow::{anyhow, ensure, Result};
+use callback::UniV3Callback;
+use oracle::Observations;
+use position::{PositionId, PositionInfo, PositionTable};
+use tick::*;
+use tick_bitmap::TickBitmap;
+
+/// a solidity address
+pub type Address = U160;
+
+/// protocol fees owed to governance, in token0/token1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolFees {
+    pub token_0: u128,
+    pub token_1: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UniV3PoolMutableState {
+    // the current price
+    sqrt_price_x96: U160,
+    // the current tick
+    tick: Tick,
+    // the most-recently updated index of the observations array
+    observation_index: u16,
+    // the current maximum number of observations that are being stored
+    observation_cardinality: u16,
+    // the next maximum number of observations to store, triggered in observations.write
+    observation_cardinality_next: u16,
+    // the current protocol fee as a percentage of the swap fee taken on withdrawal
+    // represented as an integer denominator (1/x)%, token0 in the low 4 bits and token1 in the high 4 bits
+    fee_protocol: u8,
+    // current virtual liquidity within tick
+    liquidity: u128,
+    // current total fees globally
+    fee_growth_global: FeeGrowth,
+    // protocol fees total accumulated
+    protocol_fees: ProtocolFees,
+    // all the ticks with their info
+    ticks: TickTable,
+    // which ticks are initialized, so the swap can find the next one quickly
+    tick_bitmap: TickBitmap,
+    // all the positions
+    positions: PositionTable,
+    // pool balance of token 0
+    balance_0: U256,
+    // pool balance of token 1
+    balance_1: U256,
+    // the oracle's ring buffer
+    observations: Observations,
+    // the simulated block.timestamp
+    block_timestamp: u32,
+}
+
+impl UniV3PoolMutableState {
+    fn new(sqrt_price_x96: U160, block_timestamp: u32) -> Result<Self> {
+        let mut observations = Observations::new();
+        let (cardinality, cardinality_next) =
+            oracle::initialize(&mut observations, block_timestamp);
+        Ok(Self {
+            sqrt_price_x96,
+            tick: tick_math::get_tick_at_sqrt_ratio(sqrt_price_x96)?,
+            observation_index: 0,
+            observation_cardinality: cardinality,
+            observation_cardinality_next: cardinality_next,
+            fee_protocol: 0,
+            liquidity: 0,
+            fee_growth_global: FeeGrowth::zero(),
+            protocol_fees: ProtocolFees::default(),
+            ticks: TickTable::new(),
+            tick_bitmap: TickBitmap::new(),
+            positions: PositionTable::new(),
+            balance_0: U256::zero(),
+            balance_1: U256::zero(),
+            observations,
+            block_timestamp,
+        })
+    }
+}
+
+/// A simulated Uniswap V3 pool, following UniswapV3Pool.sol
+/// The tables in its state are persistent, so cloning a pool is cheap and the clones share
+/// whatever neither of them has written to since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniV3Pool {
+    // the pool's fee in hundredths of a bip, i.e. 1e-6
+    fee: u32,
+    // spacing between ticks...
+    tick_spacing: i32,
+    // the maximum amount of position liquidity that can use any tick in the range
+    max_liquidity_per_tick: u128,
+    // and the internal mutable state
+    state: UniV3PoolMutableState,
+}
+
+/// The state of a pool at some point, to go back to with `UniV3Pool::rollback`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    state: UniV3PoolMutableState,
+}
+
+// the top level state of the swap, the results of which are recorded in storage at the end
+struct SwapState {
+    // the amount remaining to be swapped in/out of the input/output asset
+    amount_specified_remaining: I256,
+    // the amount already swapped out/in of the output/input asset
+    amount_calculated: I256,
+    // current sqrt(price)
+    sqrt_price_x96: U160,
+    // the tick associated with the current price
+    tick: Tick,
+    // the global fee growth of the input token
+    fee_growth_global: U256,
+    // amount of input token paid as protocol fee
+    protocol_fee: u128,
+    // the current liquidity in range
+    liquidity: u128,
+}
+
+// what a swap did, before any tokens change hands
+struct SwapOutcome {
+    amount_0: I256,
+    amount_1: I256,
+    // how many initialized ticks the price moved across
+    initialized_ticks_crossed: u32,
+}
+
+#[derive(Default)]
+struct StepComputations {
+    // the price at the beginning of the step
+    sqrt_price_start_x96: U160,
+    // the next tick to swap to from the current tick in the swap direction
+    tick_next: Tick,
+    // whether tick_next is initialized or not
+    initialized: bool,
+    // sqrt(price) for the next tick (1/0)
+    sqrt_price_next_x96: U160,
+    // how much is being swapped in in this step
+    amount_in: U256,
+    // how much is being swapped out
+    amount_out: U256,
+    // how much fee is being paid in
+    fee_amount: U256,
+}
+
+impl UniV3Pool {
+    /// creates a pool and initializes it at the given price
+    /// * `fee` the swap fee in hundredths of a bip
+    /// * `tick_spacing` the spacing between usable ticks
+    /// * `sqrt_price_x96` the initial sqrt price of the pool as a Q64.96
+    /// * `block_timestamp` the timestamp of the block the pool is initialized in, which starts the oracle
+    pub fn new(
+        fee: u32,
+        tick_spacing: i32,
+        sqrt_price_x96: U160,
+        block_timestamp: u32,
+    ) -> Result<Self> {
+        // the same bounds the factory puts on enabled fee amounts
+        ensure!(fee < 1000000, "fee must be below 100%");
+        // tick spacing is capped at 16384 to prevent the situation where tick_spacing is so large that
+        // tick_bitmap::next_initialized_tick_within_one_word overflows int24 container from a valid tick
+        ensure!(
+            tick_spacing > 0 && tick_spacing < 16384,
+            "tick spacing must be in (0, 16384)"
+        );
+        Ok(Self {
+            fee,
+            tick_spacing,
+            max_liquidity_per_tick: tick::tick_spacing_to_max_liquidity_per_tick(tick_spacing),
+            state: UniV3PoolMutableState::new(sqrt_price_x96, block_timestamp)?,
+        })
+    }
+
+    /// creates a pool in one of the standard fee tiers, with its usual tick spacing
+    pub fn from_fee_amount(
+        fee_amount: FeeAmount,
+        sqrt_price_x96: U160,
+        block_timestamp: u32,
+    ) -> Result<Self> {
+        Self::new(
+            fee_amount.fee(),
+            fee_amount.tick_spacing(),
+            sqrt_price_x96,
+            block_timestamp,
+        )
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.fee
+    }
+    pub fn tick_spacing(&self) -> i32 {
+        self.tick_spacing
+    }
+    pub fn max_liquidity_per_tick(&self) -> u128 {
+        self.max_liquidity_per_tick
+    }
+    pub fn sqrt_price_x96(&self) -> U160 {
+        self.state.sqrt_price_x96
+    }
+    pub fn tick(&self) -> Tick {
+        self.state.tick
+    }
+    pub fn liquidity(&self) -> u128 {
+        self.state.liquidity
+    }
+    pub fn fee_growth_global(&self) -> FeeGrowth {
+        self.state.fee_growth_global
+    }
+    pub fn protocol_fees(&self) -> ProtocolFees {
+        self.state.protocol_fees
+    }
+    /// the protocol fee denominators for token0 and token1, 0 when switched off
+    pub fn fee_protocol(&self) -> (u8, u8) {
+        (self.state.fee_protocol % 16, self.state.fee_protocol >> 4)
+    }
+    pub fn ticks(&self) -> &TickTable {
+        &self.state.ticks
+    }
+    pub fn positions(&self) -> &PositionTable {
+        &self.state.positions
+    }
+    pub fn position(&self, owner: Address, tick_lower: Tick, tick_upper: Tick) -> PositionInfo {
+        position::get(&self.state.positions, owner, tick_lower, tick_upper)
+    }
+    /// the pool's token balances, i.e. what the pool contract holds of token0 and token1
+    pub fn balances(&self) -> (U256, U256) {
+        (self.state.balance_0, self.state.balance_1)
+    }
+    pub fn observations(&self) -> &Observations {
+        &self.state.observations
+    }
+    /// the oracle's (index, cardinality, cardinality_next), as in slot0
+    pub fn observation_state(&self) -> (u16, u16, u16) {
+        (
+            self.state.observation_index,
+            self.state.observation_cardinality,
+            self.state.observation_cardinality_next,
+        )
+    }
+    pub fn block_timestamp(&self) -> u32 {
+        self.state.block_timestamp
+    }
+
+    /// A copy of the pool to try things on, which leaves this one alone
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+
+    /// Saves the pool's current state, sharing its tables rather than copying them
+    pub fn snapshot(&self) -> PoolSnapshot {
+        PoolSnapshot {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Puts the pool back the way it was when `snapshot` was taken
+    /// The snapshot is kept, so the same state can be rolled back to again and again
+    pub fn rollback(&mut self, snapshot: &PoolSnapshot) {
+        self.state = snapshot.state.clone();
+    }
+
+    /// Moves the simulated block.timestamp, which timestamps every later oracle write
+    /// Only wraps around the way a uint32 timestamp does, going backwards is an error
+    pub fn set_block_timestamp(&mut self, block_timestamp: u32) -> Result<()> {
+        ensure!(
+            block_timestamp.wrapping_sub(self.state.block_timestamp) < 1 << 31,
+            "block timestamp went backwards"
+        );
+        self.state.block_timestamp = block_timestamp;
+        Ok(())
+    }
+
+    /// Increase the maximum number of price and liquidity observations that this pool will store
+    /// This method is no-op if the pool already has an observationCardinalityNext greater than or equal to
+    /// the input observationCardinalityNext.
+    /// * `observation_cardinality_next` The desired minimum number of observations for the pool to store
+    pub fn increase_observation_cardinality_next(
+        &mut self,
+        observation_cardinality_next: u16,
+    ) -> Result<()> {
+        // for the event
+        self.state.observation_cardinality_next = oracle::grow(
+            &mut self.state.observations,
+            self.state.observation_cardinality_next,
+            observation_cardinality_next,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the cumulative tick and liquidity as of each timestamp `seconds_ago` from the current block timestamp
+    /// To get a time weighted average tick or liquidity-in-range, you must call this with two values, one representing
+    /// the beginning of the period and another for the end of the period. E.g., to get the last hour time-weighted average tick,
+    /// you must call it with seconds_agos = [3600, 0].
+    /// * `seconds_agos` From how long ago each cumulative tick and liquidity value should be returned
+    ///
+    /// returns the cumulative tick values and the cumulative seconds per liquidity-in-range value as of each `seconds_agos`
+    pub fn observe(&self, seconds_agos: &[u32]) -> Result<(Vec<Int56>, Vec<U160>)> {
+        oracle::observe(
+            &self.state.observations,
+            self.state.block_timestamp,
+            seconds_agos,
+            self.state.tick,
+            self.state.observation_index,
+            self.state.liquidity,
+            self.state.observation_cardinality,
+        )
+    }
+
+    /// the current tick accumulator and seconds per liquidity accumulator
+    fn observe_now(&self) -> Result<(Int56, U160)> {
+        oracle::observe_single(
+            &self.state.observations,
+            self.state.block_timestamp,
+            0,
+            self.state.tick,
+            self.state.observation_index,
+            self.state.liquidity,
+            self.state.observation_cardinality,
+        )
+    }
+
+    /// Returns a snapshot of the tick cumulative, seconds per liquidity and seconds inside a tick range
+    /// Snapshots must only be compared to other snapshots, taken over a period for which a position existed.
+    /// I.e., snapshots cannot be compared if a position is not held for the entire period between when the first
+    /// snapshot is taken and the second snapshot is taken.
+    /// * `tick_lower` The lower tick of the range
+    /// * `tick_upper` The upper tick of the range
+    ///
+    /// returns the snapshot of the tick accumulator, seconds per liquidity and seconds inside the range
+    pub fn snapshot_cumulatives_inside(
+        &self,
+        tick_lower: Tick,
+        tick_upper: Tick,
+    ) -> Result<(Int56, U160, u32)> {
+        Self::check_ticks(tick_lower, tick_upper)?;
+
+        let lower = self
+            .state
+            .ticks
+            .get(&tick_lower)
+            .filter(|info| info.initialized)
+            .ok_or(anyhow!("tick lower not initialized"))?;
+        let upper = self
+            .state
+            .ticks
+            .get(&tick_upper)
+            .filter(|info| info.initialized)
+            .ok_or(anyhow!("tick upper not initialized"))?;
+
+        Ok(if self.state.tick < tick_lower {
+            (
+                lower
+                    .tick_cumulative_outside
+                    .wrapping_sub(upper.tick_cumulative_outside),
+                lower
+                    .seconds_per_liquidity_outside_x128
+                    .wrapping_sub(upper.seconds_per_liquidity_outside_x128),
+                lower.seconds_outside.wrapping_sub(upper.seconds_outside),
+            )
+        } else if self.state.tick < tick_upper {
+            let (tick_cumulative, seconds_per_liquidity_cumulative_x128) = self.observe_now()?;
+            (
+                tick_cumulative
+                    .wrapping_sub(lower.tick_cumulative_outside)
+                    .wrapping_sub(upper.tick_cumulative_outside),
+                seconds_per_liquidity_cumulative_x128
+                    .wrapping_sub(lower.seconds_per_liquidity_outside_x128)
+                    .wrapping_sub(upper.seconds_per_liquidity_outside_x128),
+                self.state
+                    .block_timestamp
+                    .wrapping_sub(lower.seconds_outside)
+                    .wrapping_sub(upper.seconds_outside),
+            )
+        } else {
+            (
+                upper
+                    .tick_cumulative_outside
+                    .wrapping_sub(lower.tick_cumulative_outside),
+                upper
+                    .seconds_per_liquidity_outside_x128
+                    .wrapping_sub(lower.seconds_per_liquidity_outside_x128),
+                upper.seconds_outside.wrapping_sub(lower.seconds_outside),
+            )
+        })
+    }
+
+    /// Runs an operation that either completes or leaves the pool untouched, like a reverted transaction
+    fn atomically<T>(&mut self, op: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.snapshot();
+        let result = op(self);
+        if result.is_err() {
+            self.rollback(&saved);
+        }
+        result
+    }
+
+    /// tokens transferred to the pool by a callback
+    fn receive(&mut self, amount_0: U256, amount_1: U256) -> Result<()> {
+        self.state.balance_0 = self
+            .state
+            .balance_0
+            .checked_add(amount_0)
+            .ok_or(anyhow!("pool balance overflowed"))?;
+        self.state.balance_1 = self
+            .state
+            .balance_1
+            .checked_add(amount_1)
+            .ok_or(anyhow!("pool balance overflowed"))?;
+        Ok(())
+    }
+
+    /// Common checks for valid tick inputs.
+    fn check_ticks(tick_lower: Tick, tick_upper: Tick) -> Result<()> {
+        ensure!(tick_lower < tick_upper, "TLU");
+        ensure!(tick_lower >= MIN_TICK, "TLM");
+        ensure!(tick_upper <= MAX_TICK, "TUM");
+        Ok(())
+    }
+
+    /// Positions also have to sit on the tick spacing. Onchain this is only caught when the tick bitmap is
+    /// flipped, after the ticks were already written, so check it before touching anything
+    fn check_tick_spacing(&self, tick_lower: Tick, tick_upper: Tick) -> Result<()> {
+        ensure!(
+            tick_lower % self.tick_spacing == 0 && tick_upper % self.tick_spacing == 0,
+            "tick not spaced"
+        );
+        Ok(())
+    }
+
+    /// Gets and updates a position with the given liquidity delta
+    /// * `owner` the owner of the position
+    /// * `tick_lower` the lower tick of the position's tick range
+    /// * `tick_upper` the upper tick of the position's tick range
+    /// * `liquidity_delta` the change in liquidity of the position
+    /// * `tick` the current tick, passed to avoid sloads
+    fn update_position(
+        &mut self,
+        owner: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        liquidity_delta: i128,
+        tick: Tick,
+    ) -> Result<()> {
+        let time = self.state.block_timestamp;
+        let (tick_cumulative, seconds_per_liquidity_cumulative_x128) = self.observe_now()?;
+        let state = &mut self.state;
+        // fee_growth_global, to save on sloads
+        let fee_growth_global = state.fee_growth_global;
+
+        // if we need to update the ticks, do it
+        let mut flipped_lower = false;
+        let mut flipped_upper = false;
+        if liquidity_delta != 0 {
+            flipped_lower = tick::update(
+                &mut state.ticks,
+                tick_lower,
+                tick,
+                liquidity_delta,
+                fee_growth_global,
+                seconds_per_liquidity_cumulative_x128,
+                tick_cumulative,
+                time,
+                false,
+                self.max_liquidity_per_tick,
+            )?;
+            flipped_upper = tick::update(
+                &mut state.ticks,
+                tick_upper,
+                tick,
+                liquidity_delta,
+                fee_growth_global,
+                seconds_per_liquidity_cumulative_x128,
+                tick_cumulative,
+                time,
+                true,
+                self.max_liquidity_per_tick,
+            )?;
+
+            if flipped_lower {
+                tick_bitmap::flip_tick(&mut state.tick_bitmap, tick_lower, self.tick_spacing)?;
+            }
+            if flipped_upper {
+                tick_bitmap::flip_tick(&mut state.tick_bitmap, tick_upper, self.tick_spacing)?;
+            }
+        }
+
+        let fee_growth_inside = tick::get_fee_growth_inside(
+            &state.ticks,
+            tick_lower,
+            tick_upper,
+            tick,
+            fee_growth_global,
+        )?;
+
+        let id = PositionId {
+            owner,
+            tick_lower,
+            tick_upper,
+        };
+        let mut info = position::get(&state.positions, owner, tick_lower, tick_upper);
+        position::update(&mut info, liquidity_delta, fee_growth_inside)?;
+        state.positions.insert(id, info);
+
+        // clear any tick data that is no longer needed
+        if liquidity_delta < 0 {
+            if flipped_lower {
+                tick::clear(&mut state.ticks, tick_lower);
+            }
+            if flipped_upper {
+                tick::clear(&mut state.ticks, tick_upper);
+            }
+        }
+        Ok(())
+    }
+
+    /// Effect some changes to a position
+    /// * `owner` the owner of the position
+    /// * `tick_lower` the lower tick of the position's tick range
+    /// * `tick_upper` the upper tick of the position's tick range
+    /// * `liquidity_delta` the amount of liquidity to add (positive) or remove (negative)
+    ///
+    /// returns the amount of token0 and token1 owed to the pool, negative if the pool should pay the recipient
+    fn modify_position(
+        &mut self,
+        owner: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        liquidity_delta: i128,
+    ) -> Result<(I256, I256)> {
+        Self::check_ticks(tick_lower, tick_upper)?;
+        self.check_tick_spacing(tick_lower, tick_upper)?;
+
+        let tick = self.state.tick;
+        self.update_position(owner, tick_lower, tick_upper, liquidity_delta, tick)?;
+
+        let mut amount_0 = I256::zero();
+        let mut amount_1 = I256::zero();
+        if liquidity_delta != 0 {
+            let sqrt_ratio_lower = tick_math::get_sqrt_ratio_at_tick(tick_lower)?;
+            let sqrt_ratio_upper = tick_math::get_sqrt_ratio_at_tick(tick_upper)?;
+            if tick < tick_lower {
+                // current tick is below the passed range; liquidity can only become in range by crossing from left to
+                // right, when we'll need _more_ token0 (it's becoming more valuable) so user must provide it
+                amount_0 = sqrt_price_math::get_amount0_delta(
+                    sqrt_ratio_lower,
+                    sqrt_ratio_upper,
+                    liquidity_delta,
+                )?;
+            } else if tick < tick_upper {
+                // current tick is inside the passed range
+                amount_0 = sqrt_price_math::get_amount0_delta(
+                    self.state.sqrt_price_x96,
+                    sqrt_ratio_upper,
+                    liquidity_delta,
+                )?;
+                amount_1 = sqrt_price_math::get_amount1_delta(
+                    sqrt_ratio_lower,
+                    self.state.sqrt_price_x96,
+                    liquidity_delta,
+                )?;
+
+                // write an oracle entry
+                (
+                    self.state.observation_index,
+                    self.state.observation_cardinality,
+                ) = oracle::write(
+                    &mut self.state.observations,
+                    self.state.observation_index,
+                    self.state.block_timestamp,
+                    tick,
+                    self.state.liquidity,
+                    self.state.observation_cardinality,
+                    self.state.observation_cardinality_next,
+                );
+
+                self.state.liquidity = liq_math::add_delta(self.state.liquidity, liquidity_delta)?;
+            } else {
+                // current tick is above the passed range; liquidity can only become in range by crossing from right to
+                // left, when we'll need _more_ token1 (it's becoming more valuable) so user must provide it
+                amount_1 = sqrt_price_math::get_amount1_delta(
+                    sqrt_ratio_lower,
+                    sqrt_ratio_upper,
+                    liquidity_delta,
+                )?;
+            }
+        }
+        Ok((amount_0, amount_1))
+    }
+
+    /// Adds liquidity for the given recipient/tick_lower/tick_upper position
+    /// * `recipient` The address for which the liquidity will be created
+    /// * `tick_lower` The lower tick of the position in which to add liquidity
+    /// * `tick_upper` The upper tick of the position in which to add liquidity
+    /// * `amount` The amount of liquidity to mint
+    /// * `callback` Pays the pool for the liquidity in `uniswap_v3_mint_callback`
+    ///
+    /// returns the amount of token0 and token1 that was paid to mint the given amount of liquidity
+    pub fn mint<C: UniV3Callback + ?Sized>(
+        &mut self,
+        recipient: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount: u128,
+        callback: &mut C,
+    ) -> Result<(U256, U256)> {
+        self.atomically(|pool| {
+            ensure!(amount > 0, "mint amount must be positive");
+            let liquidity_delta = i128::try_from(amount)?;
+            let (amount_0_int, amount_1_int) =
+                pool.modify_position(recipient, tick_lower, tick_upper, liquidity_delta)?;
+
+            let amount_0 = amount_0_int.into_raw();
+            let amount_1 = amount_1_int.into_raw();
+
+            let (paid_0, paid_1) = callback.uniswap_v3_mint_callback(amount_0, amount_1)?;
+            pool.receive(paid_0, paid_1)?;
+            ensure!(paid_0 >= amount_0, "M0");
+            ensure!(paid_1 >= amount_1, "M1");
+
+            Ok((amount_0, amount_1))
+        })
+    }
+
+    /// Burn liquidity from the sender and account tokens owed for the liquidity to the position
+    /// Can be used to trigger a recalculation of fees owed to a position by calling with an amount of 0
+    /// Fees must be collected separately via a call to `collect`
+    /// * `owner` The owner of the position, i.e. the msg.sender of the burn
+    /// * `tick_lower` The lower tick of the position for which to burn liquidity
+    /// * `tick_upper` The upper tick of the position for which to burn liquidity
+    /// * `amount` How much liquidity to burn
+    ///
+    /// returns the amount of token0 and token1 sent to the recipient
+    pub fn burn(
+        &mut self,
+        owner: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount: u128,
+    ) -> Result<(U256, U256)> {
+        self.atomically(|pool| pool.burn_unchecked(owner, tick_lower, tick_upper, amount))
+    }
+
+    fn burn_unchecked(
+        &mut self,
+        owner: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount: u128,
+    ) -> Result<(U256, U256)> {
+        let liquidity_delta = -i128::try_from(amount)?;
+        let (amount_0_int, amount_1_int) =
+            self.modify_position(owner, tick_lower, tick_upper, liquidity_delta)?;
+
+        let amount_0 = (-amount_0_int).into_raw();
+        let amount_1 = (-amount_1_int).into_raw();
+
+        if !amount_0.is_zero() || !amount_1.is_zero() {
+            let id = PositionId {
+                owner,
+                tick_lower,
+                tick_upper,
+            };
+            let info = self
+                .state
+                .positions
+                .get_mut(&id)
+                .ok_or(anyhow!("position not found"))?;
+            // the amounts are at most uint128, as they come from a uint128 liquidity
+            info.tokens_owed_0 = info.tokens_owed_0.wrapping_add(amount_0.low_u128());
+            info.tokens_owed_1 = info.tokens_owed_1.wrapping_add(amount_1.low_u128());
+        }
+        Ok((amount_0, amount_1))
+    }
+
+    /// Collects tokens owed to a position
+    /// Does not recompute fees earned, which must be done either via mint or burn of any amount of liquidity.
+    /// * `owner` The owner of the position, i.e. the msg.sender of the collect
+    /// * `tick_lower` The lower tick of the position for which to collect fees
+    /// * `tick_upper` The upper tick of the position for which to collect fees
+    /// * `amount_0_requested` How much token0 should be withdrawn from the fees owed
+    /// * `amount_1_requested` How much token1 should be withdrawn from the fees owed
+    ///
+    /// returns the amount of fees collected in token0 and token1
+    pub fn collect(
+        &mut self,
+        owner: Address,
+        tick_lower: Tick,
+        tick_upper: Tick,
+        amount_0_requested: u128,
+        amount_1_requested: u128,
+    ) -> Result<(u128, u128)> {
+        let id = PositionId {
+            owner,
+            tick_lower,
+            tick_upper,
+        };
+        // we don't need to check_ticks here, because invalid positions will never have non-zero tokens_owed
+        let Some(info) = self.state.positions.get_mut(&id) else {
+            return Ok((0, 0));
+        };
+
+        let amount_0 = amount_0_requested.min(info.tokens_owed_0);
+        let amount_1 = amount_1_requested.min(info.tokens_owed_1);
+
+        let balance_0 = take_from_balance(self.state.balance_0, amount_0.into())?;
+        let balance_1 = take_from_balance(self.state.balance_1, amount_1.into())?;
+        info.tokens_owed_0 -= amount_0;
+        info.tokens_owed_1 -= amount_1;
+        self.state.balance_0 = balance_0;
+        self.state.balance_1 = balance_1;
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Swap token0 for token1, or token1 for token0
+    /// * `zero_for_one` The direction of the swap, true for token0 to token1, false for token1 to token0
+    /// * `amount_specified` The amount of the swap, which implicitly configures the swap as exact input (positive), or exact output (negative)
+    /// * `sqrt_price_limit_x96` The Q64.96 sqrt price limit. If zero for one, the price cannot be less than this
+    ///   value after the swap. If one for zero, the price cannot be greater than this value after the swap
+    /// * `callback` Pays the pool for the swap in `uniswap_v3_swap_callback`
+    ///
+    /// returns the delta of the balance of token0 and token1 of the pool, exact when negative, minimum when positive
+    pub fn swap<C: UniV3Callback + ?Sized>(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: U160,
+        callback: &mut C,
+    ) -> Result<(I256, I256)> {
+        let outcome = self.swap_with_outcome(
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit_x96,
+            callback,
+        )?;
+        Ok((outcome.amount_0, outcome.amount_1))
+    }
+
+    /// `swap`, also saying how many initialized ticks it crossed
+    fn swap_with_outcome<C: UniV3Callback + ?Sized>(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: U160,
+        callback: &mut C,
+    ) -> Result<SwapOutcome> {
+        self.atomically(|pool| {
+            let outcome =
+                pool.swap_unchecked(zero_for_one, amount_specified, sqrt_price_limit_x96)?;
+            let (amount_0, amount_1) = (outcome.amount_0, outcome.amount_1);
+
+            // do the transfers and collect payment
+            let amount_in = if zero_for_one {
+                if amount_1.is_negative() {
+                    pool.state.balance_1 =
+                        take_from_balance(pool.state.balance_1, amount_1.unsigned_abs())?;
+                }
+                amount_0
+            } else {
+                if amount_0.is_negative() {
+                    pool.state.balance_0 =
+                        take_from_balance(pool.state.balance_0, amount_0.unsigned_abs())?;
+                }
+                amount_1
+            };
+
+            let (paid_0, paid_1) = callback.uniswap_v3_swap_callback(amount_0, amount_1)?;
+            pool.receive(paid_0, paid_1)?;
+            let paid_in = if zero_for_one { paid_0 } else { paid_1 };
+            ensure!(
+                amount_in.is_negative() || paid_in >= amount_in.into_raw(),
+                "IIA"
+            );
+
+            Ok(outcome)
+        })
+    }
+
+    /// the swap itself, leaving the transfers to the caller
+    fn swap_unchecked(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: U160,
+    ) -> Result<SwapOutcome> {
+        ensure!(!amount_specified.is_zero(), "AS");
+
+        let slot0_start_tick = self.state.tick;
+        let slot0_start_price = self.state.sqrt_price_x96;
+        let price_limit_ok = if zero_for_one {
+            sqrt_price_limit_x96 < slot0_start_price
+                && sqrt_price_limit_x96 > tick_math::MIN_SQRT_RATIO
+        } else {
+            sqrt_price_limit_x96 > slot0_start_price
+                && sqrt_price_limit_x96 < tick_math::MAX_SQRT_RATIO
+        };
+        ensure!(price_limit_ok, "SPL");
+
+        let liquidity_start = self.state.liquidity;
+        // the current value of the tick and seconds per liquidity accumulators, only computed if we cross an initialized tick
+        let mut latest_observation: Option<(Int56, U160)> = None;
+        let fee_protocol = if zero_for_one {
+            self.state.fee_protocol % 16
+        } else {
+            self.state.fee_protocol >> 4
+        };
+        let exact_input = amount_specified > I256::zero();
+        let mut initialized_ticks_crossed = 0;
+
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: I256::zero(),
+            sqrt_price_x96: slot0_start_price,
+            tick: slot0_start_tick,
+            fee_growth_global: if zero_for_one {
+                self.state.fee_growth_global.token_0
+            } else {
+                self.state.fee_growth_global.token_1
+            },
+            protocol_fee: 0,
+            liquidity: liquidity_start,
+        };
+
+        // continue swapping as long as we haven't used the entire input/output and haven't reached the price limit
+        while !state.amount_specified_remaining.is_zero()
+            && state.sqrt_price_x96 != sqrt_price_limit_x96
+        {
+            let mut step = StepComputations {
+                sqrt_price_start_x96: state.sqrt_price_x96,
+                ..Default::default()
+            };
+
+            (step.tick_next, step.initialized) =
+                tick_bitmap::next_initialized_tick_within_one_word(
+                    &self.state.tick_bitmap,
+                    state.tick,
+                    self.tick_spacing,
+                    zero_for_one,
+                )?;
+
+            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
+            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+
+            // get the price for the next tick
+            step.sqrt_price_next_x96 = tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+
+            // compute values to swap to the target tick, price limit, or point where input/output amount is exhausted
+            let target_is_limit = if zero_for_one {
+                step.sqrt_price_next_x96 < sqrt_price_limit_x96
+            } else {
+                step.sqrt_price_next_x96 > sqrt_price_limit_x96
+            };
+            let computed = swap_math::compute_swap_step(
+                state.sqrt_price_x96,
+                if target_is_limit {
+                    sqrt_price_limit_x96
+                } else {
+                    step.sqrt_price_next_x96
+                },
+                state.liquidity,
+                state.amount_specified_remaining,
+                self.fee,
+            )?;
+            state.sqrt_price_x96 = computed.sqrt_ratio_next_x96;
+            step.amount_in = computed.amount_in;
+            step.amount_out = computed.amount_out;
+            step.fee_amount = computed.fee_amount;
+
+            if exact_input {
+                state.amount_specified_remaining -=
+                    I256::try_from(step.amount_in + step.fee_amount)?;
+                state.amount_calculated -= I256::try_from(step.amount_out)?;
+            } else {
+                state.amount_specified_remaining += I256::try_from(step.amount_out)?;
+                state.amount_calculated += I256::try_from(step.amount_in + step.fee_amount)?;
+            }
+
+            // if the protocol fee is on, calculate how much is owed, decrement fee_amount, and increment protocol_fee
+            if fee_protocol > 0 {
+                let delta = step.fee_amount / fee_protocol;
+                step.fee_amount -= delta;
+                state.protocol_fee = state.protocol_fee.wrapping_add(delta.low_u128());
+            }
+
+            // update global fee tracker
+            if state.liquidity > 0 {
+                state.fee_growth_global = state
+                    .fee_growth_global
+                    .overflowing_add(FeeGrowth::from_fee_amount(
+                        step.fee_amount,
+                        state.liquidity,
+                    )?)
+                    .0;
+            }
+
+            // shift tick if we reached the next price
+            if state.sqrt_price_x96 == step.sqrt_price_next_x96 {
+                // if the tick is initialized, run the tick transition
+                if step.initialized {
+                    let fee_growth_global = if zero_for_one {
+                        FeeGrowth::new(
+                            state.fee_growth_global,
+                            self.state.fee_growth_global.token_1,
+                        )
+                    } else {
+                        FeeGrowth::new(
+                            self.state.fee_growth_global.token_0,
+                            state.fee_growth_global,
+                        )
+                    };
+                    // check for the placeholder value, which we replace with the actual value the first time the swap
+                    // crosses an initialized tick
+                    let (tick_cumulative, seconds_per_liquidity_cumulative_x128) =
+                        match latest_observation {
+                            Some(observation) => observation,
+                            None => *latest_observation.insert(self.observe_now()?),
+                        };
+                    let mut liquidity_net = tick::cross(
+                        &mut self.state.ticks,
+                        step.tick_next,
+                        fee_growth_global,
+                        seconds_per_liquidity_cumulative_x128,
+                        tick_cumulative,
+                        self.state.block_timestamp,
+                    )?;
+                    // if we're moving leftward, we interpret liquidity_net as the opposite sign
+                    // safe because liquidity_net cannot be type(int128).min
+                    if zero_for_one {
+                        liquidity_net = -liquidity_net
+                    };
+
+                    state.liquidity = liq_math::add_delta(state.liquidity, liquidity_net)?;
+                    initialized_ticks_crossed += 1;
+                }
+
+                state.tick = if zero_for_one {
+                    step.tick_next - 1
+                } else {
+                    step.tick_next
+                };
+            } else if state.sqrt_price_x96 != step.sqrt_price_start_x96 {
+                // recompute unless we're on a lower tick boundary (i.e. already transitioned ticks), and haven't moved
+                state.tick = tick_math::get_tick_at_sqrt_ratio(state.sqrt_price_x96)?;
+            }
+        }
+
+        // update tick and write an oracle entry if the tick change
+        if state.tick != slot0_start_tick {
+            (
+                self.state.observation_index,
+                self.state.observation_cardinality,
+            ) = oracle::write(
+                &mut self.state.observations,
+                self.state.observation_index,
+                self.state.block_timestamp,
+                slot0_start_tick,
+                liquidity_start,
+                self.state.observation_cardinality,
+                self.state.observation_cardinality_next,
+            );
+        }
+        self.state.sqrt_price_x96 = state.sqrt_price_x96;
+        self.state.tick = state.tick;
+
+        // update liquidity if it changed
+        if liquidity_start != state.liquidity {
+            self.state.liquidity = state.liquidity;
+        }
+
+        // update fee growth global and, if necessary, protocol fees
+        // overflow is acceptable, protocol has to withdraw before it hits type(uint128).max fees
+        if zero_for_one {
+            self.state.fee_growth_global.token_0 = state.fee_growth_global;
+            self.state.protocol_fees.token_0 = self
+                .state
+                .protocol_fees
+                .token_0
+                .wrapping_add(state.protocol_fee);
+        } else {
+            self.state.fee_growth_global.token_1 = state.fee_growth_global;
+            self.state.protocol_fees.token_1 = self
+                .state
+                .protocol_fees
+                .token_1
+                .wrapping_add(state.protocol_fee);
+        }
+
+        let (amount_0, amount_1) = if zero_for_one == exact_input {
+            (
+                amount_specified - state.amount_specified_remaining,
+                state.amount_calculated,
+            )
+        } else {
+            (
+                state.amount_calculated,
+                amount_specified - state.amount_specified_remaining,
+            )
+        };
+
+        Ok(SwapOutcome {
+            amount_0,
+            amount_1,
+            initialized_ticks_crossed,
+        })
+    }
+
+    /// Receive token0 and/or token1 and pay it back, plus a fee, in the callback
+    /// Can be used to donate underlying tokens pro-rata to currently in-range liquidity providers by calling
+    /// with 0 amount{0,1} and sending the donation amount(s) from the callback
+    /// * `amount_0` The amount of token0 to send
+    /// * `amount_1` The amount of token1 to send
+    /// * `callback` Repays the loan and the fee in `uniswap_v3_flash_callback`
+    ///
+    /// returns what was paid to the pool on top of the loan, in token0 and token1
+    pub fn flash<C: UniV3Callback + ?Sized>(
+        &mut self,
+        amount_0: U256,
+        amount_1: U256,
+        callback: &mut C,
+    ) -> Result<(U256, U256)> {
+        self.atomically(|pool| {
+            let liquidity = pool.state.liquidity;
+            ensure!(liquidity > 0, "L");
+
+            let fee_0 = full_math::mul_div_rounding_up(amount_0, pool.fee.into(), 1000000.into())?;
+            let fee_1 = full_math::mul_div_rounding_up(amount_1, pool.fee.into(), 1000000.into())?;
+            let balance_0_before = pool.state.balance_0;
+            let balance_1_before = pool.state.balance_1;
+
+            pool.state.balance_0 = take_from_balance(pool.state.balance_0, amount_0)?;
+            pool.state.balance_1 = take_from_balance(pool.state.balance_1, amount_1)?;
+
+            let (repaid_0, repaid_1) =
+                callback.uniswap_v3_flash_callback(amount_0, amount_1, fee_0, fee_1)?;
+            pool.receive(repaid_0, repaid_1)?;
+
+            let balance_0_after = pool.state.balance_0;
+            let balance_1_after = pool.state.balance_1;
+            ensure!(
+                balance_0_before
+                    .checked_add(fee_0)
+                    .is_some_and(|owed| owed <= balance_0_after),
+                "F0"
+            );
+            ensure!(
+                balance_1_before
+                    .checked_add(fee_1)
+                    .is_some_and(|owed| owed <= balance_1_after),
+                "F1"
+            );
+
+            // sub is safe because we know balanceAfter is gt balanceBefore by at least fee
+            let paid_0 = balance_0_after - balance_0_before;
+            let paid_1 = balance_1_after - balance_1_before;
+
+            let (fee_protocol_0, fee_protocol_1) = pool.fee_protocol();
+            if !paid_0.is_zero() {
+                let fees_0 = if fee_protocol_0 == 0 {
+                    U256::zero()
+                } else {
+                    paid_0 / fee_protocol_0
+                };
+                pool.state.protocol_fees.token_0 = pool
+                    .state
+                    .protocol_fees
+                    .token_0
+                    .wrapping_add(fees_0.low_u128());
+                pool.state.fee_growth_global.token_0 = pool
+                    .state
+                    .fee_growth_global
+                    .token_0
+                    .overflowing_add(FeeGrowth::from_fee_amount(paid_0 - fees_0, liquidity)?)
+                    .0;
+            }
+            if !paid_1.is_zero() {
+                let fees_1 = if fee_protocol_1 == 0 {
+                    U256::zero()
+                } else {
+                    paid_1 / fee_protocol_1
+                };
+                pool.state.protocol_fees.token_1 = pool
+                    .state
+                    .protocol_fees
+                    .token_1
+                    .wrapping_add(fees_1.low_u128());
+                pool.state.fee_growth_global.token_1 = pool
+                    .state
+                    .fee_growth_global
+                    .token_1
+                    .overflowing_add(FeeGrowth::from_fee_amount(paid_1 - fees_1, liquidity)?)
+                    .0;
+            }
+
+            Ok((paid_0, paid_1))
+        })
+    }
+
+    /// Set the denominator of the protocol's % share of the fees
+    /// * `fee_protocol_0` new protocol fee for token0 of the pool
+    /// * `fee_protocol_1` new protocol fee for token1 of the pool
+    pub fn set_fee_protocol(&mut self, fee_protocol_0: u8, fee_protocol_1: u8) -> Result<()> {
+        let valid = |fee_protocol: u8| fee_protocol == 0 || (4..=10).contains(&fee_protocol);
+        ensure!(
+            valid(fee_protocol_0) && valid(fee_protocol_1),
+            "invalid fee protocol"
+        );
+        self.state.fee_protocol = fee_protocol_0 + (fee_protocol_1 << 4);
+        Ok(())
+    }
+
+    /// Collect the protocol fee accrued to the pool
+    /// * `amount_0_requested` The maximum amount of token0 to send, can be 0 to collect fees in only token1
+    /// * `amount_1_requested` The maximum amount of token1 to send, can be 0 to collect fees in only token0
+    ///
+    /// returns the protocol fee collected in token0 and token1
+    pub fn collect_protocol(
+        &mut self,
+        amount_0_requested: u128,
+        amount_1_requested: u128,
+    ) -> Result<(u128, u128)> {
+        let protocol_fees = self.state.protocol_fees;
+        let mut amount_0 = amount_0_requested.min(protocol_fees.token_0);
+        let mut amount_1 = amount_1_requested.min(protocol_fees.token_1);
+
+        // ensure that the slots are not cleared, for gas savings
+        if amount_0 > 0 && amount_0 == protocol_fees.token_0 {
+            amount_0 -= 1;
+        }
+        if amount_1 > 0 && amount_1 == protocol_fees.token_1 {
+            amount_1 -= 1;
+        }
+        // both transfers have to go through before anything is written, a failed one reverts the whole call
+        let balance_0 = take_from_balance(self.state.balance_0, amount_0.into())?;
+        let balance_1 = take_from_balance(self.state.balance_1, amount_1.into())?;
+
+        self.state.protocol_fees.token_0 -= amount_0;
+        self.state.protocol_fees.token_1 -= amount_1;
+        self.state.balance_0 = balance_0;
+        self.state.balance_1 = balance_1;
+
+        Ok((amount_0, amount_1))
+    }
+}
+
+/// pays an amount of a token out of the pool
+fn take_from_balance(balance: U256, amount: U256) -> Result<U256> {
+    balance
+        .checked_sub(amount)
+        .ok_or(anyhow!("pool balance is insufficient"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::callback::PayExact;
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn wallet() -> Address {
+        Address::from(0xc0ffeeu32)
+    }
+
+    fn price_one() -> U160 {
+        U160::one() << 96
+    }
+
+    fn mint(pool: &mut UniV3Pool, tick_lower: Tick, tick_upper: Tick, liquidity: u128) {
+        pool.mint(wallet(), tick_lower, tick_upper, liquidity, &mut PayExact)
+            .unwrap();
+    }
+
+    /// burns the whole position and collects everything owed to it
+    fn withdraw(pool: &mut UniV3Pool, tick_lower: Tick, tick_upper: Tick) -> (u128, u128) {
+        let liquidity = pool.position(wallet(), tick_lower, tick_upper).liquidity;
+        pool.burn(wallet(), tick_lower, tick_upper, liquidity)
+            .unwrap();
+        pool.collect(wallet(), tick_lower, tick_upper, u128::MAX, u128::MAX)
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        assert_eq!(
+            pool.swap(
+                true,
+                I256::zero(),
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut PayExact
+            )
+            .unwrap_err()
+            .to_string(),
+            "AS"
+        );
+        assert_eq!(
+            pool.swap(true, I256::one(), price_one(), &mut PayExact)
+                .unwrap_err()
+                .to_string(),
+            "SPL"
+        );
+        assert_eq!(
+            pool.swap(false, I256::one(), tick_math::MAX_SQRT_RATIO, &mut PayExact)
+                .unwrap_err()
+                .to_string(),
+            "SPL"
+        );
+        assert!(UniV3Pool::new(3000, 60, U160::one(), 0).is_err());
+        assert!(UniV3Pool::new(1000000, 60, price_one(), 0).is_err());
+        assert!(UniV3Pool::new(3000, 0, price_one(), 0).is_err());
+        assert!(UniV3Pool::new(3000, 16384, price_one(), 0).is_err());
+    }
+
+    #[test]
+    fn fee_tier_presets() {
+        for fee_amount in FeeAmount::ALL {
+            let pool = UniV3Pool::from_fee_amount(fee_amount, price_one(), 0).unwrap();
+            assert_eq!(pool.fee(), fee_amount.fee());
+            assert_eq!(pool.tick_spacing(), fee_amount.tick_spacing());
+            assert_eq!(
+                pool.max_liquidity_per_tick(),
+                tick::tick_spacing_to_max_liquidity_per_tick(fee_amount.tick_spacing())
+            );
+        }
+    }
+
+    #[test]
+    fn mint_respects_max_liquidity_per_tick() {
+        let mut pool = UniV3Pool::from_fee_amount(FeeAmount::High, price_one(), 0).unwrap();
+        let max = pool.max_liquidity_per_tick();
+        mint(&mut pool, -200, 200, max - 10);
+        assert_eq!(
+            pool.mint(wallet(), -200, 400, 11, &mut PayExact)
+                .unwrap_err()
+                .to_string(),
+            "LO"
+        );
+        mint(&mut pool, -400, 200, 10);
+        assert_eq!(pool.ticks()[&200].liquidity_gross, max);
+    }
+
+    #[test]
+    fn single_step_matches_compute_swap_step() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        let amount = I256::from(E18 / 1000);
+        let expected = swap_math::compute_swap_step(
+            price_one(),
+            tick_math::get_sqrt_ratio_at_tick(-600).unwrap(),
+            2 * E18,
+            amount,
+            3000,
+        )
+        .unwrap();
+        let (amount_0, amount_1) = pool
+            .swap(
+                true,
+                amount,
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+        assert_eq!(amount_0, amount);
+        assert_eq!(amount_1, -I256::try_from(expected.amount_out).unwrap());
+        assert_eq!(pool.sqrt_price_x96(), expected.sqrt_ratio_next_x96);
+        assert_eq!(
+            pool.tick(),
+            tick_math::get_tick_at_sqrt_ratio(expected.sqrt_ratio_next_x96).unwrap()
+        );
+        assert_eq!(pool.liquidity(), 2 * E18);
+        assert!(!pool.fee_growth_global().token_0.is_zero());
+        assert!(pool.fee_growth_global().token_1.is_zero());
+    }
+
+    #[test]
+    fn stops_at_price_limit() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        let limit = tick_math::get_sqrt_ratio_at_tick(50).unwrap();
+        let (amount_0, amount_1) = pool
+            .swap(false, I256::from(E18), limit, &mut PayExact)
+            .unwrap();
+        assert_eq!(pool.sqrt_price_x96(), limit);
+        assert_eq!(pool.tick(), 50);
+        assert!(amount_0 < I256::zero());
+        assert!(amount_1 > I256::zero() && amount_1 < I256::from(E18));
+    }
+
+    /// UniswapV3Pool.spec: "swapping across gaps works in 1 for 0 direction"
+    #[test]
+    fn swaps_across_gap_one_for_zero() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        let liquidity = E18 / 4;
+        mint(&mut pool, 120000, 121200, liquidity);
+        pool.swap(
+            false,
+            I256::from(E18),
+            tick_math::MAX_SQRT_RATIO - U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert!(pool.tick() > 120000 && pool.tick() < 121200);
+        assert_eq!(pool.liquidity(), liquidity);
+        // the burn hands back the principal, as in the spec's Burn event
+        assert_eq!(
+            pool.burn(wallet(), 120000, 121200, liquidity).unwrap(),
+            (
+                U256::from(30027458295511u128),
+                U256::from(996999999999999999u128)
+            )
+        );
+        // and collecting adds the swap fees on top
+        assert_eq!(
+            pool.collect(wallet(), 120000, 121200, u128::MAX, u128::MAX)
+                .unwrap(),
+            (30027458295511, 999999999999999998)
+        );
+        assert_eq!(pool.tick(), 120196);
+    }
+
+    /// UniswapV3Pool.spec: "swapping across gaps works in 0 for 1 direction"
+    #[test]
+    fn swaps_across_gap_zero_for_one() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        let liquidity = E18 / 4;
+        mint(&mut pool, -121200, -120000, liquidity);
+        pool.swap(
+            true,
+            I256::from(E18),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert!(pool.tick() >= -121200 && pool.tick() < -120000);
+        assert_eq!(pool.liquidity(), liquidity);
+        // the burn hands back the principal, as in the spec's Burn event
+        assert_eq!(
+            pool.burn(wallet(), -121200, -120000, liquidity).unwrap(),
+            (
+                U256::from(996999999999999999u128),
+                U256::from(30027458295511u128)
+            )
+        );
+        // and collecting adds the swap fees on top
+        assert_eq!(
+            pool.collect(wallet(), -121200, -120000, u128::MAX, u128::MAX)
+                .unwrap(),
+            (999999999999999998, 30027458295511)
+        );
+        assert_eq!(pool.tick(), -120197);
+    }
+
+    #[test]
+    fn crosses_initialized_ticks_both_ways() {
+        let mut pool = UniV3Pool::new(500, 10, price_one(), 0).unwrap();
+        mint(&mut pool, -100, 100, E18);
+        mint(&mut pool, -1000, 1000, E18);
+        assert_eq!(pool.liquidity(), 2 * E18);
+
+        // push the price above tick 100, dropping the narrow position
+        pool.swap(
+            false,
+            I256::from(E18 / 50),
+            tick_math::get_sqrt_ratio_at_tick(200).unwrap(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.tick(), 200);
+        assert_eq!(pool.liquidity(), E18);
+        assert!(!pool.ticks()[&100].fee_growth_outside.token_1.is_zero());
+
+        // and back below -100
+        pool.swap(
+            true,
+            I256::from(E18 / 10),
+            tick_math::get_sqrt_ratio_at_tick(-200).unwrap(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.tick(), -200);
+        assert_eq!(pool.liquidity(), E18);
+
+        // exact output back into the middle
+        let (amount_0, amount_1) = pool
+            .swap(
+                false,
+                -I256::from(E18 / 100),
+                tick_math::MAX_SQRT_RATIO - U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+        assert_eq!(amount_0, -I256::from(E18 / 100));
+        assert!(amount_1 > I256::zero());
+        assert!(pool.tick() > -100);
+        assert_eq!(pool.liquidity(), 2 * E18);
+    }
+
+    #[test]
+    fn protocol_fee_is_carved_out() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        pool.set_fee_protocol(4, 4).unwrap();
+        assert_eq!(pool.fee_protocol(), (4, 4));
+        pool.swap(
+            true,
+            I256::from(E18 / 100),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        // 0.3% of 1e16, a quarter of which goes to the protocol
+        assert_eq!(pool.protocol_fees().token_0, 7500000000000);
+        assert_eq!(pool.protocol_fees().token_1, 0);
+
+        // and the LPs get the rest
+        pool.burn(wallet(), -600, 600, 0).unwrap();
+        let owed = pool.position(wallet(), -600, 600).tokens_owed_0;
+        assert!((22500000000000 - 1..=22500000000000).contains(&owed));
+    }
+
+    #[test]
+    fn fee_protocol_is_per_direction() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        assert!(pool.set_fee_protocol(3, 0).is_err());
+        assert!(pool.set_fee_protocol(0, 11).is_err());
+        pool.set_fee_protocol(0, 10).unwrap();
+        assert_eq!(pool.fee_protocol(), (0, 10));
+
+        // token0 in: protocol fee is off
+        pool.swap(
+            true,
+            I256::from(E18 / 100),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.protocol_fees(), ProtocolFees::default());
+
+        // token1 in: a tenth of the fee
+        pool.swap(
+            false,
+            I256::from(E18 / 100),
+            tick_math::MAX_SQRT_RATIO - U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.protocol_fees().token_0, 0);
+        assert_eq!(pool.protocol_fees().token_1, 2999999999999);
+
+        // switching it off again stops the accrual
+        pool.set_fee_protocol(0, 0).unwrap();
+        pool.swap(
+            false,
+            I256::from(E18 / 100),
+            tick_math::MAX_SQRT_RATIO - U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.protocol_fees().token_1, 2999999999999);
+    }
+
+    #[test]
+    fn collect_protocol_leaves_one_behind() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        pool.set_fee_protocol(6, 6).unwrap();
+        pool.swap(
+            true,
+            I256::from(E18 / 100),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.protocol_fees().token_0, 5000000000000);
+        let (balance_0, _) = pool.balances();
+
+        assert_eq!(pool.collect_protocol(1000, 1000).unwrap(), (1000, 0));
+        assert_eq!(
+            pool.collect_protocol(u128::MAX, u128::MAX).unwrap(),
+            (5000000000000 - 1000 - 1, 0)
+        );
+        assert_eq!(pool.protocol_fees().token_0, 1);
+        assert_eq!(pool.balances().0, balance_0 - 4999999999999u64);
+        assert_eq!(pool.collect_protocol(u128::MAX, 0).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn collect_protocol_is_all_or_nothing() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        pool.state.protocol_fees.token_0 = 1000;
+        pool.state.protocol_fees.token_1 = 1000;
+        // token1 can't be paid out
+        pool.state.balance_1 = U256::from(10);
+        let before = pool.state.clone();
+
+        assert_eq!(
+            pool.collect_protocol(500, 500).unwrap_err().to_string(),
+            "pool balance is insufficient"
+        );
+        assert_eq!(pool.state, before);
+    }
+
+    #[test]
+    fn mint_checks_ticks() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        let err = |r: Result<(U256, U256)>| r.unwrap_err().to_string();
+        assert_eq!(err(pool.mint(wallet(), 60, 60, 1, &mut PayExact)), "TLU");
+        assert_eq!(
+            err(pool.mint(wallet(), MIN_TICK - 1, 60, 1, &mut PayExact)),
+            "TLM"
+        );
+        assert_eq!(
+            err(pool.mint(wallet(), -60, MAX_TICK + 1, 1, &mut PayExact)),
+            "TUM"
+        );
+        assert!(pool.mint(wallet(), -60, 60, 0, &mut PayExact).is_err());
+        assert_eq!(
+            err(pool.mint(wallet(), -61, 60, 1, &mut PayExact)),
+            "tick not spaced"
+        );
+        assert_eq!(
+            err(pool.mint(wallet(), -60, 61, 1, &mut PayExact)),
+            "tick not spaced"
+        );
+        assert!(pool.ticks().is_empty());
+        assert_eq!(err(pool.burn(wallet(), -60, 60, 1)), "LS");
+    }
+
+    #[test]
+    fn mint_takes_tokens_by_range() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        // above the current price: only token0
+        let (amount_0, amount_1) = pool.mint(wallet(), 60, 120, E18, &mut PayExact).unwrap();
+        assert!(!amount_0.is_zero());
+        assert!(amount_1.is_zero());
+        assert_eq!(pool.liquidity(), 0);
+        // below: only token1
+        let (amount_0, amount_1) = pool.mint(wallet(), -120, -60, E18, &mut PayExact).unwrap();
+        assert!(amount_0.is_zero());
+        assert!(!amount_1.is_zero());
+        assert_eq!(pool.liquidity(), 0);
+        // straddling the current price: both, and the pool's liquidity goes up
+        let (amount_0, amount_1) = pool.mint(wallet(), -60, 60, E18, &mut PayExact).unwrap();
+        assert_eq!(amount_0, amount_1);
+        assert_eq!(pool.liquidity(), E18);
+        assert_eq!(pool.ticks()[&60].liquidity_gross, 2 * E18);
+        assert_eq!(pool.ticks()[&60].liquidity_net, 0);
+    }
+
+    #[test]
+    fn burn_returns_deposit_and_clears_ticks() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        let (deposit_0, deposit_1) = pool.mint(wallet(), -120, 60, E18, &mut PayExact).unwrap();
+        let (amount_0, amount_1) = pool.burn(wallet(), -120, 60, E18).unwrap();
+        // rounding is in the pool's favor
+        assert_eq!(amount_0 + 1, deposit_0);
+        assert_eq!(amount_1 + 1, deposit_1);
+        assert_eq!(pool.liquidity(), 0);
+        assert!(pool.ticks().is_empty());
+        let info = pool.position(wallet(), -120, 60);
+        assert_eq!(info.liquidity, 0);
+        assert_eq!(info.tokens_owed_0, amount_0.as_u128());
+
+        // collect is capped at what's owed, and can be taken in pieces
+        assert_eq!(pool.collect(wallet(), -120, 60, 1, 0).unwrap(), (1, 0));
+        assert_eq!(
+            pool.collect(wallet(), -120, 60, u128::MAX, u128::MAX)
+                .unwrap(),
+            (amount_0.as_u128() - 1, amount_1.as_u128())
+        );
+        assert_eq!(pool.balances(), (U256::one(), U256::one()));
+        assert_eq!(pool.collect(wallet(), -120, 60, 1, 1).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn fees_accrue_to_positions_in_range() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, E18);
+        mint(&mut pool, 600, 1200, E18);
+        pool.swap(
+            true,
+            I256::from(E18 / 100),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+
+        // a poke credits the fees, rounded down
+        pool.burn(wallet(), -600, 600, 0).unwrap();
+        let info = pool.position(wallet(), -600, 600);
+        assert!(info.tokens_owed_0 <= 30000000000000);
+        assert!(info.tokens_owed_0 >= 30000000000000 - 1);
+        assert_eq!(info.tokens_owed_1, 0);
+        assert_eq!(
+            pool.collect(wallet(), -600, 600, u128::MAX, u128::MAX)
+                .unwrap(),
+            (info.tokens_owed_0, 0)
+        );
+
+        // the out of range position earned nothing, and can't be poked once empty
+        assert_eq!(withdraw(&mut pool, 600, 1200).1, 0);
+        assert_eq!(
+            pool.burn(wallet(), 600, 1200, 0).unwrap_err().to_string(),
+            "NP"
+        );
+    }
+
+    #[test]
+    fn oracle_tracks_twap() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 1000).unwrap();
+        mint(&mut pool, -600, 600, E18);
+        pool.increase_observation_cardinality_next(4).unwrap();
+        assert_eq!(pool.observation_state(), (0, 1, 4));
+
+        pool.set_block_timestamp(1010).unwrap();
+        pool.swap(
+            false,
+            I256::from(E18),
+            tick_math::get_sqrt_ratio_at_tick(200).unwrap(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert_eq!(pool.tick(), 200);
+        assert_eq!(pool.observation_state(), (1, 4, 4));
+
+        pool.set_block_timestamp(1030).unwrap();
+        let (tick_cumulatives, seconds_per_liquidity) = pool.observe(&[30, 20, 10, 0]).unwrap();
+        assert_eq!(
+            tick_cumulatives.iter().map(|t| t.get()).collect::<Vec<_>>(),
+            vec![0, 0, 2000, 4000]
+        );
+        // tick 200 over the last 20 seconds
+        assert_eq!((tick_cumulatives[3] - tick_cumulatives[1]).get() / 20, 200);
+        assert_eq!(
+            seconds_per_liquidity[1],
+            U160::try_from((U256::from(10) << 128) / U256::from(E18)).unwrap()
+        );
+        assert_eq!(pool.observe(&[31]).unwrap_err().to_string(), "OLD");
+        assert!(pool.set_block_timestamp(1029).is_err());
+    }
+
+    #[test]
+    fn snapshot_cumulatives_inside_a_range() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -120, 120, E18);
+        assert!(pool.snapshot_cumulatives_inside(-60, 120).is_err());
+
+        pool.set_block_timestamp(50).unwrap();
+        let (tick_cumulative, _, seconds_inside) =
+            pool.snapshot_cumulatives_inside(-120, 120).unwrap();
+        assert_eq!((tick_cumulative.get(), seconds_inside), (0, 50));
+
+        // push the price above the range, the range stops accruing time
+        pool.swap(
+            false,
+            I256::from(E18),
+            tick_math::get_sqrt_ratio_at_tick(180).unwrap(),
+            &mut PayExact,
+        )
+        .unwrap();
+        pool.set_block_timestamp(80).unwrap();
+        let (_, seconds_per_liquidity, seconds_inside) =
+            pool.snapshot_cumulatives_inside(-120, 120).unwrap();
+        assert_eq!(seconds_inside, 50);
+        assert_eq!(
+            seconds_per_liquidity,
+            U160::try_from((U256::from(50) << 128) / U256::from(E18)).unwrap()
+        );
+    }
+
+    /// pays one less than the pool asks for, whatever it asks
+    struct Underpay;
+
+    impl UniV3Callback for Underpay {
+        fn uniswap_v3_swap_callback(
+            &mut self,
+            amount_0_delta: I256,
+            amount_1_delta: I256,
+        ) -> Result<(U256, U256)> {
+            let (paid_0, paid_1) =
+                PayExact.uniswap_v3_swap_callback(amount_0_delta, amount_1_delta)?;
+            Ok((
+                paid_0.saturating_sub(1.into()),
+                paid_1.saturating_sub(1.into()),
+            ))
+        }
+
+        fn uniswap_v3_mint_callback(
+            &mut self,
+            amount_0_owed: U256,
+            amount_1_owed: U256,
+        ) -> Result<(U256, U256)> {
+            Ok((amount_0_owed, amount_1_owed.saturating_sub(1.into())))
+        }
+
+        fn uniswap_v3_flash_callback(
+            &mut self,
+            amount_0: U256,
+            amount_1: U256,
+            fee_0: U256,
+            fee_1: U256,
+        ) -> Result<(U256, U256)> {
+            Ok((amount_0 + fee_0, amount_1 + fee_1 - 1))
+        }
+    }
+
+    #[test]
+    fn flash_fees_go_to_liquidity_providers() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        let err = |r: Result<(U256, U256)>| r.unwrap_err().to_string();
+        assert_eq!(err(pool.flash(0.into(), 0.into(), &mut PayExact)), "L");
+
+        mint(&mut pool, -600, 600, 2 * E18);
+        pool.set_fee_protocol(4, 0).unwrap();
+        let balances = pool.balances();
+        assert_eq!(
+            pool.flash(1000.into(), 2000.into(), &mut PayExact).unwrap(),
+            (3.into(), 6.into())
+        );
+        assert_eq!(pool.balances(), (balances.0 + 3, balances.1 + 6));
+        // a quarter of token0's fee, rounded down, is the protocol's
+        assert_eq!(pool.protocol_fees().token_0, 0);
+        assert_eq!(
+            pool.fee_growth_global(),
+            FeeGrowth::new(
+                FeeGrowth::from_fee_amount(3.into(), 2 * E18).unwrap(),
+                FeeGrowth::from_fee_amount(6.into(), 2 * E18).unwrap(),
+            )
+        );
+
+        // more than the pool holds can't be lent
+        assert!(pool.flash(balances.0 + 4, 0.into(), &mut PayExact).is_err());
+    }
+
+    #[test]
+    fn flash_checks_repayment() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        let balances = pool.balances();
+        assert_eq!(
+            pool.flash(1000.into(), 1000.into(), &mut Underpay)
+                .unwrap_err()
+                .to_string(),
+            "F1"
+        );
+        assert_eq!(pool.balances(), balances);
+        assert_eq!(pool.fee_growth_global(), FeeGrowth::zero());
+
+        // paying nothing at all is on the default callback
+        struct Nothing;
+        impl UniV3Callback for Nothing {}
+        assert!(pool.flash(1.into(), 0.into(), &mut Nothing).is_err());
+
+        // a repayment that doesn't fit a uint256 is an error, not a panic
+        assert!(PayExact
+            .uniswap_v3_flash_callback(U256::MAX, 0.into(), 1.into(), 0.into())
+            .is_err());
+    }
+
+    #[test]
+    fn callbacks_have_to_pay_or_everything_reverts() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        assert_eq!(
+            pool.mint(wallet(), -60, 60, E18, &mut Underpay)
+                .unwrap_err()
+                .to_string(),
+            "M1"
+        );
+        assert_eq!(pool.liquidity(), 0);
+        assert!(pool.ticks().is_empty());
+        assert_eq!(pool.position(wallet(), -60, 60).liquidity, 0);
+        assert_eq!(pool.balances(), (U256::zero(), U256::zero()));
+
+        mint(&mut pool, -600, 600, 2 * E18);
+        let before = pool.clone();
+        assert_eq!(
+            pool.swap(
+                true,
+                I256::from(E18 / 100),
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut Underpay,
+            )
+            .unwrap_err()
+            .to_string(),
+            "IIA"
+        );
+        assert_eq!(pool.sqrt_price_x96(), before.sqrt_price_x96());
+        assert_eq!(pool.balances(), before.balances());
+        assert_eq!(pool.fee_growth_global(), before.fee_growth_global());
+    }
+
+    #[test]
+    fn forks_share_until_written() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        mint(&mut pool, -120, 120, E18);
+
+        let mut fork = pool.fork();
+        assert_eq!(fork, pool);
+        assert!(fork.ticks().ptr_eq(pool.ticks()));
+        assert!(fork.positions().ptr_eq(pool.positions()));
+
+        fork.swap(
+            true,
+            I256::from(E18),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        assert!(fork.tick() < -600);
+        assert_eq!(pool.tick(), 0);
+        assert_eq!(pool.liquidity(), 3 * E18);
+        assert!(!fork.ticks().ptr_eq(pool.ticks()));
+        // the swap only touched the tick table
+        assert!(fork.positions().ptr_eq(pool.positions()));
+    }
+
+    #[test]
+    fn rolls_back_to_a_snapshot_any_number_of_times() {
+        let mut pool = UniV3Pool::new(3000, 60, price_one(), 0).unwrap();
+        mint(&mut pool, -600, 600, 2 * E18);
+        let snapshot = pool.snapshot();
+        let before = pool.clone();
+
+        for amount in [E18 / 100, E18 / 10, E18] {
+            pool.set_block_timestamp(100).unwrap();
+            mint(&mut pool, -60, 60, amount);
+            pool.swap(
+                false,
+                I256::from(amount),
+                tick_math::MAX_SQRT_RATIO - U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+            withdraw(&mut pool, -600, 600);
+            assert_ne!(pool, before);
+
+            pool.rollback(&snapshot);
+            assert_eq!(pool, before);
+            assert_eq!(pool.snapshot(), snapshot);
+        }
+    }
+}
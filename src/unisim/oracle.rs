@@ -0,0 +1,610 @@
+//! Oracle.sol: a ring buffer of price and liquidity observations, used to compute TWAPs.
+//!
+//! Instances of stored oracle data, "observations", are collected in the oracle array.
+//! Every pool is initialized with an oracle array length of 1. Anyone can pay the SSTOREs to increase the
+//! maximum length of the oracle array. New slots will be added when the array is fully populated.
+//! Observations are overwritten when the full length of the oracle array is populated.
+//! The most recent observation is available, independent of the length of the oracle array, by passing 0 to `observe`.
+
+use super::tick::Tick;
+use crate::solidints::sized::{Int24, Int56};
+use crate::solidints::{U160::U160, U256};
+use anyhow::{ensure, Result};
+
+/// One observation of the pool's accumulators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Observation {
+    /// the block timestamp of the observation
+    pub block_timestamp: u32,
+    /// the tick accumulator, i.e. tick * time elapsed since the pool was first initialized
+    pub tick_cumulative: Int56,
+    /// the seconds per liquidity, i.e. seconds elapsed / max(1, liquidity) since the pool was first initialized
+    pub seconds_per_liquidity_cumulative_x128: U160,
+    /// whether or not the observation is initialized
+    pub initialized: bool,
+}
+
+/// the oracle array. it only grows as far as `grow` has asked it to, unlike the fixed 65535 slots onchain
+pub type Observations = im::Vector<Observation>;
+
+/// Transforms a previous observation into a new observation, given the passage of time and the current tick and liquidity values
+/// blockTimestamp _must_ be chronologically equal to or greater than last.blockTimestamp, safe for 0 or 1 overflows
+/// # Arguments
+///
+/// * `last` The specified observation to be transformed
+/// * `block_timestamp` The timestamp of the new observation
+/// * `tick` The active tick at the time of the new observation
+/// * `liquidity` The total in-range liquidity at the time of the new observation
+///
+/// returns the newly populated observation
+fn transform(last: &Observation, block_timestamp: u32, tick: Tick, liquidity: u128) -> Observation {
+    let delta = block_timestamp.wrapping_sub(last.block_timestamp);
+    let seconds_per_liquidity = (U256::from(delta) << 128) / U256::from(liquidity.max(1));
+    // int56(tick) * delta. ticks always fit an int24, MIN_TICK..=MAX_TICK
+    let tick = Int24::wrapping_new(tick.into()).wrapping_cast::<56>();
+    Observation {
+        block_timestamp,
+        tick_cumulative: last
+            .tick_cumulative
+            .wrapping_add(tick.wrapping_mul(Int56::wrapping_new(delta.into()))),
+        seconds_per_liquidity_cumulative_x128: last
+            .seconds_per_liquidity_cumulative_x128
+            .wrapping_add(U160::from_u256_wrapping(seconds_per_liquidity)),
+        initialized: true,
+    }
+}
+
+/// Initialize the oracle array by writing the first slot. Called once for the lifecycle of the observations array
+/// # Arguments
+///
+/// * `time` The time of the oracle initialization
+///
+/// returns the number of populated elements in the oracle array, and the new length of the oracle array, independent of population
+pub fn initialize(observations: &mut Observations, time: u32) -> (u16, u16) {
+    let first = Observation {
+        block_timestamp: time,
+        tick_cumulative: Int56::zero(),
+        seconds_per_liquidity_cumulative_x128: U160::zero(),
+        initialized: true,
+    };
+    observations.clear();
+    observations.push_back(first);
+    (1, 1)
+}
+
+/// Writes an oracle observation to the array
+/// Writable at most once per block. Index represents the most recently written element. cardinality and index must be tracked externally.
+/// If the index is at the end of the allowable array length (according to cardinality), and the next cardinality
+/// is greater than the current one, cardinality may be increased. This restriction is created to preserve ordering.
+/// # Arguments
+///
+/// * `index` The index of the observation that was most recently written to the observations array
+/// * `block_timestamp` The timestamp of the new observation
+/// * `tick` The active tick at the time of the new observation
+/// * `liquidity` The total in-range liquidity at the time of the new observation
+/// * `cardinality` The number of populated elements in the oracle array
+/// * `cardinality_next` The new length of the oracle array, independent of population
+///
+/// returns the new index of the most recently written element in the oracle array, and the new cardinality of the oracle array
+pub fn write(
+    observations: &mut Observations,
+    index: u16,
+    block_timestamp: u32,
+    tick: Tick,
+    liquidity: u128,
+    cardinality: u16,
+    cardinality_next: u16,
+) -> (u16, u16) {
+    let last = observations[index as usize];
+
+    // early return if we've already written an observation this block
+    if last.block_timestamp == block_timestamp {
+        return (index, cardinality);
+    }
+
+    // if the conditions are right, we can bump the cardinality
+    let cardinality_updated = if cardinality_next > cardinality && index == cardinality - 1 {
+        cardinality_next
+    } else {
+        cardinality
+    };
+
+    let index_updated = ((index as u32 + 1) % cardinality_updated as u32) as u16;
+    observations[index_updated as usize] = transform(&last, block_timestamp, tick, liquidity);
+    (index_updated, cardinality_updated)
+}
+
+/// Prepares the oracle array to store up to `next` observations
+/// # Arguments
+///
+/// * `current` The current next cardinality of the oracle array
+/// * `next` The proposed next cardinality which will be populated in the oracle array
+///
+/// returns the next cardinality which will be populated in the oracle array
+pub fn grow(observations: &mut Observations, current: u16, next: u16) -> Result<u16> {
+    ensure!(current > 0, "I");
+    // no-op if the passed next value isn't greater than the current next value
+    if next <= current {
+        return Ok(current);
+    }
+    // store in each slot to prevent fresh SSTOREs in swaps
+    // this data will not be used because the initialized boolean is still false
+    let missing = (next as usize).saturating_sub(observations.len());
+    observations.extend(std::iter::repeat_n(
+        Observation {
+            block_timestamp: 1,
+            ..Default::default()
+        },
+        missing,
+    ));
+    Ok(next)
+}
+
+/// comparator for 32-bit timestamps
+/// safe for 0 or 1 overflows, a and b _must_ be chronologically before or equal to time
+/// # Arguments
+///
+/// * `time` A timestamp truncated to 32 bits
+/// * `a` A comparison timestamp from which to determine the relative position of `time`
+/// * `b` From which to determine the relative position of `time`
+///
+/// returns whether `a` is chronologically <= `b`
+fn lte(time: u32, a: u32, b: u32) -> bool {
+    // if there hasn't been overflow, no need to adjust
+    if a <= time && b <= time {
+        return a <= b;
+    }
+
+    let a_adjusted = if a > time {
+        a as u64
+    } else {
+        a as u64 + (1 << 32)
+    };
+    let b_adjusted = if b > time {
+        b as u64
+    } else {
+        b as u64 + (1 << 32)
+    };
+
+    a_adjusted <= b_adjusted
+}
+
+/// Fetches the observations beforeOrAt and atOrAfter a target, i.e. where [beforeOrAt, atOrAfter] is satisfied.
+/// The result may be the same observation, or adjacent observations.
+/// The answer must be contained in the array, used when the target is located within the stored observation
+/// boundaries: older than the most recent observation and younger, or the same age as, the oldest observation
+/// # Arguments
+///
+/// * `time` The current block.timestamp
+/// * `target` The timestamp at which the reserved observation should be for
+/// * `index` The index of the observation that was most recently written to the observations array
+/// * `cardinality` The number of populated elements in the oracle array
+///
+/// returns the observation recorded before, or at, the target, and the observation recorded at, or after, the target
+fn binary_search(
+    observations: &Observations,
+    time: u32,
+    target: u32,
+    index: u16,
+    cardinality: u16,
+) -> (Observation, Observation) {
+    let cardinality = cardinality as usize;
+    // oldest observation
+    let mut l = (index as usize + 1) % cardinality;
+    // newest observation
+    let mut r = l + cardinality - 1;
+    loop {
+        let i = (l + r) / 2;
+
+        let before_or_at = observations[i % cardinality];
+
+        // we've landed on an uninitialized tick, keep searching higher (more recently)
+        if !before_or_at.initialized {
+            l = i + 1;
+            continue;
+        }
+
+        let at_or_after = observations[(i + 1) % cardinality];
+
+        let target_at_or_after = lte(time, before_or_at.block_timestamp, target);
+
+        // check if we've found the answer!
+        if target_at_or_after && lte(time, target, at_or_after.block_timestamp) {
+            return (before_or_at, at_or_after);
+        }
+
+        if !target_at_or_after {
+            r = i - 1;
+        } else {
+            l = i + 1;
+        }
+    }
+}
+
+/// Fetches the observations beforeOrAt and atOrAfter a given target, i.e. where [beforeOrAt, atOrAfter] is satisfied
+/// Assumes there is at least 1 initialized observation.
+/// Used by observe_single() to compute the counterfactual accumulator values as of a given block timestamp.
+/// # Arguments
+///
+/// * `time` The current block.timestamp
+/// * `target` The timestamp at which the reserved observation should be for
+/// * `tick` The active tick at the time of the returned or simulated observation
+/// * `index` The index of the observation that was most recently written to the observations array
+/// * `liquidity` The total pool liquidity at the time of the call
+/// * `cardinality` The number of populated elements in the oracle array
+///
+/// returns the observation which occurred at, or before, the given timestamp, and the observation which occurred at, or after, the given timestamp
+fn get_surrounding_observations(
+    observations: &Observations,
+    time: u32,
+    target: u32,
+    tick: Tick,
+    index: u16,
+    liquidity: u128,
+    cardinality: u16,
+) -> Result<(Observation, Observation)> {
+    // optimistically set before to the newest observation
+    let before_or_at = observations[index as usize];
+
+    // if the target is chronologically at or after the newest observation, we can early return
+    if lte(time, before_or_at.block_timestamp, target) {
+        return Ok(if before_or_at.block_timestamp == target {
+            // if newest observation equals target, we're in the same block, so we can ignore atOrAfter
+            (before_or_at, Observation::default())
+        } else {
+            // otherwise, we need to transform
+            (
+                before_or_at,
+                transform(&before_or_at, target, tick, liquidity),
+            )
+        });
+    }
+
+    // now, set before to the oldest observation
+    let mut before_or_at = observations[(index as usize + 1) % cardinality as usize];
+    if !before_or_at.initialized {
+        before_or_at = observations[0];
+    }
+
+    // ensure that the target is chronologically at or after the oldest observation
+    ensure!(lte(time, before_or_at.block_timestamp, target), "OLD");
+
+    // if we've reached this point, we have to binary search
+    Ok(binary_search(
+        observations,
+        time,
+        target,
+        index,
+        cardinality,
+    ))
+}
+
+/// Reverts if an observation at or before the desired observation timestamp does not exist.
+/// 0 may be passed as `seconds_ago' to return the current cumulative values.
+/// If called with a timestamp falling between two observations, returns the counterfactual accumulator values
+/// at exactly the timestamp between the two observations.
+/// # Arguments
+///
+/// * `time` The current block timestamp
+/// * `seconds_ago` The amount of time to look back, in seconds, at which point to return an observation
+/// * `tick` The current tick
+/// * `index` The index of the observation that was most recently written to the observations array
+/// * `liquidity` The current in-range pool liquidity
+/// * `cardinality` The number of populated elements in the oracle array
+///
+/// returns the tick * time elapsed since the pool was first initialized, as of `seconds_ago`,
+/// and the time elapsed / max(1, liquidity) since the pool was first initialized, as of `seconds_ago`
+pub fn observe_single(
+    observations: &Observations,
+    time: u32,
+    seconds_ago: u32,
+    tick: Tick,
+    index: u16,
+    liquidity: u128,
+    cardinality: u16,
+) -> Result<(Int56, U160)> {
+    if seconds_ago == 0 {
+        let mut last = observations[index as usize];
+        if last.block_timestamp != time {
+            last = transform(&last, time, tick, liquidity);
+        }
+        return Ok((
+            last.tick_cumulative,
+            last.seconds_per_liquidity_cumulative_x128,
+        ));
+    }
+
+    let target = time.wrapping_sub(seconds_ago);
+
+    let (before_or_at, at_or_after) = get_surrounding_observations(
+        observations,
+        time,
+        target,
+        tick,
+        index,
+        liquidity,
+        cardinality,
+    )?;
+
+    if target == before_or_at.block_timestamp {
+        // we're at the left boundary
+        Ok((
+            before_or_at.tick_cumulative,
+            before_or_at.seconds_per_liquidity_cumulative_x128,
+        ))
+    } else if target == at_or_after.block_timestamp {
+        // we're at the right boundary
+        Ok((
+            at_or_after.tick_cumulative,
+            at_or_after.seconds_per_liquidity_cumulative_x128,
+        ))
+    } else {
+        // we're in the middle
+        let observation_time_delta = at_or_after
+            .block_timestamp
+            .wrapping_sub(before_or_at.block_timestamp);
+        let target_delta = target.wrapping_sub(before_or_at.block_timestamp);
+        let tick_cumulative = before_or_at.tick_cumulative.wrapping_add(
+            (at_or_after
+                .tick_cumulative
+                .wrapping_sub(before_or_at.tick_cumulative)
+                / Int56::wrapping_new(observation_time_delta.into()))
+            .wrapping_mul(Int56::wrapping_new(target_delta.into())),
+        );
+        let seconds_per_liquidity_delta = U256::from(
+            at_or_after
+                .seconds_per_liquidity_cumulative_x128
+                .wrapping_sub(before_or_at.seconds_per_liquidity_cumulative_x128),
+        ) * U256::from(target_delta)
+            / U256::from(observation_time_delta);
+        let seconds_per_liquidity = before_or_at
+            .seconds_per_liquidity_cumulative_x128
+            .wrapping_add(U160::from_u256_wrapping(seconds_per_liquidity_delta));
+        Ok((tick_cumulative, seconds_per_liquidity))
+    }
+}
+
+/// Returns the accumulator values as of each time seconds ago from the given time in the array of `seconds_agos`
+/// Reverts if `seconds_agos` > oldest observation
+/// # Arguments
+///
+/// * `time` The current block.timestamp
+/// * `seconds_agos` Each amount of time to look back, in seconds, at which point to return an observation
+/// * `tick` The current tick
+/// * `index` The index of the observation that was most recently written to the observations array
+/// * `liquidity` The current in-range pool liquidity
+/// * `cardinality` The number of populated elements in the oracle array
+///
+/// returns the tick * time elapsed since the pool was first initialized, as of each `seconds_ago`,
+/// and the cumulative seconds / max(1, liquidity) since the pool was first initialized, as of each `seconds_ago`
+pub fn observe(
+    observations: &Observations,
+    time: u32,
+    seconds_agos: &[u32],
+    tick: Tick,
+    index: u16,
+    liquidity: u128,
+    cardinality: u16,
+) -> Result<(Vec<Int56>, Vec<U160>)> {
+    ensure!(cardinality > 0, "I");
+
+    let mut tick_cumulatives = Vec::with_capacity(seconds_agos.len());
+    let mut seconds_per_liquidity_cumulative_x128s = Vec::with_capacity(seconds_agos.len());
+    for &seconds_ago in seconds_agos {
+        let (tick_cumulative, seconds_per_liquidity) = observe_single(
+            observations,
+            time,
+            seconds_ago,
+            tick,
+            index,
+            liquidity,
+            cardinality,
+        )?;
+        tick_cumulatives.push(tick_cumulative);
+        seconds_per_liquidity_cumulative_x128s.push(seconds_per_liquidity);
+    }
+    Ok((tick_cumulatives, seconds_per_liquidity_cumulative_x128s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solidmath::tick_math::MIN_TICK;
+
+    /// a little stand in for OracleTest.sol
+    struct Oracle {
+        observations: Observations,
+        time: u32,
+        tick: Tick,
+        liquidity: u128,
+        index: u16,
+        cardinality: u16,
+        cardinality_next: u16,
+    }
+
+    impl Oracle {
+        fn new(time: u32, tick: Tick, liquidity: u128) -> Self {
+            let mut observations = Observations::new();
+            let (cardinality, cardinality_next) = initialize(&mut observations, time);
+            Self {
+                observations,
+                time,
+                tick,
+                liquidity,
+                index: 0,
+                cardinality,
+                cardinality_next,
+            }
+        }
+
+        fn update(&mut self, advance_time_by: u32, tick: Tick, liquidity: u128) {
+            self.time = self.time.wrapping_add(advance_time_by);
+            (self.index, self.cardinality) = write(
+                &mut self.observations,
+                self.index,
+                self.time,
+                self.tick,
+                self.liquidity,
+                self.cardinality,
+                self.cardinality_next,
+            );
+            self.tick = tick;
+            self.liquidity = liquidity;
+        }
+
+        fn grow(&mut self, cardinality_next: u16) {
+            self.cardinality_next = grow(
+                &mut self.observations,
+                self.cardinality_next,
+                cardinality_next,
+            )
+            .unwrap();
+        }
+
+        fn observe_single(&self, seconds_ago: u32) -> Result<(Int56, U160)> {
+            observe_single(
+                &self.observations,
+                self.time,
+                seconds_ago,
+                self.tick,
+                self.index,
+                self.liquidity,
+                self.cardinality,
+            )
+        }
+    }
+
+    fn x128(seconds: u128, liquidity: u128) -> U160 {
+        U160::try_from((U256::from(seconds) << 128) / U256::from(liquidity)).unwrap()
+    }
+
+    #[test]
+    fn initialize_and_grow() {
+        let mut oracle = Oracle::new(1, 1, 1);
+        assert_eq!((oracle.cardinality, oracle.cardinality_next), (1, 1));
+        assert_eq!(
+            oracle.observations[0],
+            Observation {
+                block_timestamp: 1,
+                tick_cumulative: Int56::zero(),
+                seconds_per_liquidity_cumulative_x128: U160::zero(),
+                initialized: true,
+            }
+        );
+
+        oracle.grow(5);
+        assert_eq!(oracle.cardinality_next, 5);
+        assert_eq!(oracle.cardinality, 1);
+        assert_eq!(oracle.observations[4].block_timestamp, 1);
+        assert!(!oracle.observations[4].initialized);
+        // shrinking is a no-op
+        oracle.grow(3);
+        assert_eq!(oracle.cardinality_next, 5);
+        assert_eq!(
+            grow(&mut Observations::new(), 0, 5)
+                .unwrap_err()
+                .to_string(),
+            "I"
+        );
+    }
+
+    #[test]
+    fn write_accumulates_and_bumps_cardinality() {
+        let mut oracle = Oracle::new(0, 1, 5);
+        oracle.grow(2);
+        // writes once per block
+        oracle.update(0, 3, 2);
+        assert_eq!(oracle.index, 0);
+        assert_eq!(oracle.cardinality, 1);
+
+        oracle.update(6, 5, 2);
+        assert_eq!(oracle.index, 1);
+        assert_eq!(oracle.cardinality, 2);
+        // written with the tick and liquidity from before the update
+        assert_eq!(oracle.observations[1].tick_cumulative.get(), 18);
+        assert_eq!(
+            oracle.observations[1].seconds_per_liquidity_cumulative_x128,
+            x128(6, 2)
+        );
+
+        // and wraps around
+        oracle.update(3, 5, 2);
+        assert_eq!(oracle.index, 0);
+        assert_eq!(oracle.observations[0].block_timestamp, 9);
+        assert_eq!(oracle.observations[0].tick_cumulative.get(), 33);
+    }
+
+    #[test]
+    fn observe_interpolates_between_observations() {
+        let mut oracle = Oracle::new(5, 2, 4);
+        oracle.grow(4);
+        oracle.update(10, 6, 8); // tick 2 for 10 seconds
+        oracle.update(10, -4, 2); // tick 6 for 10 seconds
+        oracle.time += 5; // tick -4 for 5 seconds, not written yet
+
+        assert_eq!(oracle.observe_single(0).unwrap().0.get(), 20 + 60 - 20);
+        assert_eq!(oracle.observe_single(5).unwrap().0.get(), 80);
+        assert_eq!(oracle.observe_single(10).unwrap().0.get(), 50);
+        assert_eq!(oracle.observe_single(25).unwrap().0.get(), 0);
+        assert_eq!(oracle.observe_single(24).unwrap().0.get(), 2);
+        assert_eq!(oracle.observe_single(26).unwrap_err().to_string(), "OLD");
+        assert_eq!(oracle.observe_single(20).unwrap().1, x128(5, 4));
+
+        let (ticks, _) = observe(
+            &oracle.observations,
+            oracle.time,
+            &[0, 10, 25],
+            oracle.tick,
+            oracle.index,
+            oracle.liquidity,
+            oracle.cardinality,
+        )
+        .unwrap();
+        assert_eq!(
+            ticks.iter().map(|t| t.get()).collect::<Vec<_>>(),
+            vec![60, 50, 0]
+        );
+    }
+
+    #[test]
+    fn lte_handles_overflow() {
+        assert!(lte(10, 5, 5));
+        assert!(lte(10, 4, 5));
+        assert!(!lte(10, 6, 5));
+        // a is from before the timestamp wrapped around
+        assert!(lte(10, u32::MAX - 5, 5));
+        assert!(!lte(10, 5, u32::MAX - 5));
+    }
+
+    #[test]
+    fn observe_across_timestamp_overflow() {
+        let mut oracle = Oracle::new(u32::MAX - 9, 3, 1);
+        oracle.grow(3);
+        oracle.update(20, 1, 1);
+        assert_eq!(oracle.time, 10);
+        assert_eq!(oracle.observe_single(0).unwrap().0.get(), 60);
+        assert_eq!(oracle.observe_single(15).unwrap().0.get(), 15);
+        oracle.time += 10;
+        assert_eq!(oracle.observe_single(0).unwrap().0.get(), 70);
+        assert_eq!(oracle.observe_single(15).unwrap().0.get(), 45);
+    }
+
+    #[test]
+    fn tick_accumulator_wraps_at_56_bits() {
+        let last = Observation {
+            block_timestamp: 0,
+            tick_cumulative: Int56::MAX,
+            seconds_per_liquidity_cumulative_x128: U160::zero(),
+            initialized: true,
+        };
+        assert_eq!(transform(&last, 1, 1, 1).tick_cumulative, Int56::MIN);
+        let last = Observation {
+            tick_cumulative: Int56::MIN,
+            ..last
+        };
+        assert_eq!(
+            transform(&last, 2, MIN_TICK, 1).tick_cumulative.get(),
+            (1 << 55) + 2 * MIN_TICK as i128
+        );
+    }
+}
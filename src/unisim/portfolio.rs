@@ -0,0 +1,507 @@
+//! A ledger of our own positions: when they were minted, what went into them, every change to their liquidity,
+//! every collect and the gas it all cost, so returns can be split into what's been realized and what hasn't.
+//! Cost basis is tracked at average cost, so burning some of a position releases that share of its basis.
+//! Everything is valued in one numeraire at the pool's price when it happens.
+
+use super::callback::UniV3Callback;
+use super::position::PositionId;
+use super::valuation::{self, Holding, Numeraire};
+use super::UniV3Pool;
+use crate::solidints::{I256::I256, U256};
+use crate::solidmath::full_math;
+use anyhow::{anyhow, ensure, Result};
+use std::collections::HashMap;
+
+/// The transaction an action on a position happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tx {
+    pub block_number: u64,
+    /// what the transaction cost in gas, in wei
+    pub gas_paid: U256,
+}
+
+/// Something that happened to a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEvent {
+    Mint {
+        liquidity: u128,
+        amount_0: U256,
+        amount_1: U256,
+    },
+    Burn {
+        liquidity: u128,
+        amount_0: U256,
+        amount_1: U256,
+    },
+    Collect {
+        amount_0: u128,
+        amount_1: u128,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub tx: Tx,
+    pub event: PositionEvent,
+}
+
+/// Everything we know about one of our positions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionRecord {
+    pub position: PositionId,
+    /// the block it was first minted in
+    pub mint_block: u64,
+    /// the liquidity it has now
+    pub liquidity: u128,
+    /// everything ever deposited
+    pub deposited_0: U256,
+    pub deposited_1: U256,
+    /// the deposits still in the position, i.e. less the share released by burns
+    pub basis_0: U256,
+    pub basis_1: U256,
+    /// what those deposits were worth when they went in
+    pub basis_value: U256,
+    /// burned principal that hasn't been collected yet
+    pub principal_owed_0: U256,
+    pub principal_owed_1: U256,
+    /// fees collected so far
+    pub fees_collected_0: U256,
+    pub fees_collected_1: U256,
+    /// profit from burns against the basis they released, plus collected fees, valued when they happened
+    pub realized: I256,
+    /// the gas paid for everything done to the position, in wei
+    pub gas_paid: U256,
+    pub history: Vec<LedgerEntry>,
+}
+
+impl PositionRecord {
+    fn new(position: PositionId, mint_block: u64) -> Self {
+        Self {
+            position,
+            mint_block,
+            liquidity: 0,
+            deposited_0: U256::zero(),
+            deposited_1: U256::zero(),
+            basis_0: U256::zero(),
+            basis_1: U256::zero(),
+            basis_value: U256::zero(),
+            principal_owed_0: U256::zero(),
+            principal_owed_1: U256::zero(),
+            fees_collected_0: U256::zero(),
+            fees_collected_1: U256::zero(),
+            realized: I256::zero(),
+            gas_paid: U256::zero(),
+            history: Vec::new(),
+        }
+    }
+}
+
+/// Profit and loss of a position or of the whole portfolio, in the numeraire, with gas in wei on the side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pnl {
+    pub realized: I256,
+    /// what the liquidity and uncollected fees are worth now, against the basis still in the position
+    pub unrealized: I256,
+    pub gas_paid: U256,
+}
+
+/// The ledger of our positions
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    numeraire: Numeraire,
+    positions: HashMap<PositionId, PositionRecord>,
+}
+
+impl Portfolio {
+    pub fn new(numeraire: Numeraire) -> Self {
+        Self {
+            numeraire,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn numeraire(&self) -> Numeraire {
+        self.numeraire
+    }
+    pub fn positions(&self) -> &HashMap<PositionId, PositionRecord> {
+        &self.positions
+    }
+    pub fn record(&self, position: &PositionId) -> Option<&PositionRecord> {
+        self.positions.get(position)
+    }
+
+    /// What's still in each of our positions, for `valuation`
+    pub fn holdings(&self) -> Vec<Holding> {
+        self.positions
+            .values()
+            .map(|record| Holding {
+                position: record.position,
+                deposited_0: record.basis_0,
+                deposited_1: record.basis_1,
+            })
+            .collect()
+    }
+
+    /// Mints liquidity to one of our positions and records it
+    pub fn mint<C: UniV3Callback + ?Sized>(
+        &mut self,
+        pool: &mut UniV3Pool,
+        position: PositionId,
+        liquidity: u128,
+        tx: Tx,
+        callback: &mut C,
+    ) -> Result<(U256, U256)> {
+        let (amount_0, amount_1) = pool.mint(
+            position.owner,
+            position.tick_lower,
+            position.tick_upper,
+            liquidity,
+            callback,
+        )?;
+        self.record_mint(pool, position, liquidity, amount_0, amount_1, tx)?;
+        Ok((amount_0, amount_1))
+    }
+
+    /// Burns liquidity from one of our positions and records it
+    pub fn burn(
+        &mut self,
+        pool: &mut UniV3Pool,
+        position: PositionId,
+        liquidity: u128,
+        tx: Tx,
+    ) -> Result<(U256, U256)> {
+        ensure!(self.positions.contains_key(&position), "not our position");
+        let (amount_0, amount_1) = pool.burn(
+            position.owner,
+            position.tick_lower,
+            position.tick_upper,
+            liquidity,
+        )?;
+        self.record_burn(pool, position, liquidity, amount_0, amount_1, tx)?;
+        Ok((amount_0, amount_1))
+    }
+
+    /// Collects everything one of our positions is owed and records it
+    pub fn collect(
+        &mut self,
+        pool: &mut UniV3Pool,
+        position: PositionId,
+        tx: Tx,
+    ) -> Result<(u128, u128)> {
+        ensure!(self.positions.contains_key(&position), "not our position");
+        let (amount_0, amount_1) = pool.collect(
+            position.owner,
+            position.tick_lower,
+            position.tick_upper,
+            u128::MAX,
+            u128::MAX,
+        )?;
+        self.record_collect(pool, position, amount_0, amount_1, tx)?;
+        Ok((amount_0, amount_1))
+    }
+
+    /// Records a mint that has already happened, e.g. one replayed from chain. `pool` is the state right after it
+    pub fn record_mint(
+        &mut self,
+        pool: &UniV3Pool,
+        position: PositionId,
+        liquidity: u128,
+        amount_0: U256,
+        amount_1: U256,
+        tx: Tx,
+    ) -> Result<()> {
+        let value = self.value_at(pool, amount_0, amount_1)?;
+        let record = self
+            .positions
+            .entry(position)
+            .or_insert_with(|| PositionRecord::new(position, tx.block_number));
+        record.liquidity = record
+            .liquidity
+            .checked_add(liquidity)
+            .ok_or(anyhow!("LA"))?;
+        record.deposited_0 += amount_0;
+        record.deposited_1 += amount_1;
+        record.basis_0 += amount_0;
+        record.basis_1 += amount_1;
+        record.basis_value += value;
+        record.gas_paid += tx.gas_paid;
+        record.history.push(LedgerEntry {
+            tx,
+            event: PositionEvent::Mint {
+                liquidity,
+                amount_0,
+                amount_1,
+            },
+        });
+        Ok(())
+    }
+
+    /// Records a burn that has already happened. `pool` is the state right after it
+    pub fn record_burn(
+        &mut self,
+        pool: &UniV3Pool,
+        position: PositionId,
+        liquidity: u128,
+        amount_0: U256,
+        amount_1: U256,
+        tx: Tx,
+    ) -> Result<()> {
+        let value = self.value_at(pool, amount_0, amount_1)?;
+        let record = self
+            .positions
+            .get_mut(&position)
+            .ok_or(anyhow!("not our position"))?;
+        ensure!(liquidity <= record.liquidity, "LS");
+
+        // release the burned share of the basis, at average cost
+        let share = |basis: U256| {
+            if liquidity == record.liquidity {
+                Ok(basis)
+            } else {
+                full_math::muldiv(basis, U256::from(liquidity), U256::from(record.liquidity))
+            }
+        };
+        let (released_0, released_1, released_value) = (
+            share(record.basis_0)?,
+            share(record.basis_1)?,
+            share(record.basis_value)?,
+        );
+        record.basis_0 -= released_0;
+        record.basis_1 -= released_1;
+        record.basis_value -= released_value;
+        record.liquidity -= liquidity;
+
+        record.principal_owed_0 += amount_0;
+        record.principal_owed_1 += amount_1;
+        record.realized =
+            record.realized + I256::try_from(value)? - I256::try_from(released_value)?;
+        record.gas_paid += tx.gas_paid;
+        record.history.push(LedgerEntry {
+            tx,
+            event: PositionEvent::Burn {
+                liquidity,
+                amount_0,
+                amount_1,
+            },
+        });
+        Ok(())
+    }
+
+    /// Records a collect that has already happened. `pool` is the state right after it.
+    /// Whatever was burned and not collected yet comes out first, the rest is fees
+    pub fn record_collect(
+        &mut self,
+        pool: &UniV3Pool,
+        position: PositionId,
+        amount_0: u128,
+        amount_1: u128,
+        tx: Tx,
+    ) -> Result<()> {
+        let record = self
+            .positions
+            .get(&position)
+            .ok_or(anyhow!("not our position"))?;
+        let principal_0 = record.principal_owed_0.min(amount_0.into());
+        let principal_1 = record.principal_owed_1.min(amount_1.into());
+        let fees_0 = U256::from(amount_0) - principal_0;
+        let fees_1 = U256::from(amount_1) - principal_1;
+        let fees_value = self.value_at(pool, fees_0, fees_1)?;
+
+        let record = self
+            .positions
+            .get_mut(&position)
+            .ok_or(anyhow!("not our position"))?;
+        record.principal_owed_0 -= principal_0;
+        record.principal_owed_1 -= principal_1;
+        record.fees_collected_0 += fees_0;
+        record.fees_collected_1 += fees_1;
+        record.realized += I256::try_from(fees_value)?;
+        record.gas_paid += tx.gas_paid;
+        record.history.push(LedgerEntry {
+            tx,
+            event: PositionEvent::Collect { amount_0, amount_1 },
+        });
+        Ok(())
+    }
+
+    /// Profit and loss of one of our positions, marked to the pool's current state
+    pub fn pnl(&self, pool: &UniV3Pool, position: &PositionId) -> Result<Pnl> {
+        let record = self
+            .positions
+            .get(position)
+            .ok_or(anyhow!("not our position"))?;
+        let holding = Holding {
+            position: record.position,
+            deposited_0: record.basis_0,
+            deposited_1: record.basis_1,
+        };
+        let value = valuation::value_position(pool, &holding, self.numeraire)?.value;
+        // what the pool owes the position includes burned principal, which was realized when it was burned
+        let fees_0 = value.fees_0.saturating_sub(record.principal_owed_0);
+        let fees_1 = value.fees_1.saturating_sub(record.principal_owed_1);
+        let unrealized = I256::try_from(value.value + self.value_at(pool, fees_0, fees_1)?)?
+            - I256::try_from(record.basis_value)?;
+        Ok(Pnl {
+            realized: record.realized,
+            unrealized,
+            gas_paid: record.gas_paid,
+        })
+    }
+
+    /// Profit and loss of all of our positions, which all have to be in `pool`
+    pub fn total_pnl(&self, pool: &UniV3Pool) -> Result<Pnl> {
+        self.positions
+            .keys()
+            .try_fold(Pnl::default(), |total, position| {
+                let pnl = self.pnl(pool, position)?;
+                Ok(Pnl {
+                    realized: total.realized + pnl.realized,
+                    unrealized: total.unrealized + pnl.unrealized,
+                    gas_paid: total.gas_paid + pnl.gas_paid,
+                })
+            })
+    }
+
+    fn value_at(&self, pool: &UniV3Pool, amount_0: U256, amount_1: U256) -> Result<U256> {
+        valuation::mark_to_market(pool.sqrt_price_x96(), amount_0, amount_1, self.numeraire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::callback::PayExact;
+    use super::*;
+    use crate::solidints::U160::U160;
+    use crate::solidmath::tick_math;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn ours() -> PositionId {
+        PositionId {
+            owner: U160::from(0xc0ffeeu32),
+            tick_lower: -600,
+            tick_upper: 600,
+        }
+    }
+
+    fn tx(block_number: u64) -> Tx {
+        Tx {
+            block_number,
+            gas_paid: U256::from(100_000u64) * 30_000_000_000u64,
+        }
+    }
+
+    fn swap(pool: &mut UniV3Pool, zero_for_one: bool) {
+        let limit = if zero_for_one {
+            tick_math::MIN_SQRT_RATIO + U160::one()
+        } else {
+            tick_math::MAX_SQRT_RATIO - U160::one()
+        };
+        pool.swap(zero_for_one, I256::from(E18 / 10), limit, &mut PayExact)
+            .unwrap();
+    }
+
+    #[test]
+    fn records_the_lifecycle() {
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let mut portfolio = Portfolio::new(Numeraire::Token1);
+        let (deposit_0, deposit_1) = portfolio
+            .mint(&mut pool, ours(), 10 * E18, tx(10), &mut PayExact)
+            .unwrap();
+        portfolio
+            .mint(&mut pool, ours(), 10 * E18, tx(12), &mut PayExact)
+            .unwrap();
+        // someone else's liquidity isn't ours to burn through the portfolio
+        assert!(portfolio
+            .burn(
+                &mut pool,
+                PositionId {
+                    owner: U160::one(),
+                    ..ours()
+                },
+                1,
+                tx(13)
+            )
+            .is_err());
+
+        let record = portfolio.record(&ours()).unwrap();
+        assert_eq!(record.mint_block, 10);
+        assert_eq!(record.liquidity, 20 * E18);
+        assert_eq!(
+            (record.deposited_0, record.deposited_1),
+            (deposit_0 * 2, deposit_1 * 2)
+        );
+        assert_eq!(record.gas_paid, tx(0).gas_paid * 2);
+
+        portfolio.burn(&mut pool, ours(), 5 * E18, tx(20)).unwrap();
+        portfolio.collect(&mut pool, ours(), tx(21)).unwrap();
+        let record = portfolio.record(&ours()).unwrap();
+        assert_eq!(record.liquidity, 15 * E18);
+        // a quarter of the basis went with the burn
+        assert_eq!(record.basis_0, deposit_0 * 2 - deposit_0 / 2);
+        assert_eq!(record.principal_owed_0, U256::zero());
+        assert_eq!(record.fees_collected_0, U256::zero());
+        let events: Vec<_> = record
+            .history
+            .iter()
+            .map(|entry| entry.tx.block_number)
+            .collect();
+        assert_eq!(events, vec![10, 12, 20, 21]);
+        assert!(matches!(
+            record.history[3].event,
+            PositionEvent::Collect { .. }
+        ));
+        assert_eq!(portfolio.holdings().len(), 1);
+    }
+
+    #[test]
+    fn releases_large_bases_without_overflowing() {
+        let pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let mut portfolio = Portfolio::new(Numeraire::Token0);
+        let (liquidity, basis) = (1u128 << 100, U256::MAX / 4);
+        portfolio
+            .record_mint(&pool, ours(), liquidity, basis, U256::zero(), tx(1))
+            .unwrap();
+        // basis * liquidity would need well over 256 bits
+        portfolio
+            .record_burn(&pool, ours(), liquidity / 2, basis / 2, U256::zero(), tx(2))
+            .unwrap();
+        let record = portfolio.record(&ours()).unwrap();
+        assert_eq!(record.liquidity, liquidity / 2);
+        assert_eq!(record.basis_0, basis - basis / 2);
+        assert_eq!(record.principal_owed_0, basis / 2);
+    }
+
+    #[test]
+    fn splits_realized_and_unrealized() {
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let mut portfolio = Portfolio::new(Numeraire::Token1);
+        portfolio
+            .mint(&mut pool, ours(), 10 * E18, tx(1), &mut PayExact)
+            .unwrap();
+        let start = portfolio.total_pnl(&pool).unwrap();
+        assert_eq!(start.realized, I256::zero());
+        assert!(start.unrealized.abs() <= I256::from(2));
+
+        // trade back and forth: the price ends up about where it was and the position earned fees
+        swap(&mut pool, true);
+        swap(&mut pool, false);
+        let traded = portfolio.total_pnl(&pool).unwrap();
+        assert_eq!(traded.realized, I256::zero());
+        assert!(traded.unrealized.is_positive());
+
+        // taking half out and collecting realizes half the principal's pnl and all the fees
+        portfolio.burn(&mut pool, ours(), 5 * E18, tx(2)).unwrap();
+        let burned = portfolio.total_pnl(&pool).unwrap();
+        let (collected_0, collected_1) = portfolio.collect(&mut pool, ours(), tx(3)).unwrap();
+        let collected = portfolio.total_pnl(&pool).unwrap();
+        assert!(collected_0 > 0 && collected_1 > 0);
+        let record = portfolio.record(&ours()).unwrap();
+        assert!(!record.fees_collected_0.is_zero() && !record.fees_collected_1.is_zero());
+        assert!(collected.realized > burned.realized);
+        // realizing moves pnl from one column to the other, give or take rounding
+        let total = |pnl: Pnl| pnl.realized + pnl.unrealized;
+        assert!((total(collected) - total(traded)).abs() <= I256::from(4));
+        assert_eq!(collected.gas_paid, tx(0).gas_paid * 3);
+    }
+}
@@ -0,0 +1,150 @@
+use super::fee::FeeGrowth;
+use super::liq_math;
+use super::tick::Tick;
+use super::Address;
+use anyhow::{ensure, Result};
+use im::HashMap;
+
+/// Identifies a position by its owner and tick boundaries, like the keccak key in Position.sol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PositionId {
+    pub owner: Address,
+    pub tick_lower: Tick,
+    pub tick_upper: Tick,
+}
+
+/// info stored for each user's position
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PositionInfo {
+    /// the amount of liquidity owned by this position
+    pub liquidity: u128,
+    /// fee growth per unit of liquidity as of the last update to liquidity or fees owed
+    pub fee_growth_inside_last: FeeGrowth,
+    /// the fees owed to the position owner in token0/token1
+    pub tokens_owed_0: u128,
+    pub tokens_owed_1: u128,
+}
+
+/// the pool's positions, persistent like the tick table
+pub type PositionTable = HashMap<PositionId, PositionInfo>;
+
+/// Returns the info struct of a position, given an owner and position boundaries
+/// # Arguments
+///
+/// * `owner` The address of the position owner
+/// * `tick_lower` The lower tick boundary of the position
+/// * `tick_upper` The upper tick boundary of the position
+///
+/// returns the position info struct of the given owners' position, which reads as empty if it was never touched
+pub fn get(
+    table: &PositionTable,
+    owner: Address,
+    tick_lower: Tick,
+    tick_upper: Tick,
+) -> PositionInfo {
+    table
+        .get(&PositionId {
+            owner,
+            tick_lower,
+            tick_upper,
+        })
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Credits accumulated fees to a user's position
+/// # Arguments
+///
+/// * `info` The individual position to update
+/// * `liquidity_delta` The change in pool liquidity as a result of the position update
+/// * `fee_growth_inside` The all-time fee growth in token0 and token1, per unit of liquidity, inside the position's tick boundaries
+pub fn update(
+    info: &mut PositionInfo,
+    liquidity_delta: i128,
+    fee_growth_inside: FeeGrowth,
+) -> Result<()> {
+    let liquidity_next = if liquidity_delta == 0 {
+        // disallow pokes for 0 liquidity positions
+        ensure!(info.liquidity > 0, "NP");
+        info.liquidity
+    } else {
+        liq_math::add_delta(info.liquidity, liquidity_delta)?
+    };
+
+    // calculate accumulated fees
+    let (tokens_owed_0, tokens_owed_1) =
+        (fee_growth_inside - info.fee_growth_inside_last).tokens_owed(info.liquidity)?;
+
+    // update the position
+    if liquidity_delta != 0 {
+        info.liquidity = liquidity_next;
+    }
+    info.fee_growth_inside_last = fee_growth_inside;
+    if tokens_owed_0 > 0 || tokens_owed_1 > 0 {
+        // overflow is acceptable, have to withdraw before you hit type(uint128).max fees
+        info.tokens_owed_0 = info.tokens_owed_0.wrapping_add(tokens_owed_0);
+        info.tokens_owed_1 = info.tokens_owed_1.wrapping_add(tokens_owed_1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solidints::U256;
+    use crate::solidmath::fixed_point::Q128;
+
+    fn quarters(token_0: u32, token_1: u32) -> FeeGrowth {
+        FeeGrowth::new(*Q128 * token_0 / 4, *Q128 * token_1 / 4)
+    }
+
+    #[test]
+    fn update_accrues_fees_on_old_liquidity() {
+        let mut info = PositionInfo::default();
+        assert_eq!(
+            update(&mut info, 0, FeeGrowth::zero())
+                .unwrap_err()
+                .to_string(),
+            "NP"
+        );
+        update(&mut info, 100, quarters(4, 8)).unwrap();
+        assert_eq!(info.liquidity, 100);
+        assert_eq!(info.tokens_owed_0, 0);
+
+        // fees accrue on the liquidity held before the change
+        update(&mut info, 50, quarters(6, 9)).unwrap();
+        assert_eq!(info.liquidity, 150);
+        assert_eq!((info.tokens_owed_0, info.tokens_owed_1), (50, 25));
+
+        // poke
+        update(&mut info, 0, quarters(10, 9)).unwrap();
+        assert_eq!((info.tokens_owed_0, info.tokens_owed_1), (200, 25));
+        assert_eq!(info.fee_growth_inside_last, quarters(10, 9));
+
+        assert_eq!(
+            update(&mut info, -151, quarters(10, 9))
+                .unwrap_err()
+                .to_string(),
+            "LS"
+        );
+    }
+
+    #[test]
+    fn fee_growth_wraps_around() {
+        let mut info = PositionInfo::default();
+        // a quarter below zero, i.e. wrapped around
+        let last = FeeGrowth::new(U256::MAX - *Q128 / 4 + 1, U256::zero());
+        update(&mut info, 10, last).unwrap();
+        update(&mut info, 0, last + quarters(2, 0)).unwrap();
+        assert_eq!(info.fee_growth_inside_last, quarters(1, 0));
+        assert_eq!((info.tokens_owed_0, info.tokens_owed_1), (5, 0));
+    }
+
+    #[test]
+    fn missing_positions_read_as_empty() {
+        let table = PositionTable::new();
+        let info = get(&table, Address::one(), -10, 10);
+        assert_eq!(info.liquidity, 0);
+        assert_eq!(info.fee_growth_inside_last, FeeGrowth::zero());
+    }
+}
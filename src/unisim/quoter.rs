@@ -0,0 +1,329 @@
+//! QuoterV2: what a swap would take and give, without doing it.
+//! Quotes run the pool's own swap loop on a fork of the pool, so a quote can't disagree with the swap it quotes,
+//! and the pool being quoted is never touched.
+
+use super::{SwapOutcome, UniV3Pool};
+use crate::solidints::{I256::I256, U160::U160, U256};
+use crate::solidmath::tick_math;
+use anyhow::{ensure, Result};
+
+/// One leg of a multi-hop swap: the pool to swap in, and which way
+#[derive(Debug, Clone, Copy)]
+pub struct Hop<'a> {
+    pub pool: &'a UniV3Pool,
+    pub zero_for_one: bool,
+}
+
+/// What a swap in a single pool would do
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    /// the amount of the input token the swap takes
+    pub amount_in: U256,
+    /// the amount of the output token the swap gives
+    pub amount_out: U256,
+    /// the pool's sqrt price after the swap
+    pub sqrt_price_x96_after: U160,
+    /// the number of initialized ticks the swap crosses
+    pub initialized_ticks_crossed: u32,
+    /// how much less the swap gives than trading everything at the pool's price before it, as a fraction.
+    /// fees included, so even a tiny swap has an impact of about the fee
+    pub price_impact: f64,
+}
+
+/// What a swap along a path of pools would do
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathQuote {
+    /// the amount of the first hop's input token the swap takes
+    pub amount_in: U256,
+    /// the amount of the last hop's output token the swap gives
+    pub amount_out: U256,
+    /// the quote for each hop, in path order
+    pub hops: Vec<Quote>,
+    /// the price impact of the whole path, against the product of the pools' prices before the swap
+    pub price_impact: f64,
+}
+
+/// Returns the amount out received for a given exact input swap without executing the swap
+/// # Arguments
+/// * `pool` The pool to swap in
+/// * `zero_for_one` The direction of the swap, true for token0 to token1, false for token1 to token0
+/// * `amount_in` The desired input amount
+/// * `sqrt_price_limit_x96` The price limit of the pool that cannot be exceeded by the swap, none for no limit
+pub fn quote_exact_input_single(
+    pool: &UniV3Pool,
+    zero_for_one: bool,
+    amount_in: U256,
+    sqrt_price_limit_x96: Option<U160>,
+) -> Result<Quote> {
+    quote(
+        pool,
+        zero_for_one,
+        I256::try_from(amount_in)?,
+        sqrt_price_limit_x96,
+    )
+}
+
+/// Returns the amount in required to receive the given exact output amount but for a swap of a single pool
+/// # Arguments
+/// * `pool` The pool to swap in
+/// * `zero_for_one` The direction of the swap, true for token0 to token1, false for token1 to token0
+/// * `amount_out` The desired output amount
+/// * `sqrt_price_limit_x96` The price limit of the pool that cannot be exceeded by the swap, none for no limit.
+///   Without a limit the pool has to be able to give the whole output
+pub fn quote_exact_output_single(
+    pool: &UniV3Pool,
+    zero_for_one: bool,
+    amount_out: U256,
+    sqrt_price_limit_x96: Option<U160>,
+) -> Result<Quote> {
+    let quote = quote(
+        pool,
+        zero_for_one,
+        -I256::try_from(amount_out)?,
+        sqrt_price_limit_x96,
+    )?;
+    // without a price limit the full output amount has to be received, like QuoterV2 requires
+    ensure!(
+        sqrt_price_limit_x96.is_some() || quote.amount_out == amount_out,
+        "not enough liquidity for the output"
+    );
+    Ok(quote)
+}
+
+/// Returns the amount out received for a given exact input swap along a path, without executing the swap.
+/// Every hop sees its pool as it is now, so a path through the same pool twice doesn't see its own first hop.
+/// # Arguments
+/// * `path` The pools to swap through, in order
+/// * `amount_in` The amount of the first token to swap
+pub fn quote_exact_input(path: &[Hop], amount_in: U256) -> Result<PathQuote> {
+    ensure!(!path.is_empty(), "empty path");
+    let mut hops = Vec::with_capacity(path.len());
+    let mut amount = amount_in;
+    for hop in path {
+        let quote = quote_exact_input_single(hop.pool, hop.zero_for_one, amount, None)?;
+        amount = quote.amount_out;
+        hops.push(quote);
+    }
+    Ok(path_quote(path, hops))
+}
+
+/// Returns the amount in required to receive the given exact output amount along a path, without executing the swap
+/// # Arguments
+/// * `path` The pools to swap through, in order, i.e. starting from the pool that takes the input
+/// * `amount_out` The amount of the last token to receive
+pub fn quote_exact_output(path: &[Hop], amount_out: U256) -> Result<PathQuote> {
+    ensure!(!path.is_empty(), "empty path");
+    let mut hops = Vec::with_capacity(path.len());
+    let mut amount = amount_out;
+    for hop in path.iter().rev() {
+        let quote = quote_exact_output_single(hop.pool, hop.zero_for_one, amount, None)?;
+        amount = quote.amount_in;
+        hops.push(quote);
+    }
+    hops.reverse();
+    Ok(path_quote(path, hops))
+}
+
+fn quote(
+    pool: &UniV3Pool,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit_x96: Option<U160>,
+) -> Result<Quote> {
+    let sqrt_price_limit_x96 = sqrt_price_limit_x96.unwrap_or(if zero_for_one {
+        tick_math::MIN_SQRT_RATIO + U160::one()
+    } else {
+        tick_math::MAX_SQRT_RATIO - U160::one()
+    });
+
+    let mut fork = pool.fork();
+    let SwapOutcome {
+        amount_0,
+        amount_1,
+        initialized_ticks_crossed,
+    } = fork.swap_unchecked(zero_for_one, amount_specified, sqrt_price_limit_x96)?;
+    let (amount_in, amount_out) = if zero_for_one {
+        (amount_0, amount_1)
+    } else {
+        (amount_1, amount_0)
+    };
+    let (amount_in, amount_out) = (amount_in.into_raw(), amount_out.unsigned_abs());
+
+    Ok(Quote {
+        amount_in,
+        amount_out,
+        sqrt_price_x96_after: fork.sqrt_price_x96(),
+        initialized_ticks_crossed,
+        price_impact: price_impact(amount_in, amount_out, price(pool, zero_for_one)),
+    })
+}
+
+fn path_quote(path: &[Hop], hops: Vec<Quote>) -> PathQuote {
+    let amount_in = hops[0].amount_in;
+    let amount_out = hops[hops.len() - 1].amount_out;
+    let price = path
+        .iter()
+        .map(|hop| price(hop.pool, hop.zero_for_one))
+        .product();
+    PathQuote {
+        amount_in,
+        amount_out,
+        hops,
+        price_impact: price_impact(amount_in, amount_out, price),
+    }
+}
+
+/// the pool's price before the swap, in output token per input token
+fn price(pool: &UniV3Pool, zero_for_one: bool) -> f64 {
+    let sqrt_price = to_f64(pool.sqrt_price_x96().into()) / 2f64.powi(96);
+    let price = sqrt_price * sqrt_price;
+    if zero_for_one {
+        price
+    } else {
+        1.0 / price
+    }
+}
+
+fn price_impact(amount_in: U256, amount_out: U256, price: f64) -> f64 {
+    if amount_in.is_zero() {
+        return 0.0;
+    }
+    1.0 - to_f64(amount_out) / (to_f64(amount_in) * price)
+}
+
+fn to_f64(x: U256) -> f64 {
+    x.0.iter()
+        .rev()
+        .fold(0.0, |acc, &word| acc * 2f64.powi(64) + word as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::callback::PayExact;
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn pool_with_ranges(ranges: &[(i32, i32, u128)]) -> UniV3Pool {
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        for &(tick_lower, tick_upper, liquidity) in ranges {
+            pool.mint(
+                U160::from(0xc0ffeeu32),
+                tick_lower,
+                tick_upper,
+                liquidity,
+                &mut PayExact,
+            )
+            .unwrap();
+        }
+        pool
+    }
+
+    #[test]
+    fn quotes_match_swaps_and_leave_the_pool_alone() {
+        let mut pool = pool_with_ranges(&[(-600, 600, 100 * E18), (-120, 120, 50 * E18)]);
+        let before = pool.clone();
+
+        let quote = quote_exact_input_single(&pool, true, E18.into(), None).unwrap();
+        assert_eq!(pool, before);
+        assert_eq!(quote.initialized_ticks_crossed, 1);
+
+        let (amount_0, amount_1) = pool
+            .swap(
+                true,
+                I256::from(E18),
+                tick_math::MIN_SQRT_RATIO + U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+        assert_eq!(quote.amount_in, amount_0.into_raw());
+        assert_eq!(quote.amount_out, amount_1.unsigned_abs());
+        assert_eq!(quote.sqrt_price_x96_after, pool.sqrt_price_x96());
+    }
+
+    #[test]
+    fn exact_output() {
+        let mut pool = pool_with_ranges(&[(-600, 600, 100 * E18)]);
+        let quote = quote_exact_output_single(&pool, false, E18.into(), None).unwrap();
+        assert_eq!(quote.amount_out, U256::from(E18));
+        let (amount_0, amount_1) = pool
+            .swap(
+                false,
+                -I256::from(E18),
+                tick_math::MAX_SQRT_RATIO - U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+        assert_eq!(amount_0, -I256::from(E18));
+        assert_eq!(quote.amount_in, amount_1.into_raw());
+
+        // more than the pool has, unless we said where to stop
+        assert!(quote_exact_output_single(&pool, false, (10 * E18).into(), None).is_err());
+        let limit = tick_math::get_sqrt_ratio_at_tick(600).unwrap();
+        let partial =
+            quote_exact_output_single(&pool, false, (10 * E18).into(), Some(limit)).unwrap();
+        assert!(partial.amount_out < U256::from(10 * E18));
+        assert_eq!(partial.sqrt_price_x96_after, limit);
+    }
+
+    #[test]
+    fn counts_initialized_ticks_crossed() {
+        let pool = pool_with_ranges(&[(-600, 600, E18), (-120, 120, E18), (-60, 60, E18)]);
+        let crossed = |amount: u128| {
+            quote_exact_input_single(&pool, true, amount.into(), None)
+                .unwrap()
+                .initialized_ticks_crossed
+        };
+        assert_eq!(crossed(1000), 0);
+        assert_eq!(crossed(E18 / 100), 1);
+        assert_eq!(crossed(E18 / 50), 2);
+        // -600 itself, and then there's nothing left
+        assert_eq!(crossed(100 * E18), 3);
+    }
+
+    #[test]
+    fn price_impact_grows_with_size() {
+        let pool = pool_with_ranges(&[(-600, 600, 100 * E18)]);
+        let impact = |amount: u128| {
+            quote_exact_input_single(&pool, true, amount.into(), None)
+                .unwrap()
+                .price_impact
+        };
+        // a tiny swap only pays the fee
+        assert!((impact(E18 / 1000) - 0.003).abs() < 1e-3);
+        assert!(impact(E18 / 10) > impact(E18 / 1000));
+        assert!(impact(E18) > impact(E18 / 10));
+    }
+
+    #[test]
+    fn multi_hop() {
+        let first = pool_with_ranges(&[(-600, 600, 100 * E18)]);
+        let second = pool_with_ranges(&[(-1200, 1200, 50 * E18)]);
+        let path = [
+            Hop {
+                pool: &first,
+                zero_for_one: true,
+            },
+            Hop {
+                pool: &second,
+                zero_for_one: false,
+            },
+        ];
+
+        let quote = quote_exact_input(&path, (E18 / 10).into()).unwrap();
+        let hop_0 = quote_exact_input_single(&first, true, (E18 / 10).into(), None).unwrap();
+        let hop_1 = quote_exact_input_single(&second, false, hop_0.amount_out, None).unwrap();
+        assert_eq!(quote.amount_out, hop_1.amount_out);
+        assert_eq!(quote.hops, vec![hop_0, hop_1]);
+        // two fees and two pools' worth of impact
+        assert!(quote.price_impact > quote.hops[0].price_impact);
+
+        let quote = quote_exact_output(&path, (E18 / 10).into()).unwrap();
+        assert_eq!(quote.amount_out, U256::from(E18 / 10));
+        assert_eq!(quote.hops[0].amount_out, quote.hops[1].amount_in);
+        let round_trip = quote_exact_input(&path, quote.amount_in).unwrap();
+        assert!(round_trip.amount_out >= U256::from(E18 / 10));
+
+        assert!(quote_exact_input(&[], E18.into()).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! SwapRouter: multi-hop swaps across a set of simulated pools, with the router's slippage and deadline checks.
+//! Paths are encoded the way the periphery does it, `token fee token fee token ...`,
+//! 20 bytes per token and 3 per fee, and a swap along a path either completes or leaves every pool alone.
+
+use super::callback::PayExact;
+use super::quoter::{self, Hop, PathQuote};
+use super::{Address, UniV3Pool};
+use crate::solidints::{I256::I256, U160::U160, U256};
+use crate::solidmath::tick_math;
+use anyhow::{anyhow, ensure, Result};
+use im::HashMap;
+
+/// The size of an address
+const ADDR_SIZE: usize = 20;
+/// The size of a fee
+const FEE_SIZE: usize = 3;
+/// The offset of a single token address and pool fee
+const NEXT_OFFSET: usize = ADDR_SIZE + FEE_SIZE;
+/// The offset of an encoded pool key
+const POP_OFFSET: usize = NEXT_OFFSET + ADDR_SIZE;
+
+/// Identifies a pool, like PoolAddress.PoolKey. The tokens are always sorted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub token_0: Address,
+    pub token_1: Address,
+    pub fee: u32,
+}
+
+impl PoolKey {
+    /// the key of the pool for a pair of tokens in either order
+    pub fn new(token_a: Address, token_b: Address, fee: u32) -> Self {
+        let (token_0, token_1) = if token_a > token_b {
+            (token_b, token_a)
+        } else {
+            (token_a, token_b)
+        };
+        Self {
+            token_0,
+            token_1,
+            fee,
+        }
+    }
+}
+
+/// Encodes a path of tokens and the fees of the pools between them
+pub fn encode_path(tokens: &[Address], fees: &[u32]) -> Result<Vec<u8>> {
+    ensure!(
+        !fees.is_empty() && tokens.len() == fees.len() + 1,
+        "a path needs one more token than fees"
+    );
+    let mut path = vec![0u8; ADDR_SIZE + fees.len() * NEXT_OFFSET];
+    for (i, token) in tokens.iter().enumerate() {
+        token.to_big_endian(&mut path[i * NEXT_OFFSET..i * NEXT_OFFSET + ADDR_SIZE]);
+    }
+    for (i, fee) in fees.iter().enumerate() {
+        ensure!(*fee < 1 << 24, "fee {} doesn't fit in a uint24", fee);
+        let start = i * NEXT_OFFSET + ADDR_SIZE;
+        path[start..start + FEE_SIZE].copy_from_slice(&fee.to_be_bytes()[1..]);
+    }
+    Ok(path)
+}
+
+/// Decodes every pool in a path, as (token in, token out, fee) in path order
+pub fn decode_path(path: &[u8]) -> Result<Vec<(Address, Address, u32)>> {
+    ensure!(
+        path.len() >= POP_OFFSET && (path.len() - ADDR_SIZE).is_multiple_of(NEXT_OFFSET),
+        "malformed path of {} bytes",
+        path.len()
+    );
+    Ok(path
+        .windows(POP_OFFSET)
+        .step_by(NEXT_OFFSET)
+        .map(|pool| {
+            let mut fee = [0u8; 4];
+            fee[1..].copy_from_slice(&pool[ADDR_SIZE..NEXT_OFFSET]);
+            (
+                U160::from_big_endian(&pool[..ADDR_SIZE]),
+                U160::from_big_endian(&pool[NEXT_OFFSET..]),
+                u32::from_be_bytes(fee),
+            )
+        })
+        .collect())
+}
+
+/// Swap along an encoded path for as much as possible of the last token
+#[derive(Debug, Clone)]
+pub struct ExactInputParams {
+    pub path: Vec<u8>,
+    pub deadline: u32,
+    pub amount_in: U256,
+    pub amount_out_minimum: U256,
+}
+
+/// Swap as little as possible of the first token for an exact amount of the last token.
+/// The path is encoded backwards, starting from the token that comes out
+#[derive(Debug, Clone)]
+pub struct ExactOutputParams {
+    pub path: Vec<u8>,
+    pub deadline: u32,
+    pub amount_out: U256,
+    pub amount_in_maximum: U256,
+}
+
+/// A set of pools and a router to swap through them
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    // persistent like the pools' own tables, so the router can save all of them before a swap
+    pools: HashMap<PoolKey, UniV3Pool>,
+    // the simulated block.timestamp, for deadlines
+    block_timestamp: u32,
+}
+
+impl Router {
+    pub fn new(block_timestamp: u32) -> Self {
+        Self {
+            pools: HashMap::new(),
+            block_timestamp,
+        }
+    }
+
+    /// Adds a pool between two tokens, like the factory's createPool. there can only be one per pair and fee
+    pub fn add_pool(&mut self, token_a: Address, token_b: Address, pool: UniV3Pool) -> Result<()> {
+        ensure!(token_a != token_b, "identical tokens");
+        ensure!(!token_a.is_zero() && !token_b.is_zero(), "zero address");
+        let key = PoolKey::new(token_a, token_b, pool.fee());
+        ensure!(!self.pools.contains_key(&key), "pool already exists");
+        self.pools.insert(key, pool);
+        Ok(())
+    }
+
+    pub fn pool(&self, token_a: Address, token_b: Address, fee: u32) -> Option<&UniV3Pool> {
+        self.pools.get(&PoolKey::new(token_a, token_b, fee))
+    }
+    pub fn pool_mut(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+    ) -> Option<&mut UniV3Pool> {
+        self.pools.get_mut(&PoolKey::new(token_a, token_b, fee))
+    }
+    pub fn pools(&self) -> &HashMap<PoolKey, UniV3Pool> {
+        &self.pools
+    }
+    pub fn block_timestamp(&self) -> u32 {
+        self.block_timestamp
+    }
+
+    /// Moves the simulated block.timestamp of the router and all of its pools
+    pub fn set_block_timestamp(&mut self, block_timestamp: u32) -> Result<()> {
+        self.atomically(|router| {
+            for (_, pool) in router.pools.iter_mut() {
+                pool.set_block_timestamp(block_timestamp)?;
+            }
+            router.block_timestamp = block_timestamp;
+            Ok(())
+        })
+    }
+
+    /// Swaps `amount_in` of one token for as much as possible of another along the specified path
+    ///
+    /// returns the amount of the received token
+    pub fn exact_input(&mut self, params: &ExactInputParams) -> Result<U256> {
+        self.check_deadline(params.deadline)?;
+        let hops = decode_path(&params.path)?;
+        self.atomically(|router| {
+            let mut amount = params.amount_in;
+            for (token_in, token_out, fee) in hops {
+                let (_, amount_out) =
+                    router.swap(token_in, token_out, fee, I256::try_from(amount)?)?;
+                amount = amount_out;
+            }
+            ensure!(amount >= params.amount_out_minimum, "Too little received");
+            Ok(amount)
+        })
+    }
+
+    /// Swaps as little as possible of one token for `amount_out` of another along the specified path (reversed)
+    ///
+    /// returns the amount of the input token
+    pub fn exact_output(&mut self, params: &ExactOutputParams) -> Result<U256> {
+        self.check_deadline(params.deadline)?;
+        let hops = decode_path(&params.path)?;
+        self.atomically(|router| {
+            let mut amount = params.amount_out;
+            for (token_out, token_in, fee) in hops {
+                let (amount_in, amount_out) =
+                    router.swap(token_in, token_out, fee, -I256::try_from(amount)?)?;
+                // it's technically possible to not receive the full output amount,
+                // so if no price limit has been specified, require this possibility away
+                ensure!(amount_out == amount, "not enough liquidity for the output");
+                amount = amount_in;
+            }
+            ensure!(amount <= params.amount_in_maximum, "Too much requested");
+            Ok(amount)
+        })
+    }
+
+    /// Quotes `exact_input` without swapping
+    pub fn quote_exact_input(&self, path: &[u8], amount_in: U256) -> Result<PathQuote> {
+        quoter::quote_exact_input(&self.hops(decode_path(path)?)?, amount_in)
+    }
+
+    /// Quotes `exact_output` without swapping. The path is reversed, as for `exact_output`
+    pub fn quote_exact_output(&self, path: &[u8], amount_out: U256) -> Result<PathQuote> {
+        let mut pools = decode_path(path)?;
+        pools.reverse();
+        let pools = pools
+            .into_iter()
+            .map(|(token_out, token_in, fee)| (token_in, token_out, fee))
+            .collect();
+        quoter::quote_exact_output(&self.hops(pools)?, amount_out)
+    }
+
+    fn hops(&self, pools: Vec<(Address, Address, u32)>) -> Result<Vec<Hop<'_>>> {
+        pools
+            .into_iter()
+            .map(|(token_in, token_out, fee)| {
+                Ok(Hop {
+                    pool: self.get(token_in, token_out, fee)?,
+                    zero_for_one: token_in < token_out,
+                })
+            })
+            .collect()
+    }
+
+    fn get(&self, token_a: Address, token_b: Address, fee: u32) -> Result<&UniV3Pool> {
+        self.pool(token_a, token_b, fee).ok_or(anyhow!(
+            "no pool for {} {} {}",
+            token_a,
+            token_b,
+            fee
+        ))
+    }
+
+    fn check_deadline(&self, deadline: u32) -> Result<()> {
+        ensure!(self.block_timestamp <= deadline, "Transaction too old");
+        Ok(())
+    }
+
+    /// swaps in one pool with no price limit, paying exactly what the pool asks
+    ///
+    /// returns the amounts of the input and output token
+    fn swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        fee: u32,
+        amount_specified: I256,
+    ) -> Result<(U256, U256)> {
+        let zero_for_one = token_in < token_out;
+        let pool = self
+            .pools
+            .get_mut(&PoolKey::new(token_in, token_out, fee))
+            .ok_or(anyhow!("no pool for {} {} {}", token_in, token_out, fee))?;
+        let sqrt_price_limit_x96 = if zero_for_one {
+            tick_math::MIN_SQRT_RATIO + U160::one()
+        } else {
+            tick_math::MAX_SQRT_RATIO - U160::one()
+        };
+        let (amount_0, amount_1) = pool.swap(
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit_x96,
+            &mut PayExact,
+        )?;
+        Ok(if zero_for_one {
+            (amount_0.into_raw(), amount_1.unsigned_abs())
+        } else {
+            (amount_1.into_raw(), amount_0.unsigned_abs())
+        })
+    }
+
+    /// like the pool's own: an operation that fails leaves every pool the way it was
+    fn atomically<T>(&mut self, op: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.pools.clone();
+        let result = op(self);
+        if result.is_err() {
+            self.pools = saved;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn token(n: u32) -> Address {
+        Address::from(n)
+    }
+
+    // three tokens priced 1:1, with 0.3% pools between 1 and 2 and between 2 and 3
+    fn router() -> Router {
+        let mut router = Router::new(100);
+        for (token_a, token_b) in [(1, 2), (2, 3)] {
+            let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 100).unwrap();
+            pool.mint(token(0xc0ffee), -600, 600, 100 * E18, &mut PayExact)
+                .unwrap();
+            router
+                .add_pool(token(token_a), token(token_b), pool)
+                .unwrap();
+        }
+        router
+    }
+
+    #[test]
+    fn encodes_paths() {
+        let path = encode_path(&[token(1), token(2), token(3)], &[3000, 500]).unwrap();
+        assert_eq!(path.len(), 66);
+        assert_eq!(path[19], 1);
+        assert_eq!(&path[20..23], &[0x00, 0x0b, 0xb8]);
+        assert_eq!(
+            decode_path(&path).unwrap(),
+            vec![(token(1), token(2), 3000), (token(2), token(3), 500)]
+        );
+
+        assert!(encode_path(&[token(1)], &[]).is_err());
+        assert!(encode_path(&[token(1), token(2)], &[1 << 24]).is_err());
+        assert!(decode_path(&path[..42]).is_err());
+        assert!(decode_path(&path[..65]).is_err());
+    }
+
+    #[test]
+    fn pools_are_keyed_by_sorted_tokens_and_fee() {
+        let mut router = router();
+        assert!(router.pool(token(2), token(1), 3000).is_some());
+        assert!(router.pool(token(1), token(2), 500).is_none());
+        let pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        assert!(router.add_pool(token(2), token(1), pool.clone()).is_err());
+        assert!(router.add_pool(token(1), token(1), pool.clone()).is_err());
+        router.add_pool(token(1), token(3), pool).unwrap();
+        assert_eq!(router.pools().len(), 3);
+    }
+
+    #[test]
+    fn exact_input_matches_its_quote() {
+        let mut router = router();
+        let path = encode_path(&[token(1), token(2), token(3)], &[3000, 3000]).unwrap();
+        let quote = router.quote_exact_input(&path, E18.into()).unwrap();
+
+        let amount_out = router
+            .exact_input(&ExactInputParams {
+                path: path.clone(),
+                deadline: 100,
+                amount_in: E18.into(),
+                amount_out_minimum: quote.amount_out,
+            })
+            .unwrap();
+        assert_eq!(amount_out, quote.amount_out);
+        // two fees' worth less
+        assert!(amount_out < U256::from(E18 * 994 / 1000));
+        // token1 in and token2 out of the first pool, token2 in and token3 out of the second
+        let first = router.pool(token(1), token(2), 3000).unwrap();
+        let second = router.pool(token(2), token(3), 3000).unwrap();
+        assert!(first.tick() < 0);
+        assert!(second.tick() < 0);
+        assert_eq!(second.sqrt_price_x96(), quote.hops[1].sqrt_price_x96_after);
+    }
+
+    #[test]
+    fn exact_output_goes_backwards() {
+        let mut router = router();
+        // token3 out, from token1 in
+        let path = encode_path(&[token(3), token(2), token(1)], &[3000, 3000]).unwrap();
+        let quote = router.quote_exact_output(&path, E18.into()).unwrap();
+        assert_eq!(quote.amount_out, U256::from(E18));
+
+        let amount_in = router
+            .exact_output(&ExactOutputParams {
+                path,
+                deadline: 100,
+                amount_out: E18.into(),
+                amount_in_maximum: quote.amount_in,
+            })
+            .unwrap();
+        assert_eq!(amount_in, quote.amount_in);
+        assert!(amount_in > U256::from(E18 * 1006 / 1000));
+    }
+
+    #[test]
+    fn slippage_and_deadline_revert_everything() {
+        let mut router = router();
+        let before = router.clone();
+        let path = encode_path(&[token(1), token(2), token(3)], &[3000, 3000]).unwrap();
+        let mut params = ExactInputParams {
+            path,
+            deadline: 100,
+            amount_in: E18.into(),
+            amount_out_minimum: E18.into(),
+        };
+        let err = router.exact_input(&params).unwrap_err();
+        assert_eq!(err.to_string(), "Too little received");
+        assert_eq!(router.pools(), before.pools());
+
+        router.set_block_timestamp(101).unwrap();
+        params.amount_out_minimum = U256::zero();
+        let err = router.exact_input(&params).unwrap_err();
+        assert_eq!(err.to_string(), "Transaction too old");
+        assert!(router.set_block_timestamp(99).is_err());
+        assert_eq!(router.block_timestamp(), 101);
+
+        let reversed = encode_path(&[token(3), token(2), token(1)], &[3000, 3000]).unwrap();
+        let err = router
+            .exact_output(&ExactOutputParams {
+                path: reversed,
+                deadline: 200,
+                amount_out: E18.into(),
+                amount_in_maximum: E18.into(),
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Too much requested");
+
+        // a path through a pool that isn't there
+        let path = encode_path(&[token(1), token(3)], &[500]).unwrap();
+        params.path = path;
+        params.deadline = 200;
+        assert!(router.exact_input(&params).is_err());
+        for (key, pool) in router.pools() {
+            assert_eq!(pool.sqrt_price_x96(), before.pools()[key].sqrt_price_x96());
+        }
+    }
+}
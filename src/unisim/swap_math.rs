@@ -4,163 +4,290 @@ use crate::solidints::U256;
 use crate::solidmath::{full_math, sqrt_price_math};
 use anyhow::Result;
 
+/// The result of a single swap step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeSwapStepReturn {
+    /// The price after swapping the amount in/out, not to exceed the price target
+    pub sqrt_ratio_next_x96: U160,
+    /// The amount to be swapped in, of either token0 or token1, based on the direction of the swap
+    pub amount_in: U256,
+    /// The amount to be received, of either token0 or token1, based on the direction of the swap
+    pub amount_out: U256,
+    /// The amount of input that will be taken as a fee
+    pub fee_amount: U256,
+}
+
 /// @notice Computes the result of swapping some amount in, or amount out, given the parameters of the swap
 /// @dev The fee, plus the amount in, will never exceed the amount remaining if the swap's `amountSpecified` is positive
-/// @param sqrtRatioCurrentX96 The current sqrt price of the pool
-/// @param sqrtRatioTargetX96 The price that cannot be exceeded, from which the direction of the swap is inferred
+/// @param sqrt_ratio_current_x96 The current sqrt price of the pool
+/// @param sqrt_ratio_target_x96 The price that cannot be exceeded, from which the direction of the swap is inferred
 /// @param liquidity The usable liquidity
-/// @param amountRemaining How much input or output amount is remaining to be swapped in/out
-/// @param feePips The fee taken from the input amount, expressed in hundredths of a bip
-/// @return sqrtRatioNextX96 The price after swapping the amount in/out, not to exceed the price target
-/// @return amount_in The amount to be swapped in, of either token0 or token1, based on the direction of the swap
-/// @return amount_out The amount to be received, of either token0 or token1, based on the direction of the swap
-/// @return feeAmount The amount of input that will be taken as a fee
-
-struct ComputeSwapStepReturn {
-    sqrtRatioNextX96: U160,
-    amount_in: U256,
-    amount_out: U256,
-    feeAmount: U256,
-}
-
-fn computeSwapStep(
-    sqrtRatioCurrentX96: U160,
-    sqrtRatioTargetX96: U160,
+/// @param amount_remaining How much input or output amount is remaining to be swapped in/out
+/// @param fee_pips The fee taken from the input amount, expressed in hundredths of a bip
+pub fn compute_swap_step(
+    sqrt_ratio_current_x96: U160,
+    sqrt_ratio_target_x96: U160,
     liquidity: u128,
-    amountRemaining: I256,
-    feePips: u32,
+    amount_remaining: I256,
+    fee_pips: u32,
 ) -> Result<ComputeSwapStepReturn> {
-    let zero_for_one = sqrtRatioCurrentX96 >= sqrtRatioTargetX96;
-    let exactIn = amountRemaining >= I256::zero();
+    let zero_for_one = sqrt_ratio_current_x96 >= sqrt_ratio_target_x96;
+    let exact_in = amount_remaining >= I256::zero();
+    // uint256(amountRemaining) / uint256(-amountRemaining)
+    let amount_remaining_abs = amount_remaining.unsigned_abs();
 
-    let (amountRemainingLessFee, amount_in, amount_out, sqrtRatioNextX96) = if exactIn {
-        let amountRemainingLessFee =
-            full_math::muldiv(amountRemaining.try_into()?, (1000000 - feePips).into(), 1000000 as.into())?;
-        let amount_in = if zero_for_one {
+    let mut amount_in = U256::zero();
+    let mut amount_out = U256::zero();
+
+    let sqrt_ratio_next_x96 = if exact_in {
+        let amount_remaining_less_fee = full_math::muldiv(
+            amount_remaining_abs,
+            (1000000 - fee_pips).into(),
+            1000000.into(),
+        )?;
+        amount_in = if zero_for_one {
             sqrt_price_math::get_amount0_delta_helper(
-                sqrtRatioTargetX96,
-                sqrtRatioCurrentX96,
+                sqrt_ratio_target_x96,
+                sqrt_ratio_current_x96,
                 liquidity,
                 true,
             )?
         } else {
             sqrt_price_math::get_amount1_delta_helper(
-                sqrtRatioCurrentX96,
-                sqrtRatioTargetX96,
+                sqrt_ratio_current_x96,
+                sqrt_ratio_target_x96,
                 liquidity,
                 true,
             )?
         };
-        let sqrtRatioNextX96 = if amountRemainingLessFee >= amount_in {
-            sqrtRatioTargetX96
+        if amount_remaining_less_fee >= amount_in {
+            sqrt_ratio_target_x96
         } else {
-            sqrt_price_math::getNextSqrtPriceFromInput(
-                sqrtRatioCurrentX96,
+            sqrt_price_math::get_next_sqrt_price_from_input(
+                sqrt_ratio_current_x96,
                 liquidity,
-                amountRemainingLessFee,
+                amount_remaining_less_fee,
                 zero_for_one,
             )?
-        };
-        (
-            Some(amountRemainingLessFee),
-            Some(amount_in),
-            None,
-            sqrtRatioNextX96,
-        );
+        }
     } else {
-        let amount_out = if zero_for_one {
+        amount_out = if zero_for_one {
             sqrt_price_math::get_amount1_delta_helper(
-                sqrtRatioTargetX96,
-                sqrtRatioCurrentX96,
+                sqrt_ratio_target_x96,
+                sqrt_ratio_current_x96,
                 liquidity,
                 false,
-            )
+            )?
         } else {
             sqrt_price_math::get_amount0_delta_helper(
-                sqrtRatioCurrentX96,
-                sqrtRatioTargetX96,
+                sqrt_ratio_current_x96,
+                sqrt_ratio_target_x96,
                 liquidity,
                 false,
-            )
+            )?
         };
-        let sqrtRatioNextX96 = if -amountRemaining.try_into()? >= amount_out {
-            sqrtRatioTargetX96
+        if amount_remaining_abs >= amount_out {
+            sqrt_ratio_target_x96
         } else {
-            sqrt_price_math::getNextSqrtPriceFromOutput(
-                sqrtRatioCurrentX96,
+            sqrt_price_math::get_next_sqrt_price_from_output(
+                sqrt_ratio_current_x96,
                 liquidity,
-                (-amountRemaining).try_into()?,
+                amount_remaining_abs,
                 zero_for_one,
             )?
-        };
-        (None, None, Some(amount_out), sqrtRatioNextX96)
+        }
     };
 
-    let max = sqrtRatioTargetX96 == sqrtRatioNextX96;
+    let max = sqrt_ratio_target_x96 == sqrt_ratio_next_x96;
 
     // get the input/output amounts
-    let (amount_in, amount_out) = if zero_for_one {
-        let amount_in = if max && exactIn {
-            amount_in
-        } else {
-            sqrt_price_math::get_amount0_delta_helper(
-                sqrtRatioNextX96,
-                sqrtRatioCurrentX96,
+    if zero_for_one {
+        if !(max && exact_in) {
+            amount_in = sqrt_price_math::get_amount0_delta_helper(
+                sqrt_ratio_next_x96,
+                sqrt_ratio_current_x96,
                 liquidity,
                 true,
-            )?
-        };
-        let amount_out = if max && !exactIn {
-            amount_out
-        } else {
-            sqrt_price_math::get_amount1_delta_helper(
-                sqrtRatioNextX96,
-                sqrtRatioCurrentX96,
+            )?;
+        }
+        if !max || exact_in {
+            amount_out = sqrt_price_math::get_amount1_delta_helper(
+                sqrt_ratio_next_x96,
+                sqrt_ratio_current_x96,
                 liquidity,
                 false,
-            )?
-        };
-        (amount_in, amount_out)
+            )?;
+        }
     } else {
-        let amount_in = if max && exactIn {
-            amount_in
-        } else {
-            sqrt_price_math::get_amount1_delta_helper(
-                sqrtRatioCurrentX96,
-                sqrtRatioNextX96,
+        if !(max && exact_in) {
+            amount_in = sqrt_price_math::get_amount1_delta_helper(
+                sqrt_ratio_current_x96,
+                sqrt_ratio_next_x96,
                 liquidity,
                 true,
-            )?
-        };
-        amount_out = if max && !exactIn {
-            amount_out
-        } else {
-            sqrt_price_math::get_amount0_delta_helper(
-                sqrtRatioCurrentX96,
-                sqrtRatioNextX96,
+            )?;
+        }
+        if !max || exact_in {
+            amount_out = sqrt_price_math::get_amount0_delta_helper(
+                sqrt_ratio_current_x96,
+                sqrt_ratio_next_x96,
                 liquidity,
                 false,
-            )?
-        };
-        (amount_in, amount_out)
-    };
+            )?;
+        }
+    }
 
     // cap the output amount to not exceed the remaining output amount
-    if !exactIn && amount_out > -amountRemaining.into() {
-        amount_out = -amountRemaining.into();
+    if !exact_in && amount_out > amount_remaining_abs {
+        amount_out = amount_remaining_abs;
     }
 
-    let feeAmount = if exactIn && sqrtRatioNextX96 != sqrtRatioTargetX96 {
+    let fee_amount = if exact_in && sqrt_ratio_next_x96 != sqrt_ratio_target_x96 {
         // we didn't reach the target, so take the remainder of the maximum input as fee
-        amountRemaining.into() - amount_in
+        amount_remaining_abs - amount_in
     } else {
-        full_math::mul_div_rounding_up(amount_in, feePips.into(), (1000000 - feePips).into())
+        full_math::mul_div_rounding_up(amount_in, fee_pips.into(), (1000000 - fee_pips).into())?
     };
 
     Ok(ComputeSwapStepReturn {
-        sqrtRatioNextX96,
+        sqrt_ratio_next_x96,
         amount_in,
         amount_out,
-        feeAmount,
+        fee_amount,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> U256 {
+        U256::from_dec_str(s).unwrap()
+    }
+
+    fn price(s: &str) -> U160 {
+        U160::from_dec_str(s).unwrap()
+    }
+
+    fn e18() -> I256 {
+        I256::from(1_000_000_000_000_000_000i128)
+    }
+
+    // encodePriceSqrt(1, 1)
+    fn price_one() -> U160 {
+        U160::one() << 96
+    }
+
+    #[test]
+    fn exact_in_capped_at_target_one_for_zero() {
+        // encodePriceSqrt(101, 100)
+        let target = price("79623317895830914510639640423");
+        let step = compute_swap_step(price_one(), target, 2 * 10u128.pow(18), e18(), 600).unwrap();
+        assert_eq!(step.amount_in, dec("9975124224178055"));
+        assert_eq!(step.fee_amount, dec("5988667735148"));
+        assert_eq!(step.amount_out, dec("9925619580021728"));
+        assert_eq!(step.sqrt_ratio_next_x96, target);
+    }
+
+    #[test]
+    fn exact_out_capped_at_target_one_for_zero() {
+        let target = price("79623317895830914510639640423");
+        let step = compute_swap_step(price_one(), target, 2 * 10u128.pow(18), -e18(), 600).unwrap();
+        assert_eq!(step.amount_in, dec("9975124224178055"));
+        assert_eq!(step.fee_amount, dec("5988667735148"));
+        assert_eq!(step.amount_out, dec("9925619580021728"));
+        assert_eq!(step.sqrt_ratio_next_x96, target);
+    }
+
+    #[test]
+    fn exact_in_fully_spent_one_for_zero() {
+        // encodePriceSqrt(1000, 100)
+        let target = price("250541448375047931186413801569");
+        let step = compute_swap_step(price_one(), target, 2 * 10u128.pow(18), e18(), 600).unwrap();
+        assert_eq!(step.amount_in, dec("999400000000000000"));
+        assert_eq!(step.fee_amount, dec("600000000000000"));
+        assert_eq!(step.amount_out, dec("666399946655997866"));
+        assert!(step.sqrt_ratio_next_x96 < target);
+    }
+
+    #[test]
+    fn exact_out_fully_received_one_for_zero() {
+        // encodePriceSqrt(10000, 100)
+        let target = price("792281625142643375935439503360");
+        let step = compute_swap_step(price_one(), target, 2 * 10u128.pow(18), -e18(), 600).unwrap();
+        assert_eq!(step.amount_in, dec("2000000000000000000"));
+        assert_eq!(step.fee_amount, dec("1200720432259356"));
+        assert_eq!(step.amount_out, dec("1000000000000000000"));
+        assert!(step.sqrt_ratio_next_x96 < target);
+    }
+
+    #[test]
+    fn amount_out_capped_at_desired() {
+        let step = compute_swap_step(
+            price("417332158212080721273783715441582"),
+            price("1452870262520218020823638996"),
+            159344665391607089467575320103,
+            I256::from(-1i128),
+            1,
+        )
+        .unwrap();
+        assert_eq!(step.amount_in, U256::one());
+        assert_eq!(step.fee_amount, U256::one());
+        assert_eq!(step.amount_out, U256::one());
+        assert_eq!(
+            step.sqrt_ratio_next_x96,
+            price("417332158212080721273783715441581")
+        );
+    }
+
+    #[test]
+    fn target_price_of_one_uses_partial_input() {
+        let step = compute_swap_step(
+            U160::from(2u128),
+            U160::one(),
+            1,
+            I256::from_dec_str("3915081100057732413702495386755767").unwrap(),
+            1,
+        )
+        .unwrap();
+        assert_eq!(step.amount_in, dec("39614081257132168796771975168"));
+        assert_eq!(step.fee_amount, dec("39614120871253040049813"));
+        assert!(step.amount_in + step.fee_amount <= dec("3915081100057732413702495386755767"));
+        assert_eq!(step.amount_out, U256::zero());
+        assert_eq!(step.sqrt_ratio_next_x96, U160::one());
+    }
+
+    #[test]
+    fn entire_input_taken_as_fee() {
+        let step = compute_swap_step(
+            U160::from(2413u128),
+            price("79887613182836312"),
+            1985041575832132834610021537970,
+            I256::from(10i128),
+            1872,
+        )
+        .unwrap();
+        assert_eq!(step.amount_in, U256::zero());
+        assert_eq!(step.fee_amount, U256::from(10));
+        assert_eq!(step.amount_out, U256::zero());
+        assert_eq!(step.sqrt_ratio_next_x96, U160::from(2413u128));
+    }
+
+    #[test]
+    fn intermediate_insufficient_liquidity_exact_output() {
+        let sqrt_p = price("20282409603651670423947251286016");
+        let target = sqrt_p * U160::from(11u128) / U160::from(10u128);
+        let step = compute_swap_step(sqrt_p, target, 1024, I256::from(-4i128), 3000).unwrap();
+        assert_eq!(step.amount_out, U256::zero());
+        assert_eq!(step.sqrt_ratio_next_x96, target);
+        assert_eq!(step.amount_in, U256::from(26215));
+        assert_eq!(step.fee_amount, U256::from(79));
+
+        let target = sqrt_p * U160::from(9u128) / U160::from(10u128);
+        let step = compute_swap_step(sqrt_p, target, 1024, I256::from(-263000i128), 3000).unwrap();
+        assert_eq!(step.amount_out, U256::from(26214));
+        assert_eq!(step.sqrt_ratio_next_x96, target);
+        assert_eq!(step.amount_in, U256::one());
+        assert_eq!(step.fee_amount, U256::one());
+    }
+}
@@ -1,50 +1,53 @@
-use super::fee::Fee;
+use super::fee::FeeGrowth;
 use anyhow::{anyhow, Result};
 
 use super::liq_math;
-use std::borrow::Borrow;
-use std::collections::HashMap;
+use crate::solidints::sized::Int56;
+use crate::solidints::U160::U160;
+use im::HashMap;
 
 pub type Tick = i32;
 
-pub(crate) const MIN_TICK: i32 = -887272;
-pub(crate) const MAX_TICK: i32 = -MIN_TICK;
+pub(crate) use crate::solidmath::tick_math::{MAX_TICK, MIN_TICK};
 
 /// One tick's data, as stored in the tick table. For all sorts of dynamic programming goodies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct TickInfo {
     /// the total position liquidity that references this tick
-    liquidity_gross: u128,
+    pub liquidity_gross: u128,
     /// amount of net liquidity added (subtracted) when tick is crossed from left to right (right to left),
-    liquidity_net: u128,
+    pub liquidity_net: i128,
     /// fee growth per unit of liquidity on the _other_ side of this tick (relative to the current tick)
     /// only has relative meaning, not absolute — the value depends on when the tick is initialized
-    fee_growth_outside: Fee,
+    pub fee_growth_outside: FeeGrowth,
     /// the cumulative tick value on the other side of the tick
-    tick_cumulative_outside: i64,
+    pub tick_cumulative_outside: Int56,
     /// the seconds per unit of liquidity on the _other_ side of this tick (relative to the current tick)
     /// only has relative meaning, not absolute — the value depends on when the tick is initialized
-    seconds_per_liquidity_outside: f64,
+    pub seconds_per_liquidity_outside_x128: U160,
     /// the seconds spent on the other side of the tick (relative to the current tick)
     /// only has relative meaning, not absolute — the value depends on when the tick is initialized
-    seconds_outside: u32,
+    pub seconds_outside: u32,
     // true iff the tick is initialized, i.e. the value is exactly equivalent to the expression liquidityGross != 0
     // these 8 bits are set to prevent fresh sstores when crossing newly initialized ticks
-    initialized: bool,
+    pub initialized: bool,
 }
 
 /// Derives max liquidity per tick from given tick spacing. Executed within the pool constructor
 /// # Arguments
 ///
-/// * `tickSpacing` The amount of required tick separation, realized in multiples of `tickSpacing`
-///     e.g., a tickSpacing of 3 requires ticks to be initialized every 3rd tick i.e., ..., -6, -3, 0, 3, 6, ...
+/// * `tick_spacing` The amount of required tick separation, realized in multiples of `tick_spacing`
+///   e.g., a tick_spacing of 3 requires ticks to be initialized every 3rd tick i.e., ..., -6, -3, 0, 3, 6, ...
+///
 /// returns the max liquidity per tick
-/// TODO: this is incorrect due to int type things! check it.
-// fn tick_spacing_to_max_liquidity_per_tick(tick_spacing: i32) -> u128 {
-//     let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
-//     let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
-//     let num_ticks = ((max_tick - min_tick) / tick_spacing) + 1;
-//     return u128::MAX / num_ticks;
-// }
+pub fn tick_spacing_to_max_liquidity_per_tick(tick_spacing: i32) -> u128 {
+    let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    let num_ticks = ((max_tick - min_tick) / tick_spacing) as u128 + 1;
+    u128::MAX / num_ticks
+}
+
+/// the pool's ticks. persistent, so cloning a pool shares the table until one side writes to it
 pub type TickTable = HashMap<Tick, TickInfo>;
 
 /// Retrieves fee growth data
@@ -56,19 +59,16 @@ pub type TickTable = HashMap<Tick, TickInfo>;
 /// * `fee_growth_global` The all-time global fee growth, per unit of liquidity, in token0 and token1
 ///
 /// returns the all-time fee growth in token0 and token1, per unit of liquidity, inside the position's tick boundaries
-fn get_fee_growth_inside(
+pub fn get_fee_growth_inside(
     table: &TickTable,
     tick_lower: Tick,
     tick_upper: Tick,
     tick_current: Tick,
-    fee_growth_global: Fee,
-) -> Result<Fee> {
-    let lower_data = table
-        .get(tick_lower.borrow())
-        .ok_or(anyhow!("tick not found"))?;
-    let upper_data = table
-        .get(tick_upper.borrow())
-        .ok_or(anyhow!("tick not found"))?;
+    fee_growth_global: FeeGrowth,
+) -> Result<FeeGrowth> {
+    // ticks that were never touched read as all zeroes, like a solidity mapping
+    let lower_data = table.get(&tick_lower).cloned().unwrap_or_default();
+    let upper_data = table.get(&tick_upper).cloned().unwrap_or_default();
 
     // calculate fee growth below
     let fee_growth_below = if tick_current >= tick_lower {
@@ -93,29 +93,30 @@ fn get_fee_growth_inside(
 /// * `tick_current` The current tick
 /// * `liquidity_delta` A new amount of liquidity to be added (subtracted) when tick is crossed from left to right (right to left)
 /// * `fee_growth_global` The all-time global fee growth, per unit of liquidity, in token0 and token1
-/// * `seconds_per_liquidity_cumulative` The all-time seconds per max(1, liquidity) of the pool
+/// * `seconds_per_liquidity_cumulative_x128` The all-time seconds per max(1, liquidity) of the pool
 /// * `tick_cumulative`  The tick * time elapsed since the pool was first initialized
 /// * `time` The current block timestamp cast to a uint32
 /// * `is_upper` true for updating a position's upper tick, or false for updating a position's lower tick
 /// * `max_liquidity` the maximum liquidity allocation for a single tick
 ///
 /// returns true if the tick was flipped from initialized to uninitialized, or vice versa
-fn update(
+#[allow(clippy::too_many_arguments)]
+pub fn update(
     table: &mut TickTable,
     tick: Tick,
     tick_current: Tick,
     liquidity_delta: i128,
-    fee_growth_global: Fee,
-    seconds_per_liquidity_cumulative: f64,
-    tick_cumulative: i64,
+    fee_growth_global: FeeGrowth,
+    seconds_per_liquidity_cumulative_x128: U160,
+    tick_cumulative: Int56,
     time: u32,
     is_upper: bool,
     max_liquidity: u128,
 ) -> Result<bool> {
-    let mut new_info = table.get(&tick).ok_or(anyhow!("tick not found"))?.clone();
+    // ticks that were never touched read as all zeroes, like a solidity mapping
+    let mut new_info = table.get(&tick).cloned().unwrap_or_default();
     let liquidity_gross_before = new_info.liquidity_gross;
-    // TODO: they use a library called liquiditymath here- maybe we implement that?
-    let liquidity_gross_after = liq_math::addDelta(liquidity_gross_before, liquidity_delta)?;
+    let liquidity_gross_after = liq_math::add_delta(liquidity_gross_before, liquidity_delta)?;
     if liquidity_gross_after > max_liquidity {
         return Err(anyhow!("LO"));
     };
@@ -125,7 +126,7 @@ fn update(
         //  by convention, we assume that all growth before a tick was initialized happened _below_ the tick
         if tick <= tick_current {
             new_info.fee_growth_outside = fee_growth_global;
-            new_info.seconds_per_liquidity_outside = seconds_per_liquidity_cumulative;
+            new_info.seconds_per_liquidity_outside_x128 = seconds_per_liquidity_cumulative_x128;
             new_info.tick_cumulative_outside = tick_cumulative;
             new_info.seconds_outside = time;
         }
@@ -134,65 +135,273 @@ fn update(
 
     new_info.liquidity_gross = liquidity_gross_after;
 
-    // FIXME i think this math here might be wrong!
+    // when the lower (upper) tick is crossed left to right (right to left), liquidity must be added (removed)
     new_info.liquidity_net = if is_upper {
-        liq_math::addDelta(new_info.liquidity_net.to_owned(), -liquidity_delta)
+        new_info.liquidity_net.checked_sub(liquidity_delta)
     } else {
-        liq_math::addDelta(new_info.liquidity_net.to_owned(), liquidity_delta)
-    }?;
+        new_info.liquidity_net.checked_add(liquidity_delta)
+    }
+    .ok_or(anyhow!("liquidity_net overflowed int128"))?;
 
-    table.insert(tick, *new_info);
+    table.insert(tick, new_info);
 
     Ok(flipped)
 }
 
-pub fn tick_from_sqrt_price(sqrt_price: f64) -> u32 {
-    unimplemented!("need to do this lol")
+/// Clears tick data
+/// # Arguments
+///
+/// * `tick` The tick that will be cleared
+pub fn clear(table: &mut TickTable, tick: Tick) {
+    table.remove(&tick);
 }
 
-// TODO: is this even necessary?
-// @notice Clears tick data
-// @param self The mapping containing all initialized tick information for initialized ticks
-// @param tick The tick that will be cleared
-// function clear(mapping(int24 => Tick.Info) storage self, int24 tick) internal {
-//     delete self[tick];
-// }
-
 /// Transitions to next tick as needed by price movement
 /// # Arguments
 ///
 /// * `tick`  The destination tick of the transition
 /// * `fee_growth_global` The all-time global fee growth, per unit of liquidity, in token0 and token1
-/// * `seconds_per_liquidity_cumulative` The current seconds per liquidity
+/// * `seconds_per_liquidity_cumulative_x128` The current seconds per liquidity
 /// * `tick_cumulative`  The tick * time elapsed since the pool was first initialized
 /// * `time` The current block.timestamp
 ///
 /// returns The amount of liquidity added (subtracted) when tick is crossed from left to right (right to left)
-pub(crate) fn cross(
+pub fn cross(
     table: &mut TickTable,
     tick: Tick,
-    fee_growth_global: Fee,
-    seconds_per_liquidity_cumulative: f64,
-    tick_cumulative: i64,
+    fee_growth_global: FeeGrowth,
+    seconds_per_liquidity_cumulative_x128: U160,
+    tick_cumulative: Int56,
     time: u32,
-) -> Result<u128> {
-    let info = table.get(tick.borrow()).ok_or(anyhow!("tick not found"))?;
-    let new_info = TickInfo {
-        fee_growth_outside: fee_growth_global - info.fee_growth_outside.clone(),
-        seconds_per_liquidity_outside: seconds_per_liquidity_cumulative
-            - info.seconds_per_liquidity_outside.clone(),
-        tick_cumulative_outside: tick_cumulative - info.tick_cumulative_outside.clone(),
-        seconds_outside: time - info.seconds_outside.clone(),
-        ..*info.clone()
-    };
-    table.insert(tick, new_info);
-    Ok(new_info.liquidity_net.clone())
+) -> Result<i128> {
+    let info = table.get_mut(&tick).ok_or(anyhow!("tick not found"))?;
+    info.fee_growth_outside = fee_growth_global - info.fee_growth_outside;
+    info.seconds_per_liquidity_outside_x128 =
+        seconds_per_liquidity_cumulative_x128.wrapping_sub(info.seconds_per_liquidity_outside_x128);
+    info.tick_cumulative_outside = tick_cumulative.wrapping_sub(info.tick_cumulative_outside);
+    info.seconds_outside = time.wrapping_sub(info.seconds_outside);
+    Ok(info.liquidity_net)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::solidints::U256;
+
+    fn update_simple(
+        table: &mut TickTable,
+        tick: Tick,
+        tick_current: Tick,
+        liquidity_delta: i128,
+        is_upper: bool,
+    ) -> Result<bool> {
+        update(
+            table,
+            tick,
+            tick_current,
+            liquidity_delta,
+            FeeGrowth::zero(),
+            U160::zero(),
+            Int56::zero(),
+            0,
+            is_upper,
+            3,
+        )
+    }
+
+    #[test]
+    fn max_liquidity_per_tick() {
+        // the fee tier spacings
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(10),
+            1917569901783203986719870431555990
+        );
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(60),
+            11505743598341114571880798222544994
+        );
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(200),
+            38350317471085141830651933667504588
+        );
+        // only the min and max tick, and the middle
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(887272),
+            u128::MAX / 3
+        );
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(2302),
+            441351967472034323558203122479595605
+        );
+        assert_eq!(
+            tick_spacing_to_max_liquidity_per_tick(1),
+            u128::MAX / 1774545
+        );
+    }
+
+    #[test]
+    fn update_flips_and_nets() {
+        let mut table = TickTable::new();
+        assert!(update_simple(&mut table, 0, 0, 1, false).unwrap());
+        assert!(!update_simple(&mut table, 0, 0, 1, true).unwrap());
+        assert_eq!(table[&0].liquidity_gross, 2);
+        assert_eq!(table[&0].liquidity_net, 0);
+        assert!(update_simple(&mut table, 0, 0, 2, false).is_err()); // over max liquidity
+        assert!(!update_simple(&mut table, 0, 0, -1, false).unwrap());
+        assert_eq!(table[&0].liquidity_net, -1);
+        assert!(update_simple(&mut table, 0, 0, -1, true).unwrap());
+        assert_eq!(table[&0].liquidity_gross, 0);
+    }
+
+    #[test]
+    fn update_assumes_growth_happens_below() {
+        let mut table = TickTable::new();
+        let global = FeeGrowth::new(1.into(), 2.into());
+        update(
+            &mut table,
+            2,
+            1,
+            1,
+            global,
+            U160::from(5u32),
+            Int56::new(6).unwrap(),
+            7,
+            false,
+            3,
+        )
+        .unwrap();
+        assert_eq!(table[&2].fee_growth_outside.token_0, U256::zero());
+        assert_eq!(table[&2].seconds_outside, 0);
+        update(
+            &mut table,
+            1,
+            1,
+            1,
+            global,
+            U160::from(5u32),
+            Int56::new(6).unwrap(),
+            7,
+            false,
+            3,
+        )
+        .unwrap();
+        assert_eq!(table[&1].fee_growth_outside.token_1, 2.into());
+        assert_eq!(table[&1].tick_cumulative_outside, Int56::new(6).unwrap());
+        assert_eq!(table[&1].seconds_outside, 7);
+    }
+
+    #[test]
+    fn fee_growth_inside() {
+        let mut table = TickTable::new();
+        update(
+            &mut table,
+            -2,
+            0,
+            1,
+            FeeGrowth::new(3.into(), 3.into()),
+            U160::zero(),
+            Int56::zero(),
+            0,
+            false,
+            3,
+        )
+        .unwrap();
+        update(
+            &mut table,
+            2,
+            0,
+            1,
+            FeeGrowth::new(3.into(), 3.into()),
+            U160::zero(),
+            Int56::zero(),
+            0,
+            true,
+            3,
+        )
+        .unwrap();
+        let inside =
+            get_fee_growth_inside(&table, -2, 2, 0, FeeGrowth::new(15.into(), 15.into())).unwrap();
+        assert_eq!(inside.token_0, 12.into());
+        // current tick above the range: everything since the upper tick was crossed is outside
+        cross(
+            &mut table,
+            2,
+            FeeGrowth::new(10.into(), 10.into()),
+            U160::zero(),
+            Int56::zero(),
+            0,
+        )
+        .unwrap();
+        let inside =
+            get_fee_growth_inside(&table, -2, 2, 2, FeeGrowth::new(15.into(), 15.into())).unwrap();
+        assert_eq!(inside.token_1, 7.into());
+    }
+
+    /// Tick.spec: "works correctly with overflow on inside tick"
+    #[test]
+    fn fee_growth_inside_wraps() {
+        let mut table = TickTable::new();
+        for (tick, outside) in [
+            (-2, FeeGrowth::new(U256::MAX - 3, U256::MAX - 2)),
+            (2, FeeGrowth::new(3.into(), 5.into())),
+        ] {
+            table.insert(
+                tick,
+                TickInfo {
+                    fee_growth_outside: outside,
+                    initialized: true,
+                    ..Default::default()
+                },
+            );
+        }
+        let inside =
+            get_fee_growth_inside(&table, -2, 2, 0, FeeGrowth::new(15.into(), 15.into())).unwrap();
+        assert_eq!(inside, FeeGrowth::new(16.into(), 13.into()));
+    }
+
     #[test]
-    fn it_works() {
-        unimplemented!()
+    fn cross_flips_outside_values() {
+        let mut table = TickTable::new();
+        update(
+            &mut table,
+            2,
+            0,
+            1,
+            FeeGrowth::new(1.into(), 2.into()),
+            U160::from(3u32),
+            Int56::new(4).unwrap(),
+            5,
+            true,
+            3,
+        )
+        .unwrap();
+        update(
+            &mut table,
+            2,
+            3,
+            1,
+            FeeGrowth::new(1.into(), 2.into()),
+            U160::from(3u32),
+            Int56::new(4).unwrap(),
+            5,
+            true,
+            3,
+        )
+        .unwrap();
+        let liquidity_net = cross(
+            &mut table,
+            2,
+            FeeGrowth::new(7.into(), 9.into()),
+            U160::from(8u32),
+            Int56::new(15).unwrap(),
+            10,
+        )
+        .unwrap();
+        assert_eq!(liquidity_net, -2);
+        let info = &table[&2];
+        assert_eq!(info.fee_growth_outside.token_0, 7.into());
+        assert_eq!(info.seconds_per_liquidity_outside_x128, U160::from(8u32));
+        assert_eq!(info.tick_cumulative_outside, Int56::new(15).unwrap());
+        assert_eq!(info.seconds_outside, 10);
     }
 }
@@ -0,0 +1,181 @@
+use crate::solidints::U256;
+use crate::solidmath::bit_math;
+use anyhow::{ensure, Result};
+use im::HashMap;
+
+/// Packed tick initialized state, like TickBitmap.sol.
+/// The mapping uses int16 for keys since ticks are represented as int24 and there are 256 (2^8) values per word.
+/// Missing words are all zeroes.
+pub type TickBitmap = HashMap<i16, U256>;
+
+/// Computes the position in the mapping where the initialized bit for a tick lives
+/// # Arguments
+///
+/// * `tick` The tick for which to compute the position, already divided by the tick spacing
+///
+/// returns the key in the mapping containing the word in which the bit is stored,
+/// and the bit position in the word where the flag is stored
+fn position(tick: i32) -> (i16, u8) {
+    ((tick >> 8) as i16, (tick & 0xff) as u8)
+}
+
+/// Flips the initialized state for a given tick from false to true, or vice versa.
+/// The pool calls this whenever `tick::update` reports that a tick flipped, so the two stay in sync.
+/// # Arguments
+///
+/// * `tick` The tick to flip
+/// * `tick_spacing` The spacing between usable ticks
+pub fn flip_tick(bitmap: &mut TickBitmap, tick: i32, tick_spacing: i32) -> Result<()> {
+    ensure!(tick % tick_spacing == 0, "tick not spaced"); // ensure that the tick is spaced
+    let (word_pos, bit_pos) = position(tick / tick_spacing);
+    let mask = U256::one() << bit_pos;
+    let word = bitmap.entry(word_pos).or_insert_with(U256::zero);
+    *word = *word ^ mask;
+    if word.is_zero() {
+        bitmap.remove(&word_pos);
+    }
+    Ok(())
+}
+
+/// Whether the tick's bit is set in the bitmap
+pub fn is_initialized(bitmap: &TickBitmap, tick: i32, tick_spacing: i32) -> bool {
+    if tick % tick_spacing != 0 {
+        return false;
+    }
+    let (word_pos, bit_pos) = position(tick / tick_spacing);
+    bitmap
+        .get(&word_pos)
+        .is_some_and(|word| word.bit(bit_pos as usize))
+}
+
+/// Returns the next initialized tick contained in the same word (or adjacent word) as the tick that is either
+/// to the left (less than or equal to) or right (greater than) of the given tick
+/// # Arguments
+///
+/// * `tick` The starting tick
+/// * `tick_spacing` The spacing between usable ticks
+/// * `lte` Whether to search for the next initialized tick to the left (less than or equal to the starting tick)
+///
+/// returns the next initialized or uninitialized tick up to 256 ticks away from the current tick,
+/// and whether the next tick is initialized, as the function only searches within up to 256 ticks
+pub fn next_initialized_tick_within_one_word(
+    bitmap: &TickBitmap,
+    tick: i32,
+    tick_spacing: i32,
+    lte: bool,
+) -> Result<(i32, bool)> {
+    let mut compressed = tick / tick_spacing;
+    if tick < 0 && tick % tick_spacing != 0 {
+        compressed -= 1; // round towards negative infinity
+    };
+
+    if lte {
+        let (word_pos, bit_pos) = position(compressed);
+        // all the 1s at or to the right of the current bit_pos
+        let mask = (U256::one() << bit_pos) - 1 + (U256::one() << bit_pos);
+        let masked = bitmap.get(&word_pos).copied().unwrap_or_default() & mask;
+
+        // if there are no initialized ticks to the right of or at the current tick, return rightmost in the word
+        let initialized = !masked.is_zero();
+        // overflow/underflow is possible, but prevented externally by limiting both tick_spacing and tick
+        let next = if initialized {
+            (compressed - (bit_pos - bit_math::most_significant_bit(masked)?) as i32) * tick_spacing
+        } else {
+            (compressed - bit_pos as i32) * tick_spacing
+        };
+        Ok((next, initialized))
+    } else {
+        // start from the word of the next tick, since the current tick state doesn't matter
+        let (word_pos, bit_pos) = position(compressed + 1);
+        // all the 1s at or to the left of the bit_pos
+        let mask = !((U256::one() << bit_pos) - 1);
+        let masked = bitmap.get(&word_pos).copied().unwrap_or_default() & mask;
+
+        // if there are no initialized ticks to the left of the current tick, return leftmost in the word
+        let initialized = !masked.is_zero();
+        // overflow/underflow is possible, but prevented externally by limiting both tick_spacing and tick
+        let next = if initialized {
+            (compressed + 1 + (bit_math::least_significant_bit(masked)? - bit_pos) as i32)
+                * tick_spacing
+        } else {
+            (compressed + 1 + (u8::MAX - bit_pos) as i32) * tick_spacing
+        };
+        Ok((next, initialized))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap_with(ticks: &[i32]) -> TickBitmap {
+        let mut bitmap = TickBitmap::new();
+        for tick in ticks {
+            flip_tick(&mut bitmap, *tick, 1).unwrap();
+        }
+        bitmap
+    }
+
+    fn spec_bitmap() -> TickBitmap {
+        bitmap_with(&[-200, -55, -4, 70, 78, 84, 139, 240, 535])
+    }
+
+    #[test]
+    fn flip_tick_toggles() {
+        let mut bitmap = TickBitmap::new();
+        assert!(!is_initialized(&bitmap, -230, 1));
+        flip_tick(&mut bitmap, -230, 1).unwrap();
+        assert!(is_initialized(&bitmap, -230, 1));
+        assert!(!is_initialized(&bitmap, -231, 1));
+        assert!(!is_initialized(&bitmap, -230 + 256, 1));
+        flip_tick(&mut bitmap, -230, 1).unwrap();
+        assert!(!is_initialized(&bitmap, -230, 1));
+        assert!(bitmap.is_empty());
+        assert!(flip_tick(&mut bitmap, 7, 60).is_err());
+    }
+
+    #[test]
+    fn next_tick_greater_than() {
+        let bitmap = spec_bitmap();
+        let next = |tick| next_initialized_tick_within_one_word(&bitmap, tick, 1, false).unwrap();
+        assert_eq!(next(78), (84, true));
+        assert_eq!(next(-55), (-4, true));
+        assert_eq!(next(77), (78, true));
+        assert_eq!(next(-56), (-55, true));
+        assert_eq!(next(255), (511, false));
+        assert_eq!(next(383), (511, false));
+        assert_eq!(next(-257), (-200, true));
+        assert_eq!(next(508), (511, false));
+    }
+
+    #[test]
+    fn next_tick_less_than_or_equal() {
+        let mut bitmap = spec_bitmap();
+        let next = |bitmap: &TickBitmap, tick| {
+            next_initialized_tick_within_one_word(bitmap, tick, 1, true).unwrap()
+        };
+        assert_eq!(next(&bitmap, 78), (78, true));
+        assert_eq!(next(&bitmap, 79), (78, true));
+        assert_eq!(next(&bitmap, 258), (256, false));
+        assert_eq!(next(&bitmap, 256), (256, false));
+        assert_eq!(next(&bitmap, 72), (70, true));
+        assert_eq!(next(&bitmap, -257), (-512, false));
+        assert_eq!(next(&bitmap, 1023), (768, false));
+        assert_eq!(next(&bitmap, 900), (768, false));
+        flip_tick(&mut bitmap, 329, 1).unwrap();
+        assert_eq!(next(&bitmap, 456), (329, true));
+    }
+
+    #[test]
+    fn respects_tick_spacing() {
+        let mut bitmap = TickBitmap::new();
+        flip_tick(&mut bitmap, -120, 60).unwrap();
+        flip_tick(&mut bitmap, 180, 60).unwrap();
+        let next =
+            |tick, lte| next_initialized_tick_within_one_word(&bitmap, tick, 60, lte).unwrap();
+        assert_eq!(next(0, false), (180, true));
+        assert_eq!(next(-1, true), (-120, true));
+        assert_eq!(next(-61, true), (-120, true));
+        assert_eq!(next(180, false), (255 * 60, false));
+    }
+}
@@ -0,0 +1,595 @@
+//! UniswapV2Pair: a constant product pool, with its LP token, protocol fee and cumulative price oracle.
+//! Tokens come in the way they do onchain, by being sent to the pair (`transfer_in`) before calling
+//! `mint` or `swap`. Tokens going out are returned rather than credited to anyone.
+//! There are no flash swaps, the input has to be sent in first, like the router does.
+
+use super::Address;
+use crate::solidints::U256;
+use anyhow::{anyhow, ensure, Result};
+use im::HashMap;
+
+pub const MINIMUM_LIQUIDITY: u64 = 1000;
+
+/// reserves are stored as uint112
+fn max_reserve() -> U256 {
+    (U256::one() << 112) - 1
+}
+
+fn add(x: U256, y: U256) -> Result<U256> {
+    x.checked_add(y).ok_or(anyhow!("ds-math-add-overflow"))
+}
+fn sub(x: U256, y: U256) -> Result<U256> {
+    x.checked_sub(y).ok_or(anyhow!("ds-math-sub-underflow"))
+}
+fn mul(x: U256, y: U256) -> Result<U256> {
+    x.checked_mul(y).ok_or(anyhow!("ds-math-mul-overflow"))
+}
+
+/// A simulated Uniswap V2 pair, following UniswapV2Pair.sol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniV2Pair {
+    // the pair's own address, where LP tokens are sent to be burned
+    address: Address,
+    // where the protocol's share of the fees is minted to, if it's switched on
+    fee_to: Option<Address>,
+    // uses single storage slot, accessible via reserves()
+    reserve_0: U256,
+    reserve_1: U256,
+    block_timestamp_last: u32,
+    price_0_cumulative_last: U256,
+    price_1_cumulative_last: U256,
+    // reserve0 * reserve1, as of immediately after the most recent liquidity event
+    k_last: U256,
+    // what the pair holds of token0 and token1, including anything sent in and not yet synced
+    balance_0: U256,
+    balance_1: U256,
+    // the LP token
+    total_supply: U256,
+    balance_of: HashMap<Address, U256>,
+    // the simulated block.timestamp
+    block_timestamp: u32,
+}
+
+impl UniV2Pair {
+    pub fn new(address: Address, block_timestamp: u32) -> Self {
+        Self {
+            address,
+            fee_to: None,
+            reserve_0: U256::zero(),
+            reserve_1: U256::zero(),
+            block_timestamp_last: 0,
+            price_0_cumulative_last: U256::zero(),
+            price_1_cumulative_last: U256::zero(),
+            k_last: U256::zero(),
+            balance_0: U256::zero(),
+            balance_1: U256::zero(),
+            total_supply: U256::zero(),
+            balance_of: HashMap::new(),
+            block_timestamp,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+    /// the reserves and the timestamp of the last block they were updated in, like getReserves
+    pub fn reserves(&self) -> (U256, U256, u32) {
+        (self.reserve_0, self.reserve_1, self.block_timestamp_last)
+    }
+    pub fn price_cumulative_last(&self) -> (U256, U256) {
+        (self.price_0_cumulative_last, self.price_1_cumulative_last)
+    }
+    pub fn k_last(&self) -> U256 {
+        self.k_last
+    }
+    /// what the pair actually holds of token0 and token1
+    pub fn balances(&self) -> (U256, U256) {
+        (self.balance_0, self.balance_1)
+    }
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply
+    }
+    pub fn balance_of(&self, owner: Address) -> U256 {
+        self.balance_of.get(&owner).copied().unwrap_or_default()
+    }
+    pub fn fee_to(&self) -> Option<Address> {
+        self.fee_to
+    }
+    pub fn block_timestamp(&self) -> u32 {
+        self.block_timestamp
+    }
+
+    /// Switches the protocol fee, a sixth of the LPs' fee growth, on (to an address) or off. the factory's setFeeTo
+    pub fn set_fee_to(&mut self, fee_to: Option<Address>) {
+        self.fee_to = fee_to;
+    }
+
+    /// Moves the simulated block.timestamp. Only wraps around the way a uint32 timestamp does
+    pub fn set_block_timestamp(&mut self, block_timestamp: u32) -> Result<()> {
+        ensure!(
+            block_timestamp.wrapping_sub(self.block_timestamp) < 1 << 31,
+            "block timestamp went backwards"
+        );
+        self.block_timestamp = block_timestamp;
+        Ok(())
+    }
+
+    /// Sends tokens to the pair, ahead of a `mint` or `swap`, or as a donation
+    pub fn transfer_in(&mut self, amount_0: U256, amount_1: U256) -> Result<()> {
+        self.balance_0 = add(self.balance_0, amount_0)?;
+        self.balance_1 = add(self.balance_1, amount_1)?;
+        Ok(())
+    }
+
+    /// Moves LP tokens, like the pair's ERC20 transfer. Send them to `address()` to `burn` them
+    pub fn transfer(&mut self, from: Address, to: Address, value: U256) -> Result<()> {
+        let from_balance = sub(self.balance_of(from), value)?;
+        self.balance_of.insert(from, from_balance);
+        let to_balance = add(self.balance_of(to), value)?;
+        self.balance_of.insert(to, to_balance);
+        Ok(())
+    }
+
+    /// The cumulative prices as of now, counting the time since the last update, like
+    /// UniswapV2OracleLibrary.currentCumulativePrices
+    pub fn current_cumulative_prices(&self) -> (U256, U256) {
+        let mut pair = self.clone();
+        pair.accumulate_prices();
+        pair.price_cumulative_last()
+    }
+
+    /// Mints LP tokens for whatever has been sent in since the last update
+    ///
+    /// returns the liquidity minted
+    pub fn mint(&mut self, to: Address) -> Result<U256> {
+        self.atomically(|pair| {
+            let (reserve_0, reserve_1) = (pair.reserve_0, pair.reserve_1);
+            let amount_0 = sub(pair.balance_0, reserve_0)?;
+            let amount_1 = sub(pair.balance_1, reserve_1)?;
+
+            let fee_on = pair.mint_fee(reserve_0, reserve_1)?;
+            // must be defined here since total_supply can update in mint_fee
+            let total_supply = pair.total_supply;
+            let liquidity = if total_supply.is_zero() {
+                let liquidity = sub(
+                    mul(amount_0, amount_1)?.integer_sqrt(),
+                    MINIMUM_LIQUIDITY.into(),
+                )?;
+                // permanently lock the first MINIMUM_LIQUIDITY tokens
+                pair.mint_lp(Address::zero(), MINIMUM_LIQUIDITY.into())?;
+                liquidity
+            } else {
+                (mul(amount_0, total_supply)? / reserve_0)
+                    .min(mul(amount_1, total_supply)? / reserve_1)
+            };
+            ensure!(
+                liquidity > U256::zero(),
+                "UniswapV2: INSUFFICIENT_LIQUIDITY_MINTED"
+            );
+            pair.mint_lp(to, liquidity)?;
+
+            pair.update(pair.balance_0, pair.balance_1)?;
+            if fee_on {
+                // reserve0 and reserve1 are up-to-date
+                pair.k_last = mul(pair.reserve_0, pair.reserve_1)?;
+            }
+            Ok(liquidity)
+        })
+    }
+
+    /// Burns the LP tokens that have been sent to the pair, paying out its share of the reserves
+    ///
+    /// returns the amounts of token0 and token1 paid out
+    pub fn burn(&mut self) -> Result<(U256, U256)> {
+        self.atomically(|pair| {
+            let (reserve_0, reserve_1) = (pair.reserve_0, pair.reserve_1);
+            let liquidity = pair.balance_of(pair.address);
+
+            let fee_on = pair.mint_fee(reserve_0, reserve_1)?;
+            // must be defined here since total_supply can update in mint_fee
+            let total_supply = pair.total_supply;
+            // using balances ensures pro-rata distribution
+            let amount_0 = mul(liquidity, pair.balance_0)? / total_supply;
+            let amount_1 = mul(liquidity, pair.balance_1)? / total_supply;
+            ensure!(
+                !amount_0.is_zero() && !amount_1.is_zero(),
+                "UniswapV2: INSUFFICIENT_LIQUIDITY_BURNED"
+            );
+            pair.burn_lp(pair.address, liquidity)?;
+            pair.balance_0 -= amount_0;
+            pair.balance_1 -= amount_1;
+
+            pair.update(pair.balance_0, pair.balance_1)?;
+            if fee_on {
+                // reserve0 and reserve1 are up-to-date
+                pair.k_last = mul(pair.reserve_0, pair.reserve_1)?;
+            }
+            Ok((amount_0, amount_1))
+        })
+    }
+
+    /// Pays out `amount_0_out` and `amount_1_out`, as long as what has been sent in keeps k from shrinking after fees
+    pub fn swap(&mut self, amount_0_out: U256, amount_1_out: U256) -> Result<()> {
+        self.atomically(|pair| {
+            ensure!(
+                !amount_0_out.is_zero() || !amount_1_out.is_zero(),
+                "UniswapV2: INSUFFICIENT_OUTPUT_AMOUNT"
+            );
+            let (reserve_0, reserve_1) = (pair.reserve_0, pair.reserve_1);
+            ensure!(
+                amount_0_out < reserve_0 && amount_1_out < reserve_1,
+                "UniswapV2: INSUFFICIENT_LIQUIDITY"
+            );
+
+            // optimistically transfer tokens
+            pair.balance_0 = sub(pair.balance_0, amount_0_out)?;
+            pair.balance_1 = sub(pair.balance_1, amount_1_out)?;
+            let (balance_0, balance_1) = (pair.balance_0, pair.balance_1);
+
+            let amount_in = |balance: U256, reserve: U256, out: U256| {
+                if balance > reserve - out {
+                    balance - (reserve - out)
+                } else {
+                    U256::zero()
+                }
+            };
+            let amount_0_in = amount_in(balance_0, reserve_0, amount_0_out);
+            let amount_1_in = amount_in(balance_1, reserve_1, amount_1_out);
+            ensure!(
+                !amount_0_in.is_zero() || !amount_1_in.is_zero(),
+                "UniswapV2: INSUFFICIENT_INPUT_AMOUNT"
+            );
+
+            let balance_0_adjusted =
+                sub(mul(balance_0, 1000.into())?, mul(amount_0_in, 3.into())?)?;
+            let balance_1_adjusted =
+                sub(mul(balance_1, 1000.into())?, mul(amount_1_in, 3.into())?)?;
+            ensure!(
+                mul(balance_0_adjusted, balance_1_adjusted)?
+                    >= mul(mul(reserve_0, reserve_1)?, 1000000.into())?,
+                "UniswapV2: K"
+            );
+
+            pair.update(balance_0, balance_1)
+        })
+    }
+
+    /// Force balances to match reserves
+    ///
+    /// returns the amounts of token0 and token1 paid out
+    pub fn skim(&mut self) -> Result<(U256, U256)> {
+        let amount_0 = sub(self.balance_0, self.reserve_0)?;
+        let amount_1 = sub(self.balance_1, self.reserve_1)?;
+        self.balance_0 = self.reserve_0;
+        self.balance_1 = self.reserve_1;
+        Ok((amount_0, amount_1))
+    }
+
+    /// Force reserves to match balances
+    pub fn sync(&mut self) -> Result<()> {
+        self.update(self.balance_0, self.balance_1)
+    }
+
+    /// update reserves and, on the first call per block, price accumulators
+    fn update(&mut self, balance_0: U256, balance_1: U256) -> Result<()> {
+        ensure!(
+            balance_0 <= max_reserve() && balance_1 <= max_reserve(),
+            "UniswapV2: OVERFLOW"
+        );
+        self.accumulate_prices();
+        self.reserve_0 = balance_0;
+        self.reserve_1 = balance_1;
+        self.block_timestamp_last = self.block_timestamp;
+        Ok(())
+    }
+
+    fn accumulate_prices(&mut self) {
+        // overflow is desired
+        let time_elapsed = self.block_timestamp.wrapping_sub(self.block_timestamp_last);
+        if time_elapsed > 0 && !self.reserve_0.is_zero() && !self.reserve_1.is_zero() {
+            // * never overflows, and + overflow is desired
+            let price_0 = (self.reserve_1 << 112) / self.reserve_0;
+            let price_1 = (self.reserve_0 << 112) / self.reserve_1;
+            self.price_0_cumulative_last = self
+                .price_0_cumulative_last
+                .overflowing_add(price_0 * time_elapsed)
+                .0;
+            self.price_1_cumulative_last = self
+                .price_1_cumulative_last
+                .overflowing_add(price_1 * time_elapsed)
+                .0;
+        }
+    }
+
+    /// if fee is on, mint liquidity equivalent to 1/6th of the growth in sqrt(k)
+    fn mint_fee(&mut self, reserve_0: U256, reserve_1: U256) -> Result<bool> {
+        let k_last = self.k_last;
+        match self.fee_to {
+            Some(fee_to) => {
+                if !k_last.is_zero() {
+                    let root_k = mul(reserve_0, reserve_1)?.integer_sqrt();
+                    let root_k_last = k_last.integer_sqrt();
+                    if root_k > root_k_last {
+                        let numerator = mul(self.total_supply, sub(root_k, root_k_last)?)?;
+                        let denominator = add(mul(root_k, 5.into())?, root_k_last)?;
+                        let liquidity = numerator / denominator;
+                        if !liquidity.is_zero() {
+                            self.mint_lp(fee_to, liquidity)?;
+                        }
+                    }
+                }
+                Ok(true)
+            }
+            None => {
+                if !k_last.is_zero() {
+                    self.k_last = U256::zero();
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    fn mint_lp(&mut self, to: Address, value: U256) -> Result<()> {
+        self.total_supply = add(self.total_supply, value)?;
+        let balance = add(self.balance_of(to), value)?;
+        self.balance_of.insert(to, balance);
+        Ok(())
+    }
+
+    fn burn_lp(&mut self, from: Address, value: U256) -> Result<()> {
+        let balance = sub(self.balance_of(from), value)?;
+        self.balance_of.insert(from, balance);
+        self.total_supply = sub(self.total_supply, value)?;
+        Ok(())
+    }
+
+    /// an operation that fails leaves the pair the way it was, like a reverted transaction
+    fn atomically<T>(&mut self, op: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.clone();
+        let result = op(self);
+        if result.is_err() {
+            *self = saved;
+        }
+        result
+    }
+}
+
+/// given some amount of an asset and pair reserves, returns an equivalent amount of the other asset
+pub fn quote(amount_a: U256, reserve_a: U256, reserve_b: U256) -> Result<U256> {
+    ensure!(!amount_a.is_zero(), "UniswapV2Library: INSUFFICIENT_AMOUNT");
+    ensure!(
+        !reserve_a.is_zero() && !reserve_b.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_LIQUIDITY"
+    );
+    Ok(mul(amount_a, reserve_b)? / reserve_a)
+}
+
+/// given an input amount of an asset and pair reserves, returns the maximum output amount of the other asset
+pub fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256) -> Result<U256> {
+    ensure!(
+        !amount_in.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_INPUT_AMOUNT"
+    );
+    ensure!(
+        !reserve_in.is_zero() && !reserve_out.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_LIQUIDITY"
+    );
+    let amount_in_with_fee = mul(amount_in, 997.into())?;
+    let numerator = mul(amount_in_with_fee, reserve_out)?;
+    let denominator = add(mul(reserve_in, 1000.into())?, amount_in_with_fee)?;
+    Ok(numerator / denominator)
+}
+
+/// given an output amount of an asset and pair reserves, returns a required input amount of the other asset
+pub fn get_amount_in(amount_out: U256, reserve_in: U256, reserve_out: U256) -> Result<U256> {
+    ensure!(
+        !amount_out.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_OUTPUT_AMOUNT"
+    );
+    ensure!(
+        !reserve_in.is_zero() && !reserve_out.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_LIQUIDITY"
+    );
+    let numerator = mul(mul(reserve_in, amount_out)?, 1000.into())?;
+    let denominator = mul(sub(reserve_out, amount_out)?, 997.into())?;
+    ensure!(
+        !denominator.is_zero(),
+        "UniswapV2Library: INSUFFICIENT_LIQUIDITY"
+    );
+    add(numerator / denominator, 1.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn wallet() -> Address {
+        Address::from(0xc0ffeeu32)
+    }
+
+    fn pair_with(amount_0: u128, amount_1: u128) -> UniV2Pair {
+        let mut pair = UniV2Pair::new(Address::from(0x9a12u32), 0);
+        pair.transfer_in(amount_0.into(), amount_1.into()).unwrap();
+        pair.mint(wallet()).unwrap();
+        pair
+    }
+
+    fn e18(amount: u128) -> U256 {
+        U256::from(amount * E18)
+    }
+
+    #[test]
+    fn mint_locks_minimum_liquidity() {
+        let mut pair = UniV2Pair::new(Address::from(0x9a12u32), 0);
+        pair.transfer_in(e18(1), e18(4)).unwrap();
+        assert_eq!(pair.mint(wallet()).unwrap(), e18(2) - 1000);
+        assert_eq!(pair.total_supply(), e18(2));
+        assert_eq!(pair.balance_of(Address::zero()), 1000.into());
+        assert_eq!(pair.reserves(), (e18(1), e18(4), 0));
+
+        // nothing sent in, nothing minted
+        assert_eq!(
+            pair.mint(wallet()).unwrap_err().to_string(),
+            "UniswapV2: INSUFFICIENT_LIQUIDITY_MINTED"
+        );
+
+        let mut tiny = UniV2Pair::new(Address::from(0x9a12u32), 0);
+        tiny.transfer_in(1000.into(), 1000.into()).unwrap();
+        assert!(tiny.mint(wallet()).is_err());
+        assert_eq!(tiny.total_supply(), U256::zero());
+    }
+
+    #[test]
+    fn amounts_out_and_in() {
+        // getInputPrice cases from the V2 core tests, [swap, token0, token1, expected output]
+        let cases = [
+            (1, 5, 10, "1662497915624478906"),
+            (1, 10, 5, "453305446940074565"),
+            (2, 5, 10, "2851015155847869602"),
+            (2, 10, 5, "831248957812239453"),
+            (1, 10, 10, "906610893880149131"),
+            (1, 100, 100, "987158034397061298"),
+            (1, 1000, 1000, "996006981039903216"),
+        ];
+        for (swap, reserve_in, reserve_out, expected) in cases {
+            let expected = U256::from_dec_str(expected).unwrap();
+            let amount_out = get_amount_out(e18(swap), e18(reserve_in), e18(reserve_out)).unwrap();
+            assert_eq!(amount_out, expected);
+
+            // the pair takes exactly that, and not a wei more
+            let mut pair = pair_with(reserve_in * E18, reserve_out * E18);
+            pair.transfer_in(e18(swap), 0.into()).unwrap();
+            assert_eq!(
+                pair.swap(0.into(), expected + 1).unwrap_err().to_string(),
+                "UniswapV2: K"
+            );
+            pair.swap(0.into(), expected).unwrap();
+
+            let amount_in = get_amount_in(expected, e18(reserve_in), e18(reserve_out)).unwrap();
+            assert!(amount_in <= e18(swap));
+            assert!(
+                get_amount_out(amount_in, e18(reserve_in), e18(reserve_out)).unwrap() >= expected
+            );
+        }
+        assert!(get_amount_out(0.into(), e18(1), e18(1)).is_err());
+        assert!(get_amount_in(e18(1), e18(1), e18(1)).is_err());
+        assert_eq!(quote(e18(1), e18(2), e18(4)).unwrap(), e18(2));
+    }
+
+    #[test]
+    fn swap_checks() {
+        let mut pair = pair_with(5 * E18, 10 * E18);
+        assert_eq!(
+            pair.swap(0.into(), 0.into()).unwrap_err().to_string(),
+            "UniswapV2: INSUFFICIENT_OUTPUT_AMOUNT"
+        );
+        assert_eq!(
+            pair.swap(0.into(), e18(10)).unwrap_err().to_string(),
+            "UniswapV2: INSUFFICIENT_LIQUIDITY"
+        );
+        assert_eq!(
+            pair.swap(0.into(), e18(1)).unwrap_err().to_string(),
+            "UniswapV2: INSUFFICIENT_INPUT_AMOUNT"
+        );
+        assert_eq!(pair.balances(), (e18(5), e18(10)));
+    }
+
+    #[test]
+    fn burn_returns_the_share() {
+        let mut pair = pair_with(3 * E18, 3 * E18);
+        let liquidity = pair.balance_of(wallet());
+        assert_eq!(liquidity, e18(3) - 1000);
+        assert_eq!(
+            pair.burn().unwrap_err().to_string(),
+            "UniswapV2: INSUFFICIENT_LIQUIDITY_BURNED"
+        );
+        pair.transfer(wallet(), pair.address(), liquidity).unwrap();
+        assert_eq!(pair.burn().unwrap(), (e18(3) - 1000, e18(3) - 1000));
+        assert_eq!(pair.total_supply(), 1000.into());
+        assert_eq!(pair.reserves(), (1000.into(), 1000.into(), 0));
+        assert!(pair.transfer(wallet(), pair.address(), 1.into()).is_err());
+    }
+
+    #[test]
+    fn fee_to_gets_a_sixth_of_the_growth() {
+        let mut pair = UniV2Pair::new(Address::from(0x9a12u32), 0);
+        let fee_to = Address::from(0xfeeu32);
+        pair.set_fee_to(Some(fee_to));
+        pair.transfer_in(e18(1000), e18(1000)).unwrap();
+        pair.mint(wallet()).unwrap();
+
+        pair.transfer_in(0.into(), e18(1)).unwrap();
+        pair.swap(U256::from_dec_str("996006981039903216").unwrap(), 0.into())
+            .unwrap();
+
+        let liquidity = pair.balance_of(wallet());
+        pair.transfer(wallet(), pair.address(), liquidity).unwrap();
+        pair.burn().unwrap();
+        let protocol = U256::from(249750499251388u64);
+        assert_eq!(pair.total_supply(), protocol + 1000);
+        assert_eq!(pair.balance_of(fee_to), protocol);
+        // the protocol's share and the locked liquidity is all that's left
+        assert_eq!(
+            pair.balances(),
+            (
+                U256::from(1000 + 249501683697445u64),
+                U256::from(1000 + 250000187312969u64)
+            )
+        );
+    }
+
+    #[test]
+    fn skim_and_sync() {
+        let mut pair = pair_with(E18, E18);
+        pair.transfer_in(5.into(), 7.into()).unwrap();
+        let mut synced = pair.clone();
+        assert_eq!(pair.skim().unwrap(), (5.into(), 7.into()));
+        assert_eq!(pair.balances(), (e18(1), e18(1)));
+
+        synced.sync().unwrap();
+        assert_eq!(synced.reserves(), (e18(1) + 5, e18(1) + 7, 0));
+        assert_eq!(synced.skim().unwrap(), (0.into(), 0.into()));
+
+        let mut overflowing = UniV2Pair::new(Address::from(0x9a12u32), 0);
+        overflowing
+            .transfer_in(U256::one() << 112, 1.into())
+            .unwrap();
+        assert_eq!(
+            overflowing.sync().unwrap_err().to_string(),
+            "UniswapV2: OVERFLOW"
+        );
+    }
+
+    #[test]
+    fn cumulative_prices() {
+        let mut pair = pair_with(3 * E18, 3 * E18);
+        let one = U256::one() << 112;
+        assert_eq!(pair.price_cumulative_last(), (0.into(), 0.into()));
+
+        pair.set_block_timestamp(1).unwrap();
+        pair.sync().unwrap();
+        assert_eq!(pair.price_cumulative_last(), (one, one));
+        // a sync in the same block doesn't add anything
+        pair.sync().unwrap();
+        assert_eq!(pair.price_cumulative_last(), (one, one));
+
+        // 3 in for 1 out, making the price 6:2
+        pair.set_block_timestamp(10).unwrap();
+        pair.transfer_in(e18(3), 0.into()).unwrap();
+        pair.swap(0.into(), e18(1)).unwrap();
+        assert_eq!(pair.price_cumulative_last(), (one * 10, one * 10));
+
+        pair.set_block_timestamp(20).unwrap();
+        let expected = (
+            one * 10 + (U256::from(2) << 112) / 6 * 10,
+            one * 10 + (U256::from(6) << 112) / 2 * 10,
+        );
+        assert_eq!(pair.current_cumulative_prices(), expected);
+        assert_eq!(pair.price_cumulative_last(), (one * 10, one * 10));
+        pair.sync().unwrap();
+        assert_eq!(pair.price_cumulative_last(), expected);
+        assert_eq!(pair.reserves(), (e18(6), e18(2), 20));
+    }
+}
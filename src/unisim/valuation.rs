@@ -0,0 +1,298 @@
+//! What positions in a pool are worth right now: the tokens their liquidity is made of, the fees they have earned
+//! and not collected, and how that compares to having just held what went into them.
+//! Values are in units of one of the pool's two tokens, converted at the pool's current price.
+
+use super::position::PositionId;
+use super::{tick, UniV3Pool};
+use crate::solidints::{I256::I256, U160::U160, U256};
+use crate::solidmath::fixed_point::Q96;
+use crate::solidmath::{full_math, liquidity_amounts, tick_math};
+use anyhow::Result;
+use std::ops::Add;
+
+/// The token values are expressed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numeraire {
+    Token0,
+    Token1,
+}
+
+/// A position to value, with what was deposited into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Holding {
+    pub position: PositionId,
+    pub deposited_0: U256,
+    pub deposited_1: U256,
+}
+
+/// The value of a position, or of a set of them added up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Value {
+    /// the tokens the liquidity is made of at the current price, i.e. what burning it would pay out
+    pub amount_0: U256,
+    pub amount_1: U256,
+    /// fees earned and not collected yet, whether or not the position has been poked since.
+    /// like tokens owed onchain, this includes anything burned and not collected
+    pub fees_0: U256,
+    pub fees_1: U256,
+    /// the amounts, marked to market
+    pub value: U256,
+    /// the fees, marked to market
+    pub fees_value: U256,
+    /// the deposits, marked to market, i.e. the value of having held them instead
+    pub hodl_value: U256,
+    /// `value - hodl_value`: how much providing liquidity lost (negative) or made against holding, before fees
+    pub impermanent_loss: I256,
+}
+
+impl Add for Value {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            amount_0: self.amount_0 + other.amount_0,
+            amount_1: self.amount_1 + other.amount_1,
+            fees_0: self.fees_0 + other.fees_0,
+            fees_1: self.fees_1 + other.fees_1,
+            value: self.value + other.value,
+            fees_value: self.fees_value + other.fees_value,
+            hodl_value: self.hodl_value + other.hodl_value,
+            impermanent_loss: self.impermanent_loss + other.impermanent_loss,
+        }
+    }
+}
+
+/// The value of a single position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionValuation {
+    pub position: PositionId,
+    pub liquidity: u128,
+    pub value: Value,
+}
+
+/// The value of a set of positions, one by one and all together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Valuation {
+    pub positions: Vec<PositionValuation>,
+    pub total: Value,
+}
+
+/// Values a set of positions at the pool's current state
+/// # Arguments
+/// * `pool` The pool the positions are in
+/// * `holdings` The positions, and what went into each
+/// * `numeraire` Which of the pool's tokens to value everything in
+pub fn value_positions(
+    pool: &UniV3Pool,
+    holdings: &[Holding],
+    numeraire: Numeraire,
+) -> Result<Valuation> {
+    let positions = holdings
+        .iter()
+        .map(|holding| value_position(pool, holding, numeraire))
+        .collect::<Result<Vec<_>>>()?;
+    let total = positions
+        .iter()
+        .fold(Value::default(), |total, position| total + position.value);
+    Ok(Valuation { positions, total })
+}
+
+/// Values a single position at the pool's current state
+pub fn value_position(
+    pool: &UniV3Pool,
+    holding: &Holding,
+    numeraire: Numeraire,
+) -> Result<PositionValuation> {
+    let PositionId {
+        owner,
+        tick_lower,
+        tick_upper,
+    } = holding.position;
+    let info = pool.position(owner, tick_lower, tick_upper);
+
+    let (amount_0, amount_1) = liquidity_amounts::get_amounts_for_liquidity(
+        pool.sqrt_price_x96(),
+        tick_math::get_sqrt_ratio_at_tick(tick_lower)?,
+        tick_math::get_sqrt_ratio_at_tick(tick_upper)?,
+        info.liquidity,
+    )?;
+
+    // what the next poke would credit, on top of what's already owed
+    let (accrued_0, accrued_1) = accrued_fees(pool, holding.position)?;
+    let fees_0 = U256::from(info.tokens_owed_0.wrapping_add(accrued_0));
+    let fees_1 = U256::from(info.tokens_owed_1.wrapping_add(accrued_1));
+
+    let sqrt_price_x96 = pool.sqrt_price_x96();
+    let value = mark_to_market(sqrt_price_x96, amount_0, amount_1, numeraire)?;
+    let hodl_value = mark_to_market(
+        sqrt_price_x96,
+        holding.deposited_0,
+        holding.deposited_1,
+        numeraire,
+    )?;
+
+    Ok(PositionValuation {
+        position: holding.position,
+        liquidity: info.liquidity,
+        value: Value {
+            amount_0,
+            amount_1,
+            fees_0,
+            fees_1,
+            value,
+            fees_value: mark_to_market(sqrt_price_x96, fees_0, fees_1, numeraire)?,
+            hodl_value,
+            impermanent_loss: I256::try_from(value)? - I256::try_from(hodl_value)?,
+        },
+    })
+}
+
+/// The value of some amount of token0 and token1 in the numeraire, at a sqrt price. Rounds down
+pub fn mark_to_market(
+    sqrt_price_x96: U160,
+    amount_0: U256,
+    amount_1: U256,
+    numeraire: Numeraire,
+) -> Result<U256> {
+    let sqrt_price_x96 = U256::from(sqrt_price_x96);
+    Ok(match numeraire {
+        // price is token1 per token0, sqrt_price_x96 ** 2 / 2 ** 192
+        Numeraire::Token1 => {
+            let amount_0_in_1 = full_math::muldiv(
+                full_math::muldiv(amount_0, sqrt_price_x96, *Q96)?,
+                sqrt_price_x96,
+                *Q96,
+            )?;
+            amount_1 + amount_0_in_1
+        }
+        Numeraire::Token0 => {
+            let amount_1_in_0 = full_math::muldiv(
+                full_math::muldiv(amount_1, *Q96, sqrt_price_x96)?,
+                *Q96,
+                sqrt_price_x96,
+            )?;
+            amount_0 + amount_1_in_0
+        }
+    })
+}
+
+/// The fees a position would be owed right now if it was poked, without counting what is already owed
+pub fn accrued_fees(pool: &UniV3Pool, position: PositionId) -> Result<(u128, u128)> {
+    let info = pool.position(position.owner, position.tick_lower, position.tick_upper);
+    let inside = tick::get_fee_growth_inside(
+        pool.ticks(),
+        position.tick_lower,
+        position.tick_upper,
+        pool.tick(),
+        pool.fee_growth_global(),
+    )?;
+    (inside - info.fee_growth_inside_last).tokens_owed(info.liquidity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::callback::PayExact;
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn holding(pool: &mut UniV3Pool, owner: u32, tick_lower: i32, tick_upper: i32) -> Holding {
+        let owner = U160::from(owner);
+        let (deposited_0, deposited_1) = pool
+            .mint(owner, tick_lower, tick_upper, 2 * E18, &mut PayExact)
+            .unwrap();
+        Holding {
+            position: PositionId {
+                owner,
+                tick_lower,
+                tick_upper,
+            },
+            deposited_0,
+            deposited_1,
+        }
+    }
+
+    #[test]
+    fn marks_to_market_in_either_token() {
+        // a price of 4 token1 per token0
+        let sqrt_price = U160::one() << 97;
+        let value = |numeraire| mark_to_market(sqrt_price, 3.into(), 4.into(), numeraire).unwrap();
+        assert_eq!(value(Numeraire::Token1), 16.into());
+        assert_eq!(value(Numeraire::Token0), 4.into());
+    }
+
+    #[test]
+    fn values_positions_against_holding() {
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let wide = holding(&mut pool, 1, -600, 600);
+        let narrow = holding(&mut pool, 2, -60, 60);
+
+        // before anything happens, the position is worth what went in, give or take rounding
+        let start = value_positions(&pool, &[wide], Numeraire::Token1).unwrap();
+        assert!(start.total.hodl_value - start.total.value <= 2.into());
+        assert_eq!(start.total.fees_value, U256::zero());
+
+        pool.swap(
+            true,
+            I256::from(E18 / 100),
+            tick_math::MIN_SQRT_RATIO + U160::one(),
+            &mut PayExact,
+        )
+        .unwrap();
+        let valuation = value_positions(&pool, &[wide, narrow], Numeraire::Token1).unwrap();
+        let [wide_value, narrow_value] =
+            [valuation.positions[0].value, valuation.positions[1].value];
+        assert_eq!(valuation.total, wide_value + narrow_value);
+
+        // the price fell, so both did worse than holding, and both earned token0 fees
+        assert!(wide_value.impermanent_loss.is_negative());
+        assert!(narrow_value.impermanent_loss.is_negative());
+        assert!(!wide_value.fees_0.is_zero());
+        assert!(!narrow_value.fees_0.is_zero());
+        assert_eq!(wide_value.fees_1, U256::zero());
+
+        // it's all exactly what taking the position out pays
+        let PositionId {
+            owner,
+            tick_lower,
+            tick_upper,
+        } = wide.position;
+        pool.burn(owner, tick_lower, tick_upper, 2 * E18).unwrap();
+        let (collected_0, collected_1) = pool
+            .collect(owner, tick_lower, tick_upper, u128::MAX, u128::MAX)
+            .unwrap();
+        assert_eq!(
+            U256::from(collected_0),
+            wide_value.amount_0 + wide_value.fees_0
+        );
+        assert_eq!(
+            U256::from(collected_1),
+            wide_value.amount_1 + wide_value.fees_1
+        );
+    }
+
+    #[test]
+    fn counts_fees_owed_and_accrued() {
+        let mut pool = UniV3Pool::new(3000, 60, U160::one() << 96, 0).unwrap();
+        let wide = holding(&mut pool, 1, -600, 600);
+        let swap = |pool: &mut UniV3Pool| {
+            pool.swap(
+                false,
+                I256::from(E18 / 100),
+                tick_math::MAX_SQRT_RATIO - U160::one(),
+                &mut PayExact,
+            )
+            .unwrap();
+        };
+        swap(&mut pool);
+        let before_poke = value_position(&pool, &wide, Numeraire::Token0).unwrap();
+        pool.burn(U160::from(1u32), -600, 600, 0).unwrap();
+        assert_eq!(accrued_fees(&pool, wide.position).unwrap(), (0, 0));
+        let after_poke = value_position(&pool, &wide, Numeraire::Token0).unwrap();
+        assert_eq!(before_poke, after_poke);
+
+        swap(&mut pool);
+        let later = value_position(&pool, &wide, Numeraire::Token0).unwrap();
+        assert!(later.value.fees_1 > after_poke.value.fees_1);
+    }
+}